@@ -0,0 +1,128 @@
+#!/usr/bin/env bash
+# 生ソケットのデータプレーンをLinuxネットワーク名前空間内で試すデモ（要root）
+#
+#   送信者(hn-s) ─┐
+#   中継1(hn-r1) ─┤
+#   中継2(hn-r2) ─┼─ ブリッジ(hn-br)   リンク: fd00::/64, SID: 2001:db8::N/128 (中継ノードのlo、USID=1 では fc00:0:N::/48)
+#   中継3(hn-r3) ─┤
+#   受信者(hn-d) ─┘
+#
+# 使い方: scripts/netns-demo.sh [up|run|down]（省略時は up → run → down）
+# HORNET_BIN で実行ファイルを指定できる（既定: cargo build --features raw-socket の成果物）
+# USID=1 でSIDを fc00:0:N::/48（Locator-Block fc00:0::/32 + uSID N）とし、経路を1つのuSIDキャリアで送る
+set -euo pipefail
+
+cd "$(dirname "$0")/.."
+HORNET_BIN=${HORNET_BIN:-target/debug/hornet}
+USID=${USID:-0}
+NAMESPACES=(hn-s hn-r1 hn-r2 hn-r3 hn-d)
+RELAYS=3
+
+# 中継ノードNのSIDとそのプレフィックス
+# uSIDキャリアはシフト後も宛先が Locator-Block + uSID のプレフィックス内に収まるため、/48をまとめて扱う
+sid() {
+    if [ "$USID" = 1 ]; then echo "fc00:0:$1::"; else echo "2001:db8::$1"; fi
+}
+
+sid_prefix() {
+    if [ "$USID" = 1 ]; then echo "$(sid "$1")/48"; else echo "$(sid "$1")/128"; fi
+}
+
+usid_args() {
+    if [ "$USID" = 1 ]; then echo "--usid 32/16"; fi
+}
+
+link_addr() {
+    case "$1" in
+        hn-s) echo "fd00::10" ;;
+        hn-d) echo "fd00::20" ;;
+        hn-r*) echo "fd00::${1#hn-r}" ;;
+    esac
+}
+
+# 識別鍵を生成し、公開鍵（16進）を出力する
+keygen() {
+    "$HORNET_BIN" keygen --out "$1" | sed -n 's/^公開鍵: //p'
+}
+
+up() {
+    ip netns add hn-br
+    ip -n hn-br link add br0 type bridge
+    ip -n hn-br link set br0 up
+
+    for ns in "${NAMESPACES[@]}"; do
+        ip netns add "$ns"
+        ip link add veth0 netns "$ns" type veth peer name "$ns" netns hn-br
+        ip -n hn-br link set "$ns" master br0 up
+        ip -n "$ns" link set lo up
+        ip -n "$ns" link set veth0 up
+        ip -n "$ns" addr add "$(link_addr "$ns")/64" dev veth0 nodad
+        # カーネルにSRHを処理させない（生ソケットにのみ渡す）
+        ip netns exec "$ns" sysctl -qw net.ipv6.conf.all.seg6_enabled=0
+    done
+
+    # 各中継ノードのSIDプレフィックスをローカル宛とし、全名前空間からリンクアドレス経由で到達できるようにする
+    for i in $(seq 1 "$RELAYS"); do
+        ip -n "hn-r$i" -6 route add local "$(sid_prefix "$i")" dev lo
+        for ns in "${NAMESPACES[@]}"; do
+            [ "$ns" = "hn-r$i" ] && continue
+            ip -n "$ns" -6 route add "$(sid_prefix "$i")" via "fd00::$i"
+        done
+    done
+}
+
+run() {
+    if [ ! -x "$HORNET_BIN" ]; then
+        cargo build --features raw-socket
+    fi
+
+    # 各ノードの識別鍵を生成し、送信者はその公開鍵でハンドシェイク応答を検証する
+    local keys pids=() path=() peer_keys=()
+    keys=$(mktemp -d)
+    for i in $(seq 1 "$RELAYS"); do
+        peer_keys+=("[fd00::$i]:9001=$(keygen "$keys/r$i.key")")
+        # shellcheck disable=SC2046
+        ip netns exec "hn-r$i" "$HORNET_BIN" relay --raw --bind "[fd00::$i]:9001" --sid "$(sid "$i")" \
+            --key-file "$keys/r$i.key" $(usid_args) &
+        pids+=($!)
+        path+=("$(sid "$i")@[fd00::$i]:9001")
+    done
+    peer_keys+=("[fd00::20]:9001=$(keygen "$keys/d.key")")
+    ip netns exec hn-d "$HORNET_BIN" receive --raw --bind "[fd00::20]:9001" --key-file "$keys/d.key" &
+    pids+=($!)
+    sleep 1
+
+    # shellcheck disable=SC2046
+    ip netns exec hn-s "$HORNET_BIN" send --raw --bind "[fd00::10]:9000" $(usid_args) \
+        --path "$(IFS=,; echo "${path[*]}")" \
+        --receiver "[fd00::20]:9001" \
+        --peer-key "$(IFS=,; echo "${peer_keys[*]}")" \
+        --message "Hello from a network namespace!"
+    sleep 1
+
+    kill "${pids[@]}" 2>/dev/null || true
+    wait 2>/dev/null || true
+    rm -rf "$keys"
+}
+
+
+down() {
+    for ns in "${NAMESPACES[@]}" hn-br; do
+        ip netns del "$ns" 2>/dev/null || true
+    done
+}
+
+case "${1:-all}" in
+    up) up ;;
+    run) run ;;
+    down) down ;;
+    all)
+        trap down EXIT
+        up
+        run
+        ;;
+    *)
+        echo "使い方: $0 [up|run|down]" >&2
+        exit 1
+        ;;
+esac
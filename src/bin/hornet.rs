@@ -0,0 +1,772 @@
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use hornet_plus::config::{Config, Role};
+use hornet_plus::crypto::{
+    decode_public_key, generate_identity_key, generate_srh_hmac_key, load_identity_key, load_srh_hmac_key, public_key_hex,
+    SrhHmacKey, SRH_HMAC_KEY_SIZE,
+};
+use hornet_plus::node::{DeliveredMessage, Node, NodeType};
+use hornet_plus::onion::MAX_HOPS;
+use hornet_plus::pathchange::PathChange;
+use hornet_plus::pathlist::DEFAULT_PATH_LIST_LIFETIME;
+use hornet_plus::policy::{parse_color, PolicyMetadata, PolicySegment, PolicyStore, SRv6Policy, COLOR_DEFAULT};
+use hornet_plus::ranking::ScoreWeights;
+use hornet_plus::raw::RawSocket;
+use hornet_plus::error::{ConfigError, Error};
+use hornet_plus::replay::DEFAULT_REPLAY_WINDOW_SIZE;
+use hornet_plus::reroute::DEFAULT_PROBE_INTERVAL;
+use hornet_plus::sender::Sender;
+use hornet_plus::sid::{SidStructure, UsidFormat};
+use hornet_plus::srv6::{SidBehavior, UnknownSidPolicy, MAIN_TABLE};
+use hornet_plus::topology::{TopologyEdge, TopologyFile, TopologyNode};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
+
+// 定数
+const DEFAULT_PORT_BASE: u16 = 9000;
+const DEFAULT_DEMO_HOPS: usize = 3;
+const SID_STRUCTURE_HELP: &str = "SIDのLocator/Function/Argumentのビット長 (例: 64/16/48)。指定時はArgumentを除いてSIDを照合する";
+const USID_HELP: &str = "圧縮SID（uSIDキャリア）のLocator-Block/uSIDのビット長 (例: 32/16)";
+const COLOR_HELP: &str = "ポリシーのカラー (数値、または low-latency / high-bandwidth)";
+const PATH_HELP: &str = "中継ノードの経路 SID@アドレス (例: 2001:db8::1@[::1]:9001)";
+const TOPOLOGY_HELP: &str = "トポロジーファイル (JSONまたはYAML)";
+const TRUSTED_KEY_HELP: &str = "経路変更通知の検証に使う中継ノードの識別公開鍵 SID=公開鍵 (公開鍵はkeygenの表示)";
+const KEY_FILE_HELP: &str = "長期識別鍵ファイル (keygenで生成)。ハンドシェイク応答と経路変更通知に署名する";
+const DEMO_PATH_CHANGE_WAIT: Duration = Duration::from_secs(2);
+const DEMO_LINK_LATENCY_MS: f64 = 1.0;
+const DEMO_LINK_BANDWIDTH_MBPS: f64 = 1000.0;
+const RAW_HELP: &str = "データパケットを生ソケット（IPv6 + SRH）で送受信する (raw-socketフィーチャーとCAP_NET_RAWが必要)";
+
+#[derive(Parser)]
+#[command(name = "hornet", version, about = "HORNETベースのSRv6 Onion Routing")]
+struct Cli {
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text, help = "ログ出力形式 (出力レベルはRUST_LOGで指定)")]
+    log_format: LogFormat,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "設定ファイルに従ってノードを起動する")]
+    Run {
+        #[arg(long, help = "ノード設定ファイル (YAMLまたはTOML)")]
+        config: PathBuf,
+    },
+    #[command(about = "中継ノードとして起動する")]
+    Relay {
+        #[arg(long, help = "待ち受けアドレス (例: [::1]:9001)")]
+        bind: SocketAddr,
+        #[arg(long, help = "このノードのSID (例: 2001:db8::1)")]
+        sid: Ipv6Addr,
+        #[arg(long = "local-sid", value_delimiter = ',', help = "追加のローカルSID SID=動作 (動作: end, end.x:<隣接ノード>, end.t:<テーブルID>, end.dt6:<テーブルID>)")]
+        local_sids: Vec<LocalSid>,
+        #[arg(long, default_value = "drop", help = "SIDテーブルにないSID宛パケットの扱い (drop または forward)")]
+        unknown_sid: UnknownSidPolicy,
+        #[arg(long, help = SID_STRUCTURE_HELP)]
+        sid_structure: Option<SidStructure>,
+        #[arg(long, help = USID_HELP)]
+        usid: Option<UsidFormat>,
+        #[arg(long = "route", value_delimiter = ',', help = "ロケータ経路 [テーブルID@]プレフィックス=アドレス (例: 2001:db8::2/128=[::1]:9002, 10@2001:db8::/64=[::1]:9003)")]
+        routes: Vec<LocatorRoute>,
+        #[arg(long, help = KEY_FILE_HELP)]
+        key_file: PathBuf,
+        #[arg(long, default_value_t = DEFAULT_REPLAY_WINDOW_SIZE, help = "リプレイ検出ウィンドウのサイズ")]
+        replay_window: usize,
+        #[arg(long, default_value_t = DEFAULT_PROBE_INTERVAL.as_secs(), help = "転送先へのreachabilityチェックの間隔 (秒、0で無効)。連続して応答がなければ代替経路に切り替える")]
+        probe_interval: u64,
+        #[arg(long = "trusted-key", value_delimiter = ',', help = TRUSTED_KEY_HELP)]
+        trusted_keys: Vec<TrustedKey>,
+        #[arg(long, help = RAW_HELP)]
+        raw: bool,
+        #[command(flatten)]
+        srh_hmac: SrhHmacArgs,
+    },
+    #[command(about = "受信ノードとして起動する")]
+    Receive {
+        #[arg(long, help = "待ち受けアドレス (例: [::1]:9004)")]
+        bind: SocketAddr,
+        #[arg(long, help = KEY_FILE_HELP)]
+        key_file: PathBuf,
+        #[arg(long, default_value_t = DEFAULT_REPLAY_WINDOW_SIZE, help = "リプレイ検出ウィンドウのサイズ")]
+        replay_window: usize,
+        #[arg(long, help = RAW_HELP)]
+        raw: bool,
+        #[command(flatten)]
+        srh_hmac: SrhHmacArgs,
+    },
+    #[command(about = "経路上の各ノードとハンドシェイクしてメッセージを送信する")]
+    Send(Box<SendArgs>),
+    #[command(about = "全ノードを1プロセス内で起動してメッセージを送信する (トポロジーファイル省略時はlocalhostの直列経路)")]
+    Demo {
+        #[arg(long, default_value_t = DEFAULT_DEMO_HOPS, help = "中継ノード数")]
+        hops: usize,
+        #[arg(long, default_value_t = DEFAULT_PORT_BASE, help = "送信者のポート (中継・受信者は連番)")]
+        port_base: u16,
+        #[arg(long, default_value = "Hello, HORNET Onion Routing!", help = "送信するメッセージ")]
+        message: String,
+        #[arg(long, help = "中継ノードにuSID形式のSID (fc00:0:N::) を割り当て、経路を1つのuSIDキャリアに詰める")]
+        usid: bool,
+        #[arg(long, conflicts_with_all = ["hops", "port_base", "usid"], help = TOPOLOGY_HELP)]
+        topology: Option<PathBuf>,
+        #[arg(long, requires = "topology", help = "送信元のノードID (省略時はentryロールの唯一のノード)")]
+        from: Option<String>,
+        #[arg(long, requires = "topology", help = "宛先のノードID (省略時はexitロールの唯一のノード)")]
+        to: Option<String>,
+    },
+    #[command(about = "SRv6ポリシーファイルを編集・表示する")]
+    Policy {
+        #[command(subcommand)]
+        action: PolicyAction,
+    },
+    #[command(about = "長期識別鍵（またはSRH HMAC鍵）を生成する")]
+    Keygen {
+        #[arg(long, help = "出力先の鍵ファイル")]
+        out: PathBuf,
+        #[arg(long, help = "識別鍵の代わりにSRドメイン共通のSRH HMAC鍵を生成する")]
+        srh_hmac: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum PolicyAction {
+    #[command(about = "ポリシーを追加する（ファイルがなければ作成）")]
+    Add {
+        #[arg(long, help = "SRv6ポリシーファイル (JSON)")]
+        file: PathBuf,
+        #[arg(long, required = true, num_args = 1.., value_delimiter = ',', help = PATH_HELP)]
+        path: Vec<PolicySegment>,
+        #[arg(long, help = "最終宛先（受信ノード）のアドレス")]
+        endpoint: SocketAddr,
+        #[arg(long, default_value = "0", value_parser = parse_color, help = COLOR_HELP)]
+        color: u32,
+        #[arg(long, help = "このポリシーを使用する送信元ノードのアドレス")]
+        headend: Option<SocketAddr>,
+        #[arg(long, help = "経路の想定遅延 (ミリ秒)")]
+        latency_ms: Option<u32>,
+        #[arg(long, help = "経路の最低帯域 (Mbps)")]
+        bandwidth_mbps: Option<u32>,
+        #[arg(long, default_value_t = 0, help = "優先度 (大きいほど優先)")]
+        priority: u8,
+    },
+    #[command(about = "ポリシーの一覧を表示する")]
+    List {
+        #[arg(long, help = "SRv6ポリシーファイル (JSON)")]
+        file: PathBuf,
+    },
+    #[command(about = "ポリシーを削除する")]
+    Remove {
+        #[arg(long, help = "SRv6ポリシーファイル (JSON)")]
+        file: PathBuf,
+        #[arg(long, help = "削除するPolicyID")]
+        id: String,
+    },
+}
+
+#[derive(Args)]
+struct SendArgs {
+    #[arg(long, default_value = "[::]:0", conflicts_with_all = ["config", "topology"], help = "送信元アドレス")]
+    bind: SocketAddr,
+    #[arg(long, help = "entryロールのノード設定ファイル (送信元アドレス等を設定から取得)")]
+    config: Option<PathBuf>,
+    #[arg(long, required_unless_present_any = ["policy_file", "topology"], num_args = 1.., value_delimiter = ',', help = PATH_HELP)]
+    path: Vec<PolicySegment>,
+    #[arg(long, conflicts_with = "path", help = "SRv6ポリシーファイル (JSON)。受信ノードとカラーが一致するポリシーの経路で送信する")]
+    policy_file: Option<PathBuf>,
+    #[arg(long, conflicts_with_all = ["path", "policy_file", "receiver"], help = "トポロジーファイル (JSONまたはYAML)。経路を探索・評価し、上位の経路を優先度順に使う")]
+    topology: Option<PathBuf>,
+    #[arg(long, requires = "topology", help = "送信元のノードID (省略時はentryロールの唯一のノード)")]
+    from: Option<String>,
+    #[arg(long, requires = "topology", help = "宛先のノードID (省略時はexitロールの唯一のノード)")]
+    to: Option<String>,
+    #[arg(long, requires = "topology", help = "選ぶ経路数 (優先度1の経路と代替経路。省略時はトポロジーファイルの設定)")]
+    paths: Option<usize>,
+    #[arg(long, requires = "topology", help = "経路評価の重み 名前=値 (例: latency=0.5,path_diversity=0.3。省略した重みはデフォルト値)")]
+    weights: Option<ScoreWeights>,
+    #[arg(long, default_value = "0", value_parser = parse_color, help = COLOR_HELP)]
+    color: u32,
+    #[arg(long, default_value_t = DEFAULT_PATH_LIST_LIFETIME.as_secs(), help = "最初の中継ノードに送るパスリストの有効期間 (秒)")]
+    path_list_lifetime: u64,
+    #[arg(long, required_unless_present = "topology", help = "受信ノードのアドレス")]
+    receiver: Option<SocketAddr>,
+    #[arg(long, help = "送信するメッセージ")]
+    message: String,
+    #[arg(long, requires = "sid_argument", help = SID_STRUCTURE_HELP)]
+    sid_structure: Option<SidStructure>,
+    #[arg(long, requires = "sid_structure", help = "経路上の各SIDのArgument部に埋め込む値 (経路IDやフローインデックスなど)")]
+    sid_argument: Option<u64>,
+    #[arg(long, conflicts_with = "sid_argument", help = "経路をuSIDキャリアに詰めて送信する (Locator-Block/uSIDのビット長、例: 32/16)")]
+    usid: Option<UsidFormat>,
+    #[arg(long = "trusted-key", value_delimiter = ',', help = TRUSTED_KEY_HELP)]
+    trusted_keys: Vec<TrustedKey>,
+    #[arg(long = "peer-key", value_delimiter = ',', help = "ハンドシェイク応答の検証に使うノードの識別公開鍵 アドレス=公開鍵 (経路上のSIDに対応する --trusted-key の鍵とトポロジーファイルのpublic_keyも使う)")]
+    peer_keys: Vec<PeerKey>,
+    #[arg(long, default_value_t = 0, help = "送信後に経路変更通知を待つ時間 (秒)。トポロジーファイルのpublic_keyと --trusted-key の鍵で検証する")]
+    path_change_wait: u64,
+    #[arg(long, help = RAW_HELP)]
+    raw: bool,
+    #[command(flatten)]
+    srh_hmac: SrhHmacArgs,
+}
+
+// SRH HMAC TLVの鍵指定（SRドメイン内の全ノードで同じ鍵を使う）
+#[derive(Args)]
+struct SrhHmacArgs {
+    #[arg(long, help = "SRH HMAC鍵ファイル (keygen --srh-hmac で生成)。指定時はセグメントリストをHMAC TLVで認証する")]
+    srh_hmac_key_file: Option<PathBuf>,
+    #[arg(long, default_value_t = 1, requires = "srh_hmac_key_file", help = "SRH HMAC鍵のKey ID")]
+    srh_hmac_key_id: u32,
+}
+
+impl SrhHmacArgs {
+    fn load(&self) -> Result<Option<SrhHmacKey>, Error> {
+        self.srh_hmac_key_file
+            .as_deref()
+            .map(|path| load_srh_hmac_key(path, self.srh_hmac_key_id))
+            .transpose()
+    }
+}
+
+// ローカルSID（SID=動作）
+#[derive(Clone, Debug)]
+struct LocalSid {
+    sid: Ipv6Addr,
+    behavior: SidBehavior,
+}
+
+impl FromStr for LocalSid {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (sid, behavior) = s
+            .split_once('=')
+            .ok_or_else(|| format!("SID=動作 の形式で指定してください: {}", s))?;
+        Ok(Self {
+            sid: sid.parse().map_err(|_| format!("SIDが不正です: {}", sid))?,
+            behavior: behavior.parse()?,
+        })
+    }
+}
+
+// 中継ノードの識別公開鍵（SID=公開鍵）
+#[derive(Clone, Debug)]
+struct TrustedKey {
+    sid: Ipv6Addr,
+    key: p384::PublicKey,
+}
+
+impl FromStr for TrustedKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (sid, key) = s
+            .split_once('=')
+            .ok_or_else(|| format!("SID=公開鍵 の形式で指定してください: {}", s))?;
+        Ok(Self {
+            sid: sid.parse().map_err(|_| format!("SIDが不正です: {}", sid))?,
+            key: decode_public_key(key).ok_or_else(|| format!("公開鍵が不正です: {}", key))?,
+        })
+    }
+}
+
+// ハンドシェイク相手の識別公開鍵（アドレス=公開鍵）
+#[derive(Clone, Debug)]
+struct PeerKey {
+    address: SocketAddr,
+    key: p384::PublicKey,
+}
+
+impl FromStr for PeerKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, key) = s
+            .split_once('=')
+            .ok_or_else(|| format!("アドレス=公開鍵 の形式で指定してください: {}", s))?;
+        Ok(Self {
+            address: address.parse().map_err(|_| format!("アドレスが不正です: {}", address))?,
+            key: decode_public_key(key).ok_or_else(|| format!("公開鍵が不正です: {}", key))?,
+        })
+    }
+}
+
+// ロケータ経路（[テーブルID@]プレフィックス=アドレス）。プレフィックス長省略時は/128、テーブル省略時はメインテーブル
+#[derive(Clone, Debug)]
+struct LocatorRoute {
+    table: u32,
+    locator: Ipv6Addr,
+    prefix_len: u8,
+    via: SocketAddr,
+}
+
+impl FromStr for LocatorRoute {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (prefix, via) = s
+            .split_once('=')
+            .ok_or_else(|| format!("プレフィックス=アドレス の形式で指定してください: {}", s))?;
+        let (table, prefix) = match prefix.split_once('@') {
+            Some((table, prefix)) => (table.parse().map_err(|_| format!("テーブルIDが不正です: {}", table))?, prefix),
+            None => (MAIN_TABLE, prefix),
+        };
+        let (locator, prefix_len) = prefix.split_once('/').unwrap_or((prefix, "128"));
+        Ok(Self {
+            table,
+            locator: locator.parse().map_err(|_| format!("ロケータが不正です: {}", locator))?,
+            prefix_len: prefix_len.parse().ok()
+                .filter(|len| *len <= 128)
+                .ok_or_else(|| format!("プレフィックス長が不正です: {}", prefix_len))?,
+            via: via.parse().map_err(|_| format!("アドレスが不正です: {}", via))?,
+        })
+    }
+}
+
+fn main() -> ExitCode {
+    match try_main() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            // ライブラリのエラーは機械判読可能なコードを併記する
+            match e.downcast_ref::<Error>() {
+                Some(error) => eprintln!("エラー [{}]: {}", error.code(), error),
+                None => eprintln!("エラー: {}", e),
+            }
+            ExitCode::FAILURE
+        },
+    }
+}
+
+fn try_main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    init_logging(cli.log_format);
+
+    // 設定ファイルはランタイム起動前に読み込み、検証エラーがあればノードを起動しない
+    let config_path = match &cli.command {
+        Command::Run { config } => Some(config),
+        Command::Send(args) => args.config.as_ref(),
+        _ => None,
+    };
+    let config = match config_path {
+        Some(path) => match Config::load(path) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                eprintln!("設定エラー ({}):", path.display());
+                for line in config_error_message(e).lines() {
+                    eprintln!("  - {}", line);
+                }
+                std::process::exit(2);
+            },
+        },
+        None => None,
+    };
+
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    if let Some(config) = &config {
+        runtime.worker_threads(config.node.performance.threads);
+    }
+    runtime.enable_all().build()?.block_on(run(cli.command, config))
+}
+
+// ログはstderrへ出力する。RUST_LOG未設定時はinfo（データグラムごとのログはdebug以下）
+fn init_logging(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr);
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
+
+fn config_error_message(error: Error) -> String {
+    match error {
+        Error::Config(ConfigError::Invalid(problems)) => problems.join("\n"),
+        Error::Config(e) => e.to_string(),
+        other => other.to_string(),
+    }
+}
+
+async fn run(command: Command, config: Option<Config>) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Command::Run { .. } => {
+            let config = config.expect("設定は読み込み済み");
+            if config.node.role == Role::Entry {
+                return Err("entryロールのノードは send --config で使用してください".into());
+            }
+            let node = config.build_node().map_err(|e| format!("ノード初期化失敗: {}", e))?;
+            info!(id = %config.node.id, role = ?config.node.role, "設定ファイルからノードを構築");
+            serve(node, config.node.network.listen).await
+        },
+        Command::Relay {
+            bind, sid, local_sids, unknown_sid, sid_structure, usid, routes, key_file, replay_window, probe_interval, trusted_keys,
+            raw, srh_hmac,
+        } => {
+            let mut node = build_node(NodeType::Relay(sid), bind, Some(&key_file), replay_window, raw, &srh_hmac)?
+                .with_unknown_sid_policy(unknown_sid)
+                .with_probe_interval((probe_interval > 0).then(|| Duration::from_secs(probe_interval)));
+            if let Some(structure) = sid_structure {
+                node = node.with_sid_structure(structure);
+            }
+            if let Some(format) = usid {
+                node = node.with_usid_format(format);
+            }
+            for local_sid in local_sids {
+                node.add_local_sid(local_sid.sid, local_sid.behavior);
+            }
+            for route in routes {
+                node.add_table_route(route.table, route.locator, route.prefix_len, route.via);
+            }
+            for trusted in trusted_keys {
+                node.add_trusted_key(trusted.sid, trusted.key);
+            }
+            serve(node, bind).await
+        },
+        Command::Receive { bind, key_file, replay_window, raw, srh_hmac } => {
+            let node = build_node(NodeType::Receiver, bind, Some(&key_file), replay_window, raw, &srh_hmac)?;
+            serve(node, bind).await
+        },
+        Command::Send(args) => {
+            let SendArgs {
+                bind, path, policy_file, topology, from, to, paths, weights, color, path_list_lifetime, receiver, message,
+                sid_structure, sid_argument, usid, trusted_keys, peer_keys, path_change_wait, raw, srh_hmac, ..
+            } = *args;
+            let topology_file = topology.as_deref().map(TopologyFile::load).transpose()?;
+            let source = topology_file.as_ref()
+                .map(|file| file.endpoint(Role::Entry, from.as_deref()))
+                .transpose()?;
+            // 送信元は設定ファイル、トポロジー上の送信元ノード、--bind の順に決める
+            let bind = match (&config, source) {
+                (Some(config), _) => config.node.network.listen,
+                (None, Some(TopologyNode { address: Some(address), .. })) => *address,
+                _ => bind,
+            };
+            // トポロジーがあれば経路を探索・評価して選び、ポリシーファイルがあれば受信ノードとカラーで選ぶ。
+            // どちらもなければ指定経路をその場限りのポリシーとする。
+            // 選ばれなかった候補は代替経路としてパスリストに載せる
+            let (policy, alternatives) = match (&topology_file, source, &policy_file) {
+                (Some(file), Some(source), _) => {
+                    let destination = file.endpoint(Role::Exit, to.as_deref())?;
+                    let mut selection = file.path_selection;
+                    selection.paths = paths.unwrap_or(selection.paths);
+                    selection.weights = weights.unwrap_or(selection.weights);
+                    selection.validate()?;
+                    let mut policies = file.policies(source, destination, &selection, color)?;
+                    let policy = policies.remove(0);
+                    (policy, policies)
+                },
+                (_, _, Some(file)) => {
+                    let receiver = receiver.expect("--receiver は必須");
+                    let headend = (!bind.ip().is_unspecified()).then_some(bind);
+                    let store = PolicyStore::load(file)?;
+                    let policy = store.select(headend, receiver, color)?.clone();
+                    let alternatives = store.candidates(headend, receiver, color)
+                        .into_iter()
+                        .skip(1)
+                        .cloned()
+                        .collect();
+                    (policy, alternatives)
+                },
+                _ => (SRv6Policy::new(receiver.expect("--receiver は必須"), color, path), Vec::new()),
+            };
+            let mut sender_node = match &config {
+                Some(config) if config.node.role != Role::Entry => {
+                    return Err(format!("send には entryロールの設定が必要です: {:?}", config.node.role).into());
+                },
+                Some(config) => config.build_node().map_err(|e| format!("ノード初期化失敗: {}", e))?,
+                None => build_node(NodeType::Sender, bind, None, DEFAULT_REPLAY_WINDOW_SIZE, raw, &srh_hmac)?,
+            };
+            if let Some(format) = usid.or(topology_file.as_ref().and_then(|file| file.usid)) {
+                sender_node = sender_node.with_usid_format(format);
+            }
+            let mut sender = Sender::new(sender_node, UdpSocket::bind(bind).await?)
+                .with_path_list_lifetime(Duration::from_secs(path_list_lifetime));
+            if let (Some(structure), Some(argument)) = (sid_structure, sid_argument) {
+                sender = sender.with_sid_argument(structure, argument);
+            }
+            // 経路変更通知とハンドシェイク応答の署名はトポロジーのpublic_keyと指定された鍵で検証する
+            if let Some(file) = &topology_file {
+                sender.trust_topology(file);
+            }
+            for trusted in trusted_keys {
+                sender.node().add_trusted_key(trusted.sid, trusted.key);
+            }
+            for peer in peer_keys {
+                sender.node().add_peer_key(peer.address, peer.key);
+            }
+            let session_id = sender.send(&policy, &alternatives, message.as_bytes()).await?;
+            println!("送信しました (セッションID: {})", session_id);
+            if path_change_wait > 0 {
+                let changes = sender.collect_path_changes(Duration::from_secs(path_change_wait)).await;
+                print_path_changes(&changes);
+            }
+            Ok(())
+        },
+        Command::Demo { hops, port_base, message, usid, topology, from, to } => {
+            let topology_file = match topology {
+                Some(file) => TopologyFile::load(&file)?,
+                None => linear_topology(hops, port_base, usid)?,
+            };
+            demo(&topology_file, from.as_deref(), to.as_deref(), &message).await
+        },
+        Command::Policy { action } => run_policy(action),
+        Command::Keygen { out, srh_hmac: true } => {
+            generate_srh_hmac_key(&out)?;
+            println!("SRH HMAC鍵を生成しました: {}", out.display());
+            Ok(())
+        },
+        Command::Keygen { out, srh_hmac: false } => {
+            let key = generate_identity_key(&out)?;
+            println!("識別鍵を生成しました: {}", out.display());
+            println!("公開鍵: {}", public_key_hex(&key.public_key()));
+            Ok(())
+        },
+    }
+}
+
+fn build_node(node_type: NodeType,
+              bind: SocketAddr,
+              key_file: Option<&Path>,
+              replay_window: usize,
+              raw: bool,
+              srh_hmac: &SrhHmacArgs) -> Result<Node, Box<dyn std::error::Error>> {
+    let mut node = Node::new(node_type, bind).with_replay_window_size(replay_window);
+    if raw {
+        // 生ソケットではbindアドレスをIPv6ヘッダーの送信元に用いる
+        if bind.ip().is_unspecified() {
+            return Err("--raw では --bind に具体的なアドレスを指定してください".into());
+        }
+        node = node.with_raw_socket(RawSocket::open()?);
+    }
+    if let Some(path) = key_file {
+        let key = load_identity_key(path)?;
+        info!(public_key = %public_key_hex(&key.public_key()), "識別鍵を読み込み");
+        node = node.with_identity_key(key);
+    }
+    if let Some(key) = srh_hmac.load()? {
+        node = node.with_srh_hmac_key(key);
+    }
+    Ok(node)
+}
+
+async fn serve(node: Node, bind: SocketAddr) -> Result<(), Box<dyn std::error::Error>> {
+    let node = node.with_message_sink(spawn_message_printer());
+    let socket = Arc::new(UdpSocket::bind(bind).await?);
+    node.run(socket).await.map_err(|e| format!("ノード停止: {}", e))?;
+    Ok(())
+}
+
+// 受信したメッセージを標準出力へ表示するタスクを起動
+fn spawn_message_printer() -> mpsc::UnboundedSender<DeliveredMessage> {
+    let (sink, mut messages) = mpsc::unbounded_channel::<DeliveredMessage>();
+    tokio::spawn(async move {
+        while let Some(delivered) = messages.recv().await {
+            println!("受信メッセージ (セッションID: {}): {}",
+                     delivered.session_id,
+                     String::from_utf8_lossy(&delivered.message));
+        }
+    });
+    sink
+}
+
+// 送信者が受け取った経路変更通知を標準出力へ表示する
+fn print_path_changes(changes: &[PathChange]) {
+    for change in changes {
+        println!("経路変更 (セッションID: {}): {} → {} ({})",
+                 change.session_id,
+                 change.old_path_id,
+                 change.new_path_id,
+                 change.reason);
+    }
+}
+
+fn run_policy(action: PolicyAction) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        PolicyAction::Add { file, path, endpoint, color, headend, latency_ms, bandwidth_mbps, priority } => {
+            let mut store = PolicyStore::load_or_default(&file)?;
+            let mut policy = SRv6Policy::new(endpoint, color, path)
+                .with_metadata(PolicyMetadata { latency_ms, bandwidth_mbps, priority });
+            if let Some(headend) = headend {
+                policy = policy.with_headend(headend);
+            }
+            println!("ポリシーを追加しました: {}", policy.policy_id);
+            store.insert(policy)?;
+            store.save(&file)?;
+        },
+        PolicyAction::List { file } => {
+            for policy in PolicyStore::load(&file)?.policies() {
+                println!("{}", policy);
+            }
+        },
+        PolicyAction::Remove { file, id } => {
+            let mut store = PolicyStore::load(&file)?;
+            store.remove(&id).ok_or_else(|| format!("ポリシーが見つかりません: {}", id))?;
+            store.save(&file)?;
+            println!("ポリシーを削除しました: {}", id);
+        },
+    }
+    Ok(())
+}
+
+// 送信者 → 中継ノード1..hops → 受信者 を直列につないだlocalhostのトポロジー。
+// SIDは2001:db8::1から連番。uSID時はLocator-Block fc00:0::/32 にuSID 1から連番
+fn linear_topology(hops: usize, port_base: u16, usid: bool) -> Result<TopologyFile, Error> {
+    if hops == 0 || hops >= MAX_HOPS {
+        return Err(ConfigError::Invalid(vec![format!("中継ノード数は 1〜{} の範囲で指定してください", MAX_HOPS - 1)]).into());
+    }
+    let localhost = IpAddr::V6(Ipv6Addr::LOCALHOST);
+    let mut file = TopologyFile { usid: usid.then(UsidFormat::default), ..TopologyFile::default() };
+    file.nodes.push(TopologyNode::new("sender")
+        .with_role(Role::Entry)
+        .with_address(SocketAddr::new(localhost, port_base)));
+    for i in 1..=hops {
+        let sid = if usid {
+            Ipv6Addr::new(0xfc00, 0, i as u16, 0, 0, 0, 0, 0)
+        } else {
+            Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, i as u16)
+        };
+        file.nodes.push(TopologyNode::new(&format!("relay{}", i))
+            .with_sid(sid)
+            .with_address(SocketAddr::new(localhost, port_base + i as u16)));
+    }
+    file.nodes.push(TopologyNode::new("receiver")
+        .with_role(Role::Exit)
+        .with_address(SocketAddr::new(localhost, port_base + hops as u16 + 1)));
+    file.links = file.nodes.windows(2)
+        .map(|pair| TopologyEdge::new(&pair[0].id, &pair[1].id, DEMO_LINK_LATENCY_MS, DEMO_LINK_BANDWIDTH_MBPS))
+        .collect();
+    file.validate()?;
+    Ok(file)
+}
+
+// トポロジーの中継ノードと受信者を起動し、送信元から宛先へメッセージを送信するデモ
+async fn demo(file: &TopologyFile, from: Option<&str>, to: Option<&str>, message: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let topology = file.topology()?;
+    let source = file.endpoint(Role::Entry, from)?;
+    let destination = file.endpoint(Role::Exit, to)?;
+    info!(nodes = file.nodes.len(), from = %source.id, to = %destination.id, "HORNETベースOnion Routing Proof of Concept");
+
+    // SRドメイン共通のSRH HMAC鍵を生成
+    let mut srh_hmac_key = SrhHmacKey { key_id: 1, key: vec![0u8; SRH_HMAC_KEY_SIZE] };
+    rand::thread_rng().fill_bytes(&mut srh_hmac_key.key);
+
+    // 中継ノードと受信者を作成（送信者は送信時に作る）
+    let mut nodes = Vec::with_capacity(file.nodes.len());
+    let mut trusted_keys = Vec::new();
+    let mut peer_keys = Vec::new();
+    for topology_node in &file.nodes {
+        let address = topology_node.address.expect("検証済み");
+        let mut node = match (topology_node.role, topology_node.sid) {
+            (Role::Relay, Some(sid)) => Node::new(NodeType::Relay(sid), address),
+            (Role::Exit, _) => Node::new(NodeType::Receiver, address).with_message_sink(spawn_message_printer()),
+            _ => continue,
+        };
+        node = node.with_srh_hmac_key(srh_hmac_key.clone());
+        // ハンドシェイク応答と経路変更通知に署名できるよう、鍵ファイルのないノードには一時的な識別鍵を生成する
+        let identity_key = match &topology_node.key_file {
+            Some(path) => {
+                let key = load_identity_key(path)?;
+                if topology_node.identity_public_key().is_some_and(|public_key| public_key != key.public_key()) {
+                    return Err(format!("{} の識別鍵が public_key と一致しません: {}", topology_node.id, path.display()).into());
+                }
+                key
+            },
+            None => p384::SecretKey::random(&mut OsRng),
+        };
+        if let Some(sid) = topology_node.sid {
+            trusted_keys.push((sid, identity_key.public_key()));
+        }
+        peer_keys.push((address, identity_key.public_key()));
+        node = node.with_identity_key(identity_key);
+        if let (Role::Relay, Some(format)) = (topology_node.role, file.usid) {
+            node = node.with_usid_format(format);
+        }
+
+        // 隣接する中継ノードのSIDへの経路を登録。
+        // uSIDキャリアはシフトで後続のuSIDが宛先に残るため、Locator-Block + uSID のプレフィックスで登録する
+        for neighbor in topology.neighbors(&topology_node.id) {
+            let (Some(sid), Some(next_hop)) = (neighbor.sid, neighbor.address) else {
+                continue;
+            };
+            match file.usid {
+                Some(format) => node.add_locator_route(sid, format.block_len + format.usid_len, next_hop),
+                None => node.add_sid_route(sid, next_hop),
+            }
+        }
+        nodes.push((topology_node.id.clone(), Arc::new(node), address));
+    }
+    for (_, node, _) in &nodes {
+        for (sid, key) in &trusted_keys {
+            node.add_trusted_key(*sid, *key);
+        }
+    }
+
+    // 各ノードを別タスクで実行
+    let mut handles = Vec::with_capacity(nodes.len());
+    for (name, node, address) in &nodes {
+        let socket = Arc::new(UdpSocket::bind(address).await?);
+        let node = Arc::clone(node);
+        let name = name.clone();
+        handles.push(tokio::spawn(async move {
+            if let Err(e) = node.run(socket).await {
+                error!(node = %name, code = %e.code(), error = %e, "ノード停止");
+            }
+        }));
+    }
+
+    // 少し待ってから送信
+    sleep(Duration::from_secs(1)).await;
+
+    // 経路を選び、テストメッセージ送信
+    let mut policies = file.policies(source, destination, &file.path_selection, COLOR_DEFAULT)?;
+    let policy = policies.remove(0);
+    info!("テストメッセージを送信");
+    let sender_socket = UdpSocket::bind(source.address.expect("検証済み")).await?;
+    let mut sender_node = Node::new(NodeType::Sender, sender_socket.local_addr()?).with_srh_hmac_key(srh_hmac_key);
+    if let Some(format) = file.usid {
+        sender_node = sender_node.with_usid_format(format);
+    }
+    for (sid, key) in &trusted_keys {
+        sender_node.add_trusted_key(*sid, *key);
+    }
+    for (address, key) in peer_keys {
+        sender_node.add_peer_key(address, key);
+    }
+    let sender = Sender::new(sender_node, sender_socket);
+    sender.send(&policy, &policies, message.as_bytes()).await?;
+
+    // 配送完了を待つ間、経路変更通知を受け取る
+    let changes = sender.collect_path_changes(DEMO_PATH_CHANGE_WAIT).await;
+    print_path_changes(&changes);
+
+    // MAC検証失敗数、SRH HMAC検証失敗数とリプレイ破棄数を表示
+    for (name, node, _) in &nodes {
+        println!("[統計] {}: MAC検証失敗 {} 件, SRH HMAC検証失敗 {} 件, リプレイ破棄 {} 件",
+                 name,
+                 node.stats().mac_failures(),
+                 node.stats().srh_hmac_failures(),
+                 node.stats().replays_rejected());
+    }
+
+    info!("終了");
+    for handle in handles {
+        handle.abort();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,520 @@
+use std::fmt;
+use std::fs;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{load_identity_key, load_srh_hmac_key};
+use crate::error::{ConfigError, Error};
+use crate::node::{Node, NodeType};
+use crate::raw::RawSocket;
+use crate::replay::DEFAULT_REPLAY_WINDOW_SIZE;
+use crate::sid::{SidStructure, UsidFormat};
+use crate::srv6::{SidBehavior, UnknownSidPolicy};
+
+// 定数
+pub const DEFAULT_KEY_ROTATION_INTERVAL: u64 = 28800; // 8時間（秒単位）
+pub const DEFAULT_MAX_CONCURRENT_SESSIONS: usize = 10000;
+
+// 本実装のAEADはAES-256-GCM。TLS 1.3の暗号スイート名で許可を判定する
+pub const SUPPORTED_CIPHER_SUITE: &str = "TLS_AES_256_GCM_SHA384";
+pub const KNOWN_CIPHER_SUITES: [&str; 3] = [
+    "TLS_AES_256_GCM_SHA384",
+    "TLS_AES_128_GCM_SHA256",
+    "TLS_CHACHA20_POLY1305_SHA256",
+];
+pub const KNOWN_TEE_TYPES: [&str; 3] = ["sgx", "sev", "trustzone"];
+
+// ノード設定ファイル（README §6.2.3）。
+// READMEの interfaces, sid_format, crypto_accel, buffer_pool_size, min_tee_version は本実装に対応する機能がないため受け付けない
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub node: NodeConfig,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct NodeConfig {
+    pub id: String,
+    pub role: Role,
+    #[serde(default)]
+    pub tee: Option<TeeConfig>,
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub performance: PerformanceConfig,
+    #[serde(default)]
+    pub security: SecurityConfig,
+}
+
+// entry=送信者, relay=中継ノード, exit=受信者
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Entry,
+    Relay,
+    Exit,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Entry => write!(f, "entry"),
+            Role::Relay => write!(f, "relay"),
+            Role::Exit => write!(f, "exit"),
+        }
+    }
+}
+
+// TEE設定（現在の実装では読み込みと検証のみ）
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TeeConfig {
+    #[serde(rename = "type")]
+    pub tee_type: String,
+    #[serde(default)]
+    pub enclave_path: Option<PathBuf>,
+    #[serde(default)]
+    pub quoting_type: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub listen: SocketAddr, // データプレーンの待ち受けアドレス
+    #[serde(default)]
+    pub raw_socket: bool, // データパケットを生ソケット（IPv6 + SRH）で送受信する
+    #[serde(default)]
+    pub srv6: Srv6Config,
+}
+
+// SIDは sid を直接指定するか、locator_prefix と function_prefix から合成する。
+// function_prefix はロケータ長だけ右にずらして結合する（例: 2001:db8:cafe:: + f:: → 2001:db8:cafe:f::）
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Srv6Config {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub sid: Option<Ipv6Addr>,
+    #[serde(default)]
+    pub locator_prefix: Option<String>, // 例: 2001:db8:cafe:: または 2001:db8:cafe::/48
+    #[serde(default)]
+    pub function_prefix: Option<Ipv6Addr>,
+    #[serde(default)]
+    pub sid_structure: Option<SidStructure>, // Locator/Function/Argumentのビット長。未設定時はSIDを分解しない
+    #[serde(default)]
+    pub usid: Option<UsidFormat>, // 圧縮SID（uSIDキャリア）のLocator-Block/uSIDのビット長
+    #[serde(default)]
+    pub local_sids: Vec<LocalSidConfig>, // sid（End動作）に加えて公開するローカルSID
+    #[serde(default)]
+    pub unknown_sid_policy: UnknownSidPolicy, // drop または forward
+    #[serde(default)]
+    pub routes: Vec<Srv6RouteConfig>, // UDP転送時のロケータ経路（生ソケット時はカーネルの経路表を使う）
+    #[serde(default)]
+    pub hmac: Option<SrhHmacConfig>, // 設定時はSRH HMAC TLVを付与・検証する
+}
+
+// SRH HMAC TLVの鍵（SRドメイン内の全ノードで共有）
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SrhHmacConfig {
+    pub key_id: u32,
+    pub key_file: PathBuf, // keygen --srh-hmac で生成
+}
+
+// ローカルSIDとエンドポイント動作
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LocalSidConfig {
+    pub sid: Ipv6Addr,
+    pub behavior: String, // end, end.x:<隣接ノード>, end.t:<テーブルID>, end.dt6:<テーブルID>
+}
+
+// ロケータ経路: SIDがプレフィックスに一致するパケットを via へ転送する
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Srv6RouteConfig {
+    pub prefix: String, // 例: 2001:db8::2/128 または 2001:db8:cafe::/48
+    pub via: SocketAddr,
+    #[serde(default)]
+    pub table: u32, // 省略時はメインテーブル（0）
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PerformanceConfig {
+    #[serde(default = "default_threads")]
+    pub threads: usize,
+    #[serde(default = "default_max_concurrent_sessions")]
+    pub max_concurrent_sessions: usize,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SecurityConfig {
+    #[serde(default = "default_key_rotation_interval")]
+    pub key_rotation_interval: u64, // 秒
+    #[serde(default = "default_cipher_suites")]
+    pub allowed_cipher_suites: Vec<String>,
+    #[serde(default)]
+    pub identity_key_file: Option<PathBuf>,
+    #[serde(default = "default_replay_window")]
+    pub replay_window: usize,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_threads() -> usize {
+    std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+fn default_max_concurrent_sessions() -> usize {
+    DEFAULT_MAX_CONCURRENT_SESSIONS
+}
+
+fn default_key_rotation_interval() -> u64 {
+    DEFAULT_KEY_ROTATION_INTERVAL
+}
+
+fn default_cipher_suites() -> Vec<String> {
+    vec![SUPPORTED_CIPHER_SUITE.to_string()]
+}
+
+fn default_replay_window() -> usize {
+    DEFAULT_REPLAY_WINDOW_SIZE
+}
+
+impl Default for Srv6Config {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            sid: None,
+            locator_prefix: None,
+            function_prefix: None,
+            sid_structure: None,
+            usid: None,
+            local_sids: Vec::new(),
+            unknown_sid_policy: UnknownSidPolicy::default(),
+            routes: Vec::new(),
+            hmac: None,
+        }
+    }
+}
+
+impl Default for PerformanceConfig {
+    fn default() -> Self {
+        Self {
+            threads: default_threads(),
+            max_concurrent_sessions: DEFAULT_MAX_CONCURRENT_SESSIONS,
+        }
+    }
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            key_rotation_interval: DEFAULT_KEY_ROTATION_INTERVAL,
+            allowed_cipher_suites: default_cipher_suites(),
+            identity_key_file: None,
+            replay_window: DEFAULT_REPLAY_WINDOW_SIZE,
+        }
+    }
+}
+
+impl Config {
+    // 拡張子が .toml ならTOML、それ以外はYAMLとして読み込み、検証する
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = fs::read_to_string(path)
+            .map_err(|source| ConfigError::Read { path: path.to_path_buf(), source })?;
+        let config = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml_str(&text)?,
+            _ => Self::from_yaml_str(&text)?,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_yaml_str(text: &str) -> Result<Self, Error> {
+        serde_yaml::from_str(text)
+            .map_err(|e| ConfigError::Parse(format!("YAML: {}", e)).into())
+    }
+
+    pub fn from_toml_str(text: &str) -> Result<Self, Error> {
+        toml::from_str(text)
+            .map_err(|e| ConfigError::Parse(format!("TOML: {}", e)).into())
+    }
+
+    // 設定値を検証し、問題をすべてまとめて報告する
+    pub fn validate(&self) -> Result<(), Error> {
+        let node = &self.node;
+        let mut problems = Vec::new();
+
+        if node.id.trim().is_empty() {
+            problems.push("node.id が空です".to_string());
+        }
+
+        if let Some(tee) = &node.tee {
+            if !KNOWN_TEE_TYPES.contains(&tee.tee_type.as_str()) {
+                problems.push(format!("node.tee.type が不明です: {} (使用可能: {})",
+                                      tee.tee_type, KNOWN_TEE_TYPES.join(", ")));
+            }
+        }
+
+        if node.network.raw_socket && node.network.listen.ip().is_unspecified() {
+            problems.push("node.network.raw_socket を使う場合は node.network.listen に具体的なアドレスを指定してください".to_string());
+        }
+        if node.role != Role::Entry && node.network.listen.port() == 0 {
+            problems.push("node.network.listen にポート番号を指定してください".to_string());
+        }
+        for (i, route) in node.network.srv6.routes.iter().enumerate() {
+            if let Err(e) = parse_prefix(&route.prefix) {
+                problems.push(format!("node.network.srv6.routes[{}].prefix: {}", i, e));
+            }
+        }
+        if let Some(Err(e)) = node.network.srv6.usid.map(|format| format.validate()) {
+            problems.push(format!("node.network.srv6.usid: {}", e));
+        }
+        for (i, local_sid) in node.network.srv6.local_sids.iter().enumerate() {
+            if let Err(e) = local_sid.behavior.parse::<SidBehavior>() {
+                problems.push(format!("node.network.srv6.local_sids[{}].behavior: {}", i, e));
+            }
+        }
+        if node.role != Role::Relay && !node.network.srv6.local_sids.is_empty() {
+            problems.push("node.network.srv6.local_sids はrelayロールでのみ使用できます".to_string());
+        }
+        if let Some(hmac) = &node.network.srv6.hmac {
+            if !hmac.key_file.is_file() {
+                problems.push(format!("node.network.srv6.hmac.key_file が見つかりません: {}", hmac.key_file.display()));
+            }
+        }
+        match self.sid() {
+            Ok(None) if node.role == Role::Relay => {
+                problems.push("relayロールには node.network.srv6 の sid または locator_prefix が必要です".to_string());
+            },
+            Ok(_) => {},
+            Err(Error::Config(ConfigError::Invalid(mut sid_problems))) => problems.append(&mut sid_problems),
+            Err(e) => problems.push(e.to_string()),
+        }
+
+        let performance = &node.performance;
+        if performance.threads == 0 {
+            problems.push("node.performance.threads は1以上が必要です".to_string());
+        }
+        if performance.max_concurrent_sessions == 0 {
+            problems.push("node.performance.max_concurrent_sessions は1以上が必要です".to_string());
+        }
+
+        let security = &node.security;
+        if security.key_rotation_interval == 0 {
+            problems.push("node.security.key_rotation_interval は1秒以上が必要です".to_string());
+        }
+        if security.replay_window == 0 {
+            problems.push("node.security.replay_window は1以上が必要です".to_string());
+        }
+        for suite in &security.allowed_cipher_suites {
+            if !KNOWN_CIPHER_SUITES.contains(&suite.as_str()) {
+                problems.push(format!("node.security.allowed_cipher_suites に不明な暗号スイートがあります: {}", suite));
+            }
+        }
+        if !security.allowed_cipher_suites.iter().any(|suite| suite == SUPPORTED_CIPHER_SUITE) {
+            problems.push(format!("node.security.allowed_cipher_suites に本実装が使用する {} が含まれていません",
+                                  SUPPORTED_CIPHER_SUITE));
+        }
+        match &security.identity_key_file {
+            Some(path) if !path.is_file() => {
+                problems.push(format!("node.security.identity_key_file が見つかりません: {}", path.display()));
+            },
+            None if node.role != Role::Entry => {
+                problems.push(format!("{}ロールにはハンドシェイク応答の署名に使う node.security.identity_key_file が必要です", node.role));
+            },
+            _ => {},
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems).into())
+        }
+    }
+
+    // 設定から自ノードのSIDを求める
+    pub fn sid(&self) -> Result<Option<Ipv6Addr>, Error> {
+        let srv6 = &self.node.network.srv6;
+        if !srv6.enabled {
+            return Ok(None);
+        }
+        if let Some(sid) = srv6.sid {
+            return Ok(Some(sid));
+        }
+        let Some(locator) = &srv6.locator_prefix else {
+            return Ok(None);
+        };
+
+        let (locator, locator_len) = parse_locator(locator, srv6.sid_structure)
+            .map_err(|e| ConfigError::Invalid(vec![format!("node.network.srv6.locator_prefix: {}", e)]))?;
+        let locator_bits = u128::from(locator);
+        let function_bits = srv6.function_prefix.map(u128::from).unwrap_or(0);
+        let function_bits = function_bits.checked_shr(locator_len as u32).unwrap_or(0);
+        Ok(Some(Ipv6Addr::from(locator_bits | function_bits)))
+    }
+
+    pub fn node_type(&self) -> Result<NodeType, Error> {
+        match self.node.role {
+            Role::Entry => Ok(NodeType::Sender),
+            Role::Exit => Ok(NodeType::Receiver),
+            Role::Relay => self.sid()?
+                .map(NodeType::Relay)
+                .ok_or(ConfigError::Invalid(vec!["relayロールにはSIDが必要です".into()]).into()),
+        }
+    }
+
+    // 設定に従ってノードを構築する
+    pub fn build_node(&self) -> Result<Node, Error> {
+        let security = &self.node.security;
+        let srv6 = &self.node.network.srv6;
+        let mut node = Node::new(self.node_type()?, self.node.network.listen)
+            .with_unknown_sid_policy(srv6.unknown_sid_policy)
+            .with_replay_window_size(security.replay_window)
+            .with_max_sessions(self.node.performance.max_concurrent_sessions)
+            .with_key_lifetime(Duration::from_secs(security.key_rotation_interval));
+        if let Some(path) = &security.identity_key_file {
+            node = node.with_identity_key(load_identity_key(path)?);
+        }
+        if self.node.network.raw_socket {
+            node = node.with_raw_socket(RawSocket::open()?);
+        }
+        for local_sid in &srv6.local_sids {
+            let behavior = local_sid.behavior.parse()
+                .map_err(|e| ConfigError::Invalid(vec![format!("node.network.srv6.local_sids: {}", e)]))?;
+            node.add_local_sid(local_sid.sid, behavior);
+        }
+        for route in &srv6.routes {
+            let (locator, prefix_len) = parse_prefix(&route.prefix)
+                .map_err(|e| ConfigError::Invalid(vec![format!("node.network.srv6.routes: {}", e)]))?;
+            node.add_table_route(route.table, locator, prefix_len, route.via);
+        }
+        if let Some(structure) = srv6.sid_structure {
+            node = node.with_sid_structure(structure);
+        }
+        if let Some(format) = srv6.usid {
+            node = node.with_usid_format(format);
+        }
+        if let Some(hmac) = &srv6.hmac {
+            node = node.with_srh_hmac_key(load_srh_hmac_key(&hmac.key_file, hmac.key_id)?);
+        }
+        Ok(node)
+    }
+}
+
+// "2001:db8::1/64" 形式のプレフィックスを解析
+fn parse_prefix(text: &str) -> Result<(Ipv6Addr, u8), String> {
+    let (address, length) = text
+        .split_once('/')
+        .ok_or_else(|| format!("プレフィックス長がありません: {}", text))?;
+    let address = address.parse::<Ipv6Addr>()
+        .map_err(|_| format!("IPv6アドレスが不正です: {}", address))?;
+    let length = length.parse::<u8>()
+        .ok()
+        .filter(|length| *length <= 128)
+        .ok_or_else(|| format!("プレフィックス長が不正です: {}", length))?;
+    Ok((address, length))
+}
+
+// ロケータを解析。長さ省略時はSID構造のロケータ長、SID構造もなければ末尾のゼロでないハーフワードまでをロケータとみなす
+fn parse_locator(text: &str, structure: Option<SidStructure>) -> Result<(Ipv6Addr, u8), String> {
+    if text.contains('/') {
+        return parse_prefix(text);
+    }
+    let address = text.parse::<Ipv6Addr>()
+        .map_err(|_| format!("IPv6アドレスが不正です: {}", text))?;
+    if let Some(structure) = structure {
+        return Ok((address, structure.locator_len()));
+    }
+    let significant = address.segments().iter().rposition(|segment| *segment != 0).map_or(0, |i| i + 1);
+    Ok((address, (significant * 16) as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+
+    const EXAMPLE: &str = include_str!("../config/relay.example.yaml");
+
+    const ENTRY_TOML: &str = r#"
+[node]
+id = "sender"
+role = "entry"
+
+[node.network]
+listen = "[::1]:0"
+
+[node.performance]
+threads = 2
+"#;
+
+    fn problems(config: &Config) -> Vec<String> {
+        match config.validate() {
+            Err(Error::Config(ConfigError::Invalid(problems))) => problems,
+            other => panic!("検証エラーになるはず: {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn parses_example_yaml() {
+        let config = Config::from_yaml_str(EXAMPLE).unwrap();
+        assert_eq!(config.node.role, Role::Relay);
+        assert_eq!(config.node.performance.threads, 8);
+        assert_eq!(config.node.network.srv6.local_sids.len(), 2);
+        assert_eq!(config.sid().unwrap(), Some("2001:db8:cafe:0:f::".parse().unwrap()));
+    }
+
+    #[test]
+    fn parses_toml_with_defaults() {
+        let config = Config::from_toml_str(ENTRY_TOML).unwrap();
+        assert_eq!(config.node.role, Role::Entry);
+        assert_eq!(config.node.performance.threads, 2);
+        assert_eq!(config.node.performance.max_concurrent_sessions, DEFAULT_MAX_CONCURRENT_SESSIONS);
+        assert_eq!(config.node.security.replay_window, DEFAULT_REPLAY_WINDOW_SIZE);
+        assert!(config.validate().is_ok());
+        assert!(matches!(config.node_type().unwrap(), NodeType::Sender));
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let yaml = EXAMPLE.replace("      enabled: true\n", "      enabled: true\n      sid_format: \"ioam\"\n");
+        assert_eq!(Config::from_yaml_str(&yaml).unwrap_err().code(), ErrorCode::ConfigParse);
+
+        let toml = format!("{}crypto_accel = true\n", ENTRY_TOML);
+        assert_eq!(Config::from_toml_str(&toml).unwrap_err().code(), ErrorCode::ConfigParse);
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let mut config = Config::from_yaml_str(EXAMPLE).unwrap();
+        config.node.id = " ".into();
+        config.node.performance.threads = 0;
+        config.node.security.replay_window = 0;
+        config.node.security.allowed_cipher_suites = vec!["TLS_UNKNOWN".into()];
+        config.node.security.identity_key_file = None;
+
+        let problems = problems(&config);
+        assert_eq!(problems.len(), 6, "{:?}", problems);
+        for field in ["node.id", "threads", "replay_window", "TLS_UNKNOWN", SUPPORTED_CIPHER_SUITE, "identity_key_file"] {
+            assert!(problems.iter().any(|problem| problem.contains(field)), "{} がない: {:?}", field, problems);
+        }
+    }
+
+    #[test]
+    fn relay_requires_sid() {
+        let mut config = Config::from_yaml_str(EXAMPLE).unwrap();
+        config.node.network.srv6.locator_prefix = None;
+        assert!(problems(&config).iter().any(|problem| problem.contains("locator_prefix")));
+        assert_eq!(config.node_type().err().unwrap().code(), ErrorCode::ConfigInvalid);
+    }
+}
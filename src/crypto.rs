@@ -0,0 +1,289 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::net::Ipv6Addr;
+use std::path::Path;
+
+use aes_gcm::{Aes256Gcm, Nonce};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use p384::{PublicKey, SecretKey};
+use p384::ecdsa::{Signature, SigningKey, VerifyingKey};
+use p384::ecdsa::signature::{Signer, Verifier};
+use p384::elliptic_curve::sec1::ToEncodedPoint;
+use rand::RngCore;
+use rand::rngs::OsRng;
+use sha2::Sha256;
+
+use crate::error::{CryptoError, Error, InitError};
+
+// 定数
+pub const MAC_SIZE: usize = 32;
+pub const NONCE_SIZE: usize = 12;
+pub const TAG_SIZE: usize = 16;
+pub const SRH_HMAC_KEY_SIZE: usize = 32;
+pub const SIGNATURE_SIZE: usize = 96; // P-384 ECDSA署名（r | s）
+
+type HmacSha256 = Hmac<Sha256>;
+type Aes256Ctr = ctr::Ctr32BE<aes::Aes256>;
+
+// ハンドシェイクで導出されるセッション鍵一式（README §4.2.2）
+#[derive(Clone)]
+pub struct SessionKeys {
+    pub encryption_key: Vec<u8>,
+    pub mac_key: Vec<u8>,
+    pub iv_base: [u8; NONCE_SIZE],
+}
+
+// SRH HMAC TLV用のSRドメイン共通鍵（RFC 8754 §2.1.2）
+#[derive(Clone)]
+pub struct SrhHmacKey {
+    pub key_id: u32,
+    pub key: Vec<u8>,
+}
+
+// KDFヘルパー関数
+pub fn derive_keys(shared_secret: &[u8], context: &[u8]) -> SessionKeys {
+    let salt = b"HORNET-POC-Salt";
+    let hkdf = Hkdf::<Sha256>::new(Some(salt), shared_secret);
+    
+    let mut encryption_key = vec![0u8; 32];
+    hkdf.expand_multi_info(&[context, b"enc"], &mut encryption_key)
+        .expect("HKDF拡張に失敗");
+        
+    let mut mac_key = vec![0u8; 32];
+    hkdf.expand_multi_info(&[context, b"mac"], &mut mac_key)
+        .expect("HKDF拡張に失敗");
+    
+    let mut iv_base = [0u8; NONCE_SIZE];
+    hkdf.expand_multi_info(&[context, b"iv"], &mut iv_base)
+        .expect("HKDF拡張に失敗");
+    
+    SessionKeys {
+        encryption_key,
+        mac_key,
+        iv_base,
+    }
+}
+
+// 層ごとのノンス: IVBaseの下位8バイトにシーケンス番号をXOR
+// シーケンス番号はセッション内で重複しないため、同一鍵でノンスが再利用されることはない
+pub fn layer_nonce(iv_base: &[u8; NONCE_SIZE], sequence: u64) -> [u8; NONCE_SIZE] {
+    let mut nonce = *iv_base;
+    for (byte, counter) in nonce[NONCE_SIZE - 8..].iter_mut().zip(sequence.to_be_bytes()) {
+        *byte ^= counter;
+    }
+    nonce
+}
+
+// ペイロード用ノンス: 鍵ストリームとノンスが重ならないよう先頭ビットで領域を分離
+pub fn payload_nonce(iv_base: &[u8; NONCE_SIZE], sequence: u64) -> [u8; NONCE_SIZE] {
+    let mut nonce = layer_nonce(iv_base, sequence);
+    nonce[0] ^= 0x80;
+    nonce
+}
+
+// 層ごとの鍵ストリーム（AES-256-CTR）
+pub fn keystream(key: &[u8], nonce: &[u8; NONCE_SIZE], len: usize) -> Result<Vec<u8>, Error> {
+    let mut iv = [0u8; 16];
+    iv[..NONCE_SIZE].copy_from_slice(nonce);
+    let mut cipher = Aes256Ctr::new_from_slices(key, &iv)
+        .map_err(|_| CryptoError::InvalidKeyLength)?;
+    
+    let mut stream = vec![0u8; len];
+    cipher.apply_keystream(&mut stream);
+    Ok(stream)
+}
+
+pub fn xor_in_place(data: &mut [u8], stream: &[u8]) {
+    for (byte, key) in data.iter_mut().zip(stream) {
+        *byte ^= key;
+    }
+}
+
+// 制御メッセージ（パスリストなど）をセッション鍵で暗号化する。
+// データパケットのノンス（IVBase由来）と衝突しないよう、ノンスは毎回ランダムに生成して暗号文と一緒に送る。
+// セッションIDを関連データとして認証し、別セッションへの付け替えを防ぐ
+pub fn seal_control(key: &[u8], session_id: u32, plaintext: &[u8]) -> Result<([u8; NONCE_SIZE], Vec<u8>), Error> {
+    let cipher = Aes256Gcm::new_from_slice(key)
+        .map_err(|_| CryptoError::InvalidKeyLength)?;
+    let mut nonce = [0u8; NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = cipher.encrypt(&Nonce::from(nonce), Payload { msg: plaintext, aad: &session_id.to_be_bytes() })
+        .map_err(|_| CryptoError::Encryption)?;
+    Ok((nonce, ciphertext))
+}
+
+pub fn open_control(key: &[u8], session_id: u32, nonce: &[u8; NONCE_SIZE], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+    let cipher = Aes256Gcm::new_from_slice(key)
+        .map_err(|_| CryptoError::InvalidKeyLength)?;
+    let plaintext = cipher.decrypt(&Nonce::from(*nonce), Payload { msg: ciphertext, aad: &session_id.to_be_bytes() })
+        .map_err(|_| CryptoError::Decryption)?;
+    Ok(plaintext)
+}
+
+// Onion層のMACを計算: HMAC(MACKey, Layer || SessionID || Seq)
+// Layerはルーティング情報とペイロード。シーケンス番号も認証対象とし、リプレイ検出を回避する改ざんを防ぐ
+pub fn compute_mac(mac_key: &[u8], routing_info: &[u8], payload: &[u8], session_id: u32, sequence: u64) -> [u8; MAC_SIZE] {
+    let mut hmac = <HmacSha256 as Mac>::new_from_slice(mac_key)
+        .expect("HMACは任意長の鍵を受け付ける");
+    hmac.update(routing_info);
+    hmac.update(payload);
+    hmac.update(&session_id.to_be_bytes());
+    hmac.update(&sequence.to_be_bytes());
+    
+    let mut mac = [0u8; MAC_SIZE];
+    mac.copy_from_slice(&hmac.finalize().into_bytes());
+    mac
+}
+
+// Onion層のMACを定数時間で検証
+pub fn verify_mac(mac_key: &[u8], routing_info: &[u8], payload: &[u8], session_id: u32, sequence: u64, mac: &[u8; MAC_SIZE]) -> bool {
+    let mut hmac = <HmacSha256 as Mac>::new_from_slice(mac_key)
+        .expect("HMACは任意長の鍵を受け付ける");
+    hmac.update(routing_info);
+    hmac.update(payload);
+    hmac.update(&session_id.to_be_bytes());
+    hmac.update(&sequence.to_be_bytes());
+    hmac.verify_slice(mac).is_ok()
+}
+
+// 制御メッセージ（鍵更新の開始メッセージなど）のMAC: HMAC(MACKey, data)
+pub fn compute_control_mac(mac_key: &[u8], data: &[u8]) -> [u8; MAC_SIZE] {
+    let mut hmac = <HmacSha256 as Mac>::new_from_slice(mac_key)
+        .expect("HMACは任意長の鍵を受け付ける");
+    hmac.update(data);
+    let mut mac = [0u8; MAC_SIZE];
+    mac.copy_from_slice(&hmac.finalize().into_bytes());
+    mac
+}
+
+// 制御メッセージのMACを定数時間で検証
+pub fn verify_control_mac(mac_key: &[u8], data: &[u8], mac: &[u8]) -> bool {
+    let mut hmac = <HmacSha256 as Mac>::new_from_slice(mac_key)
+        .expect("HMACは任意長の鍵を受け付ける");
+    hmac.update(data);
+    hmac.verify_slice(mac).is_ok()
+}
+
+// SRH HMACを計算（RFC 8754 §2.1.2.1）:
+// HMAC(Key, 送信元アドレス || Last Entry || Flags || HMAC Key ID || セグメントリスト)
+pub fn compute_srh_hmac(key: &SrhHmacKey, source: &Ipv6Addr, last_entry: u8, flags: u8, segment_list: &[Ipv6Addr]) -> [u8; MAC_SIZE] {
+    let mut mac = [0u8; MAC_SIZE];
+    mac.copy_from_slice(&srh_hmac(key, source, last_entry, flags, segment_list).finalize().into_bytes());
+    mac
+}
+
+// SRH HMACを定数時間で検証
+pub fn verify_srh_hmac(key: &SrhHmacKey, source: &Ipv6Addr, last_entry: u8, flags: u8, segment_list: &[Ipv6Addr], mac: &[u8]) -> bool {
+    srh_hmac(key, source, last_entry, flags, segment_list).verify_slice(mac).is_ok()
+}
+
+fn srh_hmac(key: &SrhHmacKey, source: &Ipv6Addr, last_entry: u8, flags: u8, segment_list: &[Ipv6Addr]) -> HmacSha256 {
+    let mut hmac = <HmacSha256 as Mac>::new_from_slice(&key.key)
+        .expect("HMACは任意長の鍵を受け付ける");
+    hmac.update(&source.octets());
+    hmac.update(&[last_entry, flags]);
+    hmac.update(&key.key_id.to_be_bytes());
+    for segment in segment_list {
+        hmac.update(&segment.octets());
+    }
+    hmac
+}
+
+// ハンドシェイクのKDFコンテキスト: NonceS || NonceN || SessionID
+pub fn handshake_context(nonce_s: &[u8], nonce_n: &[u8], session_id: u32) -> Vec<u8> {
+    let mut context = Vec::with_capacity(nonce_s.len() + nonce_n.len() + 4);
+    context.extend_from_slice(nonce_s);
+    context.extend_from_slice(nonce_n);
+    context.extend_from_slice(&session_id.to_be_bytes());
+    context
+}
+
+// 長期識別鍵によるECDSA P-384署名（SHA-384）
+pub fn sign_with_identity(key: &SecretKey, data: &[u8]) -> [u8; SIGNATURE_SIZE] {
+    let signature: Signature = SigningKey::from(key).sign(data);
+    let mut bytes = [0u8; SIGNATURE_SIZE];
+    bytes.copy_from_slice(&signature.to_bytes());
+    bytes
+}
+
+pub fn verify_identity_signature(key: &PublicKey, data: &[u8], signature: &[u8; SIGNATURE_SIZE]) -> bool {
+    let Ok(signature) = Signature::from_slice(signature) else {
+        return false;
+    };
+    VerifyingKey::from(key).verify(data, &signature).is_ok()
+}
+
+// 識別鍵ファイル: P-384秘密鍵スカラー(48バイト)の16進文字列1行
+pub fn load_identity_key(path: &Path) -> Result<SecretKey, Error> {
+    let identity_key_error = |reason: String| InitError::IdentityKey { path: path.to_path_buf(), reason };
+    let text = fs::read_to_string(path).map_err(|e| identity_key_error(e.to_string()))?;
+    let bytes = decode_hex(text.trim())
+        .ok_or_else(|| identity_key_error("16進表記が不正です".into()))?;
+    let key = SecretKey::from_slice(&bytes)
+        .map_err(|_| identity_key_error("P-384秘密鍵として不正です".into()))?;
+    Ok(key)
+}
+
+pub fn generate_identity_key(path: &Path) -> Result<SecretKey, Error> {
+    let key = SecretKey::random(&mut OsRng);
+    write_secret_file(path, &key.to_bytes())?;
+    Ok(key)
+}
+
+// SRH HMAC鍵ファイル: 鍵(32バイト)の16進文字列1行。SRドメイン内の全ノードで共有する
+pub fn load_srh_hmac_key(path: &Path, key_id: u32) -> Result<SrhHmacKey, Error> {
+    let srh_hmac_key_error = |reason: String| InitError::SrhHmacKey { path: path.to_path_buf(), reason };
+    let text = fs::read_to_string(path).map_err(|e| srh_hmac_key_error(e.to_string()))?;
+    let key = decode_hex(text.trim())
+        .ok_or_else(|| srh_hmac_key_error("16進表記が不正です".into()))?;
+    if key.len() < SRH_HMAC_KEY_SIZE {
+        return Err(srh_hmac_key_error(format!("鍵は{}バイト以上必要です", SRH_HMAC_KEY_SIZE)).into());
+    }
+    Ok(SrhHmacKey { key_id, key })
+}
+
+pub fn generate_srh_hmac_key(path: &Path) -> Result<(), Error> {
+    let mut key = [0u8; SRH_HMAC_KEY_SIZE];
+    OsRng.fill_bytes(&mut key);
+    write_secret_file(path, &key)
+}
+
+// 秘密鍵を16進1行で新規ファイルに書き込む。既存ファイルは上書きしない
+fn write_secret_file(path: &Path, secret: &[u8]) -> Result<(), Error> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    // 秘密鍵は所有者のみ読み書き可能にする
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let key_generation_error = |source| InitError::KeyGeneration { path: path.to_path_buf(), source };
+    let mut file = options.open(path).map_err(key_generation_error)?;
+    writeln!(file, "{}", encode_hex(secret)).map_err(key_generation_error)?;
+    Ok(())
+}
+
+// 公開鍵の表示用表記（SEC1圧縮形式の16進）
+pub fn public_key_hex(key: &p384::PublicKey) -> String {
+    encode_hex(key.to_encoded_point(true).as_bytes())
+}
+
+// public_key_hex の表記（圧縮・非圧縮いずれのSEC1形式も可）を読む
+pub fn decode_public_key(text: &str) -> Option<p384::PublicKey> {
+    decode_hex(text.trim()).and_then(|bytes| p384::PublicKey::from_sec1_bytes(&bytes).ok())
+}
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,450 @@
+use std::io;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+// エラータイプ（README §6.3.1 のエラー分類に対応）
+// 呼び出し側は文字列ではなく code() で判別する
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("設定エラー: {0}")]
+    Config(#[from] ConfigError),
+    #[error("初期化エラー: {0}")]
+    Init(#[from] InitError),
+    #[error("通信エラー: {0}")]
+    Communication(#[from] CommunicationError),
+    #[error("暗号エラー: {0}")]
+    Crypto(#[from] CryptoError),
+    #[error("セッションエラー: {0}")]
+    Session(#[from] SessionError),
+    #[error("経路エラー: {0}")]
+    Path(#[from] PathError),
+}
+
+// 設定エラー
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("設定ファイルを読み込めません ({}): {source}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+    #[error("設定ファイルの解析に失敗しました: {0}")]
+    Parse(String),
+    #[error("ファイルに書き込めません ({}): {source}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+    #[error("設定値が不正です: {}", .0.join("; "))]
+    Invalid(Vec<String>), // 検証で見つかった問題の一覧
+}
+
+// 初期化エラー
+#[derive(Debug, Error)]
+pub enum InitError {
+    #[error("識別鍵ファイルを読み込めません ({}): {reason}", path.display())]
+    IdentityKey { path: PathBuf, reason: String },
+    #[error("識別鍵ファイルを作成できません ({}): {source}", path.display())]
+    KeyGeneration { path: PathBuf, source: io::Error },
+    #[error("SRH HMAC鍵ファイルを読み込めません ({}): {reason}", path.display())]
+    SrhHmacKey { path: PathBuf, reason: String },
+    #[error("生ソケットを開けません（CAP_NET_RAWが必要です）: {0}")]
+    RawSocket(io::Error),
+    #[error("生ソケットのデータプレーンは raw-socket フィーチャー付きのLinuxビルドでのみ使用できます")]
+    RawSocketUnsupported,
+    #[error("ハンドシェイクに応答するには長期識別鍵が必要です")]
+    MissingIdentityKey,
+}
+
+// 通信エラー
+#[derive(Debug, Error)]
+pub enum CommunicationError {
+    #[error("入出力エラー: {0}")]
+    Io(#[from] io::Error),
+    #[error("不正なパケットです: {0}")]
+    MalformedPacket(String),
+    #[error("不正なSRv6ヘッダーです: {0}")]
+    MalformedSrh(String),
+    #[error("不明なメッセージ種別です: {0}")]
+    UnknownMessageType(u8),
+    #[error("予期しないメッセージ種別です: {0}")]
+    UnexpectedMessage(String),
+    #[error("ハンドシェイクがタイムアウトしました: {0}")]
+    HandshakeTimeout(SocketAddr),
+    #[error("メッセージが長すぎます: {len}バイト（最大{max}バイト）")]
+    MessageTooLong { len: usize, max: usize },
+}
+
+// 暗号エラー
+#[derive(Debug, Error)]
+pub enum CryptoError {
+    #[error("MAC検証に失敗しました (セッションID: {session_id})")]
+    MacVerification { session_id: u32 },
+    #[error("暗号化に失敗しました")]
+    Encryption,
+    #[error("復号に失敗しました")]
+    Decryption,
+    #[error("復号したペイロードの形式が不正です")]
+    MalformedPayload,
+    #[error("鍵の長さが不正です")]
+    InvalidKeyLength,
+    #[error("不正な公開鍵です")]
+    InvalidPublicKey,
+    #[error("SRH HMACの検証に失敗しました: {0}")]
+    SrhHmac(&'static str),
+    #[error("署名の検証に失敗しました (署名者: {0})")]
+    Signature(Ipv6Addr),
+    #[error("署名者の公開鍵が登録されていません: {0}")]
+    UnknownSigner(Ipv6Addr),
+    #[error("ハンドシェイク応答の署名の検証に失敗しました ({0})")]
+    HandshakeSignature(SocketAddr),
+    #[error("ハンドシェイク相手の識別公開鍵が登録されていません: {0}")]
+    UnknownPeer(SocketAddr),
+}
+
+// セッションエラー
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error("不明なセッションです (セッションID: {0})")]
+    Unknown(u32),
+    #[error("セッション鍵の有効期限が切れています (セッションID: {0})")]
+    Expired(u32),
+    #[error("同時セッション数の上限に達しました")]
+    LimitReached,
+    #[error("不正なシーケンス番号です: {0}")]
+    InvalidSequence(u64),
+    #[error("重複したシーケンス番号です: {0}")]
+    DuplicateSequence(u64),
+    #[error("シーケンス番号が古すぎます: {0}")]
+    StaleSequence(u64),
+    #[error("経路変更通知の時刻が許容範囲外です (セッションID: {0})")]
+    StaleNotification(u32),
+    #[error("重複した経路変更通知です (セッションID: {0})")]
+    DuplicateNotification(u32),
+    #[error("確立済みセッションの鍵更新が認証されていません (セッションID: {0})")]
+    UnauthenticatedRekey(u32),
+}
+
+// 経路エラー
+#[derive(Debug, Error)]
+pub enum PathError {
+    #[error("ホップ数が上限を超えています: {hops}（最大{max}）")]
+    TooManyHops { hops: usize, max: usize },
+    #[error("パスとノード・鍵の数が一致しません")]
+    LengthMismatch,
+    #[error("SIDの転送先が見つかりません: {0}")]
+    UnknownSid(Ipv6Addr),
+    #[error("このノード宛ではありません: {0}")]
+    NotForThisNode(Ipv6Addr),
+    #[error("中継ノードではありません")]
+    NotRelay,
+    #[error("不正な次ホップ指示です: {0}")]
+    InvalidNextHop(&'static str),
+    #[error("経路が見つかりません: {destination} (テーブル: {table})")]
+    NoRoute { table: u32, destination: Ipv6Addr },
+    #[error("Hop Limitを超過しました")]
+    HopLimitExceeded,
+    #[error("不正なSIDです: {0}")]
+    InvalidSid(String),
+    #[error("SRv6ポリシーが見つかりません: 宛先 {endpoint}, カラー {color}")]
+    NoPolicy { endpoint: SocketAddr, color: u32 },
+    #[error("不正なSRv6ポリシーです: {0}")]
+    InvalidPolicy(String),
+    #[error("不正なパスリストです: {0}")]
+    InvalidPathList(String),
+    #[error("パスリストの有効期限が切れています (セッションID: {0})")]
+    PathListExpired(u32),
+    #[error("トポロジーに存在しない、または中継ノードとして使えないノードです: {0}")]
+    UnknownNode(String),
+    #[error("制約を満たす経路が見つかりません: {from} → {to}")]
+    NoPath { from: String, to: String },
+    #[error("障害ノード {failed} を避ける代替経路がありません (セッションID: {session_id})")]
+    NoAlternativePath { session_id: u32, failed: Ipv6Addr },
+}
+
+// 機械判読可能なエラーコード
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    ConfigRead,
+    ConfigParse,
+    ConfigWrite,
+    ConfigInvalid,
+    InitIdentityKey,
+    InitKeyGeneration,
+    InitSrhHmacKey,
+    InitRawSocket,
+    InitRawSocketUnsupported,
+    InitMissingIdentityKey,
+    Io,
+    MalformedPacket,
+    MalformedSrh,
+    UnknownMessageType,
+    UnexpectedMessage,
+    HandshakeTimeout,
+    MessageTooLong,
+    MacFailure,
+    EncryptionFailure,
+    DecryptionFailure,
+    MalformedPayload,
+    InvalidKeyLength,
+    InvalidPublicKey,
+    SrhHmacFailure,
+    SignatureFailure,
+    UnknownSigner,
+    HandshakeSignatureFailure,
+    UnknownPeer,
+    UnknownSession,
+    SessionExpired,
+    SessionLimit,
+    InvalidSequence,
+    Replay,
+    StaleSequence,
+    StaleNotification,
+    UnauthenticatedRekey,
+    PathTooLong,
+    PathLengthMismatch,
+    UnknownSid,
+    SidMismatch,
+    NotRelay,
+    InvalidNextHop,
+    NoRoute,
+    HopLimitExceeded,
+    InvalidSid,
+    NoPolicy,
+    InvalidPolicy,
+    InvalidPathList,
+    PathListExpired,
+    UnknownNode,
+    NoPath,
+    NoAlternativePath,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::ConfigRead => "CONFIG_READ",
+            ErrorCode::ConfigParse => "CONFIG_PARSE",
+            ErrorCode::ConfigWrite => "CONFIG_WRITE",
+            ErrorCode::ConfigInvalid => "CONFIG_INVALID",
+            ErrorCode::InitIdentityKey => "INIT_IDENTITY_KEY",
+            ErrorCode::InitKeyGeneration => "INIT_KEY_GENERATION",
+            ErrorCode::InitSrhHmacKey => "INIT_SRH_HMAC_KEY",
+            ErrorCode::InitRawSocket => "INIT_RAW_SOCKET",
+            ErrorCode::InitRawSocketUnsupported => "INIT_RAW_SOCKET_UNSUPPORTED",
+            ErrorCode::InitMissingIdentityKey => "INIT_MISSING_IDENTITY_KEY",
+            ErrorCode::Io => "COMM_IO",
+            ErrorCode::MalformedPacket => "COMM_MALFORMED_PACKET",
+            ErrorCode::MalformedSrh => "COMM_MALFORMED_SRH",
+            ErrorCode::UnknownMessageType => "COMM_UNKNOWN_MESSAGE_TYPE",
+            ErrorCode::UnexpectedMessage => "COMM_UNEXPECTED_MESSAGE",
+            ErrorCode::HandshakeTimeout => "COMM_HANDSHAKE_TIMEOUT",
+            ErrorCode::MessageTooLong => "COMM_MESSAGE_TOO_LONG",
+            ErrorCode::MacFailure => "CRYPTO_MAC_FAILURE",
+            ErrorCode::EncryptionFailure => "CRYPTO_ENCRYPTION_FAILURE",
+            ErrorCode::DecryptionFailure => "CRYPTO_DECRYPTION_FAILURE",
+            ErrorCode::MalformedPayload => "CRYPTO_MALFORMED_PAYLOAD",
+            ErrorCode::InvalidKeyLength => "CRYPTO_INVALID_KEY_LENGTH",
+            ErrorCode::InvalidPublicKey => "CRYPTO_INVALID_PUBLIC_KEY",
+            ErrorCode::SrhHmacFailure => "CRYPTO_SRH_HMAC_FAILURE",
+            ErrorCode::SignatureFailure => "CRYPTO_SIGNATURE_FAILURE",
+            ErrorCode::UnknownSigner => "CRYPTO_UNKNOWN_SIGNER",
+            ErrorCode::HandshakeSignatureFailure => "CRYPTO_HANDSHAKE_SIGNATURE_FAILURE",
+            ErrorCode::UnknownPeer => "CRYPTO_UNKNOWN_PEER",
+            ErrorCode::UnknownSession => "SESSION_UNKNOWN",
+            ErrorCode::SessionExpired => "SESSION_EXPIRED",
+            ErrorCode::SessionLimit => "SESSION_LIMIT",
+            ErrorCode::InvalidSequence => "SESSION_INVALID_SEQUENCE",
+            ErrorCode::Replay => "SESSION_REPLAY",
+            ErrorCode::StaleSequence => "SESSION_STALE_SEQUENCE",
+            ErrorCode::StaleNotification => "SESSION_STALE_NOTIFICATION",
+            ErrorCode::UnauthenticatedRekey => "SESSION_UNAUTHENTICATED_REKEY",
+            ErrorCode::PathTooLong => "PATH_TOO_LONG",
+            ErrorCode::PathLengthMismatch => "PATH_LENGTH_MISMATCH",
+            ErrorCode::UnknownSid => "PATH_UNKNOWN_SID",
+            ErrorCode::SidMismatch => "PATH_SID_MISMATCH",
+            ErrorCode::NotRelay => "PATH_NOT_RELAY",
+            ErrorCode::InvalidNextHop => "PATH_INVALID_NEXT_HOP",
+            ErrorCode::NoRoute => "PATH_NO_ROUTE",
+            ErrorCode::HopLimitExceeded => "PATH_HOP_LIMIT_EXCEEDED",
+            ErrorCode::InvalidSid => "PATH_INVALID_SID",
+            ErrorCode::NoPolicy => "PATH_NO_POLICY",
+            ErrorCode::InvalidPolicy => "PATH_INVALID_POLICY",
+            ErrorCode::InvalidPathList => "PATH_INVALID_PATH_LIST",
+            ErrorCode::PathListExpired => "PATH_LIST_EXPIRED",
+            ErrorCode::UnknownNode => "PATH_UNKNOWN_NODE",
+            ErrorCode::NoPath => "PATH_NOT_FOUND",
+            ErrorCode::NoAlternativePath => "PATH_NO_ALTERNATIVE",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Error {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::Config(e) => match e {
+                ConfigError::Read { .. } => ErrorCode::ConfigRead,
+                ConfigError::Parse(_) => ErrorCode::ConfigParse,
+                ConfigError::Write { .. } => ErrorCode::ConfigWrite,
+                ConfigError::Invalid(_) => ErrorCode::ConfigInvalid,
+            },
+            Error::Init(e) => match e {
+                InitError::IdentityKey { .. } => ErrorCode::InitIdentityKey,
+                InitError::KeyGeneration { .. } => ErrorCode::InitKeyGeneration,
+                InitError::SrhHmacKey { .. } => ErrorCode::InitSrhHmacKey,
+                InitError::RawSocket(_) => ErrorCode::InitRawSocket,
+                InitError::RawSocketUnsupported => ErrorCode::InitRawSocketUnsupported,
+                InitError::MissingIdentityKey => ErrorCode::InitMissingIdentityKey,
+            },
+            Error::Communication(e) => match e {
+                CommunicationError::Io(_) => ErrorCode::Io,
+                CommunicationError::MalformedPacket(_) => ErrorCode::MalformedPacket,
+                CommunicationError::MalformedSrh(_) => ErrorCode::MalformedSrh,
+                CommunicationError::UnknownMessageType(_) => ErrorCode::UnknownMessageType,
+                CommunicationError::UnexpectedMessage(_) => ErrorCode::UnexpectedMessage,
+                CommunicationError::HandshakeTimeout(_) => ErrorCode::HandshakeTimeout,
+                CommunicationError::MessageTooLong { .. } => ErrorCode::MessageTooLong,
+            },
+            Error::Crypto(e) => match e {
+                CryptoError::MacVerification { .. } => ErrorCode::MacFailure,
+                CryptoError::Encryption => ErrorCode::EncryptionFailure,
+                CryptoError::Decryption => ErrorCode::DecryptionFailure,
+                CryptoError::MalformedPayload => ErrorCode::MalformedPayload,
+                CryptoError::InvalidKeyLength => ErrorCode::InvalidKeyLength,
+                CryptoError::InvalidPublicKey => ErrorCode::InvalidPublicKey,
+                CryptoError::SrhHmac(_) => ErrorCode::SrhHmacFailure,
+                CryptoError::Signature(_) => ErrorCode::SignatureFailure,
+                CryptoError::UnknownSigner(_) => ErrorCode::UnknownSigner,
+                CryptoError::HandshakeSignature(_) => ErrorCode::HandshakeSignatureFailure,
+                CryptoError::UnknownPeer(_) => ErrorCode::UnknownPeer,
+            },
+            Error::Session(e) => match e {
+                SessionError::Unknown(_) => ErrorCode::UnknownSession,
+                SessionError::Expired(_) => ErrorCode::SessionExpired,
+                SessionError::LimitReached => ErrorCode::SessionLimit,
+                SessionError::InvalidSequence(_) => ErrorCode::InvalidSequence,
+                SessionError::DuplicateSequence(_) => ErrorCode::Replay,
+                SessionError::StaleSequence(_) => ErrorCode::StaleSequence,
+                SessionError::StaleNotification(_) => ErrorCode::StaleNotification,
+                SessionError::DuplicateNotification(_) => ErrorCode::Replay,
+                SessionError::UnauthenticatedRekey(_) => ErrorCode::UnauthenticatedRekey,
+            },
+            Error::Path(e) => match e {
+                PathError::TooManyHops { .. } => ErrorCode::PathTooLong,
+                PathError::LengthMismatch => ErrorCode::PathLengthMismatch,
+                PathError::UnknownSid(_) => ErrorCode::UnknownSid,
+                PathError::NotForThisNode(_) => ErrorCode::SidMismatch,
+                PathError::NotRelay => ErrorCode::NotRelay,
+                PathError::InvalidNextHop(_) => ErrorCode::InvalidNextHop,
+                PathError::NoRoute { .. } => ErrorCode::NoRoute,
+                PathError::HopLimitExceeded => ErrorCode::HopLimitExceeded,
+                PathError::InvalidSid(_) => ErrorCode::InvalidSid,
+                PathError::NoPolicy { .. } => ErrorCode::NoPolicy,
+                PathError::InvalidPolicy(_) => ErrorCode::InvalidPolicy,
+                PathError::InvalidPathList(_) => ErrorCode::InvalidPathList,
+                PathError::PathListExpired(_) => ErrorCode::PathListExpired,
+                PathError::UnknownNode(_) => ErrorCode::UnknownNode,
+                PathError::NoPath { .. } => ErrorCode::NoPath,
+                PathError::NoAlternativePath { .. } => ErrorCode::NoAlternativePath,
+            },
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Communication(CommunicationError::Io(error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    // ログや監視で参照されるため、コード文字列は変更しない
+    const CODES: &[(ErrorCode, &str)] = &[
+        (ErrorCode::ConfigRead, "CONFIG_READ"),
+        (ErrorCode::ConfigParse, "CONFIG_PARSE"),
+        (ErrorCode::ConfigWrite, "CONFIG_WRITE"),
+        (ErrorCode::ConfigInvalid, "CONFIG_INVALID"),
+        (ErrorCode::InitIdentityKey, "INIT_IDENTITY_KEY"),
+        (ErrorCode::InitKeyGeneration, "INIT_KEY_GENERATION"),
+        (ErrorCode::InitSrhHmacKey, "INIT_SRH_HMAC_KEY"),
+        (ErrorCode::InitRawSocket, "INIT_RAW_SOCKET"),
+        (ErrorCode::InitRawSocketUnsupported, "INIT_RAW_SOCKET_UNSUPPORTED"),
+        (ErrorCode::InitMissingIdentityKey, "INIT_MISSING_IDENTITY_KEY"),
+        (ErrorCode::Io, "COMM_IO"),
+        (ErrorCode::MalformedPacket, "COMM_MALFORMED_PACKET"),
+        (ErrorCode::MalformedSrh, "COMM_MALFORMED_SRH"),
+        (ErrorCode::UnknownMessageType, "COMM_UNKNOWN_MESSAGE_TYPE"),
+        (ErrorCode::UnexpectedMessage, "COMM_UNEXPECTED_MESSAGE"),
+        (ErrorCode::HandshakeTimeout, "COMM_HANDSHAKE_TIMEOUT"),
+        (ErrorCode::MessageTooLong, "COMM_MESSAGE_TOO_LONG"),
+        (ErrorCode::MacFailure, "CRYPTO_MAC_FAILURE"),
+        (ErrorCode::EncryptionFailure, "CRYPTO_ENCRYPTION_FAILURE"),
+        (ErrorCode::DecryptionFailure, "CRYPTO_DECRYPTION_FAILURE"),
+        (ErrorCode::MalformedPayload, "CRYPTO_MALFORMED_PAYLOAD"),
+        (ErrorCode::InvalidKeyLength, "CRYPTO_INVALID_KEY_LENGTH"),
+        (ErrorCode::InvalidPublicKey, "CRYPTO_INVALID_PUBLIC_KEY"),
+        (ErrorCode::SrhHmacFailure, "CRYPTO_SRH_HMAC_FAILURE"),
+        (ErrorCode::SignatureFailure, "CRYPTO_SIGNATURE_FAILURE"),
+        (ErrorCode::UnknownSigner, "CRYPTO_UNKNOWN_SIGNER"),
+        (ErrorCode::HandshakeSignatureFailure, "CRYPTO_HANDSHAKE_SIGNATURE_FAILURE"),
+        (ErrorCode::UnknownPeer, "CRYPTO_UNKNOWN_PEER"),
+        (ErrorCode::UnknownSession, "SESSION_UNKNOWN"),
+        (ErrorCode::SessionExpired, "SESSION_EXPIRED"),
+        (ErrorCode::SessionLimit, "SESSION_LIMIT"),
+        (ErrorCode::InvalidSequence, "SESSION_INVALID_SEQUENCE"),
+        (ErrorCode::Replay, "SESSION_REPLAY"),
+        (ErrorCode::StaleSequence, "SESSION_STALE_SEQUENCE"),
+        (ErrorCode::StaleNotification, "SESSION_STALE_NOTIFICATION"),
+        (ErrorCode::UnauthenticatedRekey, "SESSION_UNAUTHENTICATED_REKEY"),
+        (ErrorCode::PathTooLong, "PATH_TOO_LONG"),
+        (ErrorCode::PathLengthMismatch, "PATH_LENGTH_MISMATCH"),
+        (ErrorCode::UnknownSid, "PATH_UNKNOWN_SID"),
+        (ErrorCode::SidMismatch, "PATH_SID_MISMATCH"),
+        (ErrorCode::NotRelay, "PATH_NOT_RELAY"),
+        (ErrorCode::InvalidNextHop, "PATH_INVALID_NEXT_HOP"),
+        (ErrorCode::NoRoute, "PATH_NO_ROUTE"),
+        (ErrorCode::HopLimitExceeded, "PATH_HOP_LIMIT_EXCEEDED"),
+        (ErrorCode::InvalidSid, "PATH_INVALID_SID"),
+        (ErrorCode::NoPolicy, "PATH_NO_POLICY"),
+        (ErrorCode::InvalidPolicy, "PATH_INVALID_POLICY"),
+        (ErrorCode::InvalidPathList, "PATH_INVALID_PATH_LIST"),
+        (ErrorCode::PathListExpired, "PATH_LIST_EXPIRED"),
+        (ErrorCode::UnknownNode, "PATH_UNKNOWN_NODE"),
+        (ErrorCode::NoPath, "PATH_NOT_FOUND"),
+        (ErrorCode::NoAlternativePath, "PATH_NO_ALTERNATIVE"),
+    ];
+
+    #[test]
+    fn error_codes_are_stable() {
+        for (code, expected) in CODES {
+            assert_eq!(code.as_str(), *expected);
+            assert_eq!(code.to_string(), *expected);
+        }
+    }
+
+    #[test]
+    fn error_codes_are_unique() {
+        let codes: HashSet<&str> = CODES.iter().map(|(code, _)| code.as_str()).collect();
+        assert_eq!(codes.len(), CODES.len());
+    }
+
+    #[test]
+    fn errors_map_to_codes() {
+        let cases = [
+            (Error::from(CryptoError::MalformedPayload), ErrorCode::MalformedPayload),
+            (SessionError::DuplicateSequence(3).into(), ErrorCode::Replay),
+            (SessionError::DuplicateNotification(3).into(), ErrorCode::Replay),
+            (PathError::TooManyHops { hops: 7, max: 6 }.into(), ErrorCode::PathTooLong),
+            (io::Error::other("テスト").into(), ErrorCode::Io),
+        ];
+        for (error, code) in cases {
+            assert_eq!(error.code(), code);
+        }
+    }
+}
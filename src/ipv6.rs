@@ -0,0 +1,125 @@
+use std::net::{IpAddr, Ipv6Addr};
+
+use crate::error::{CommunicationError, Error};
+
+// 定数
+pub const IPV6_HEADER_SIZE: usize = 40;
+pub const IPPROTO_ROUTING: u8 = 43; // IPv6ルーティングヘッダー
+pub const ONION_NEXT_HEADER: u8 = 253; // Onionヘッダー（RFC 3692 実験用プロトコル番号）
+pub const DEFAULT_HOP_LIMIT: u8 = 64;
+
+// IPv6基本ヘッダー（README §3.1.1）
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ipv6Header {
+    pub traffic_class: u8,
+    pub flow_label: u32, // 下位20ビットのみ有効
+    pub payload_length: u16,
+    pub next_header: u8,
+    pub hop_limit: u8,
+    pub source: Ipv6Addr,
+    pub destination: Ipv6Addr,
+}
+
+impl Ipv6Header {
+    // SRHを次ヘッダーとするIPv6ヘッダーを作成
+    pub fn new(source: Ipv6Addr, destination: Ipv6Addr, payload_length: usize) -> Self {
+        Self {
+            traffic_class: 0,
+            flow_label: 0,
+            payload_length: payload_length as u16,
+            next_header: IPPROTO_ROUTING,
+            hop_limit: DEFAULT_HOP_LIMIT,
+            source,
+            destination,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(IPV6_HEADER_SIZE);
+        let first_word = (6u32 << 28) | ((self.traffic_class as u32) << 20) | (self.flow_label & 0x000f_ffff);
+        bytes.extend_from_slice(&first_word.to_be_bytes());
+        bytes.extend_from_slice(&self.payload_length.to_be_bytes());
+        bytes.push(self.next_header);
+        bytes.push(self.hop_limit);
+        bytes.extend_from_slice(&self.source.octets());
+        bytes.extend_from_slice(&self.destination.octets());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < IPV6_HEADER_SIZE {
+            return Err(CommunicationError::MalformedPacket("IPv6ヘッダーが短すぎます".into()).into());
+        }
+
+        let first_word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        if first_word >> 28 != 6 {
+            return Err(CommunicationError::MalformedPacket(format!("IPバージョンが不正です: {}", first_word >> 28)).into());
+        }
+
+        let mut source = [0u8; 16];
+        source.copy_from_slice(&bytes[8..24]);
+        let mut destination = [0u8; 16];
+        destination.copy_from_slice(&bytes[24..40]);
+
+        Ok(Self {
+            traffic_class: ((first_word >> 20) & 0xff) as u8,
+            flow_label: first_word & 0x000f_ffff,
+            payload_length: u16::from_be_bytes([bytes[4], bytes[5]]),
+            next_header: bytes[6],
+            hop_limit: bytes[7],
+            source: Ipv6Addr::from(source),
+            destination: Ipv6Addr::from(destination),
+        })
+    }
+}
+
+// IPv4アドレスはIPv4射影アドレスとして扱う
+pub fn to_ipv6(address: IpAddr) -> Ipv6Addr {
+    match address {
+        IpAddr::V6(address) => address,
+        IpAddr::V4(address) => address.to_ipv6_mapped(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Ipv6Header {
+        let mut header = Ipv6Header::new("2001:db8::1".parse().unwrap(), "2001:db8::2".parse().unwrap(), 1280);
+        header.traffic_class = 0xb8;
+        header.flow_label = 0xabcde;
+        header.hop_limit = 3;
+        header
+    }
+
+    #[test]
+    fn round_trip() {
+        let header = header();
+        let bytes = header.to_bytes();
+        assert_eq!(bytes.len(), IPV6_HEADER_SIZE);
+        assert_eq!(bytes[..4], [0x6b, 0x8a, 0xbc, 0xde]);
+        assert_eq!(Ipv6Header::from_bytes(&bytes).unwrap(), header);
+    }
+
+    #[test]
+    fn flow_label_is_truncated_to_20_bits() {
+        let mut header = header();
+        header.flow_label = 0xfff0_0001;
+        assert_eq!(Ipv6Header::from_bytes(&header.to_bytes()).unwrap().flow_label, 1);
+    }
+
+    #[test]
+    fn rejects_short_header_and_wrong_version() {
+        let mut bytes = header().to_bytes();
+        assert!(Ipv6Header::from_bytes(&bytes[..IPV6_HEADER_SIZE - 1]).is_err());
+        bytes[0] = 0x4b;
+        assert!(Ipv6Header::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn maps_ipv4_addresses() {
+        let address: IpAddr = "192.0.2.1".parse().unwrap();
+        assert_eq!(to_ipv6(address), "::ffff:192.0.2.1".parse::<Ipv6Addr>().unwrap());
+    }
+}
@@ -0,0 +1,24 @@
+// HORNET+ : SRv6 上のオニオンルーティング実装
+pub mod config;
+pub mod crypto;
+pub mod error;
+pub mod ipv6;
+pub mod message;
+pub mod node;
+pub mod onion;
+pub mod pathchange;
+pub mod pathlist;
+pub mod policy;
+pub mod ranking;
+pub mod raw;
+pub mod replay;
+pub mod reroute;
+pub mod sender;
+pub mod sid;
+pub mod srv6;
+pub mod topology;
+
+pub use crate::config::Config;
+pub use crate::error::Error;
+pub use crate::node::{Node, NodeStats, NodeType};
+pub use crate::sender::Sender;
@@ -0,0 +1,253 @@
+use std::time::Duration;
+
+use p384::{PublicKey, SecretKey};
+use p384::elliptic_curve::sec1::ToEncodedPoint;
+use rand::Rng;
+
+use crate::crypto::{
+    compute_control_mac, open_control, seal_control, sign_with_identity, verify_control_mac, verify_identity_signature,
+    NONCE_SIZE, SIGNATURE_SIZE,
+};
+use crate::error::{CommunicationError, CryptoError, Error};
+
+// 定数
+pub const HANDSHAKE_NONCE_SIZE: usize = 16;
+pub const HANDSHAKE_PUBLIC_KEY_SIZE: usize = 97; // SEC1非圧縮形式のP-384公開鍵
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const RESPONSE_SIGNATURE_CONTEXT: &[u8] = b"HORNET+ HandshakeResponse";
+const REKEY_MAC_CONTEXT: &[u8] = b"HORNET+ Rekey";
+
+// UDPデータグラムの先頭1バイトで示すメッセージ種別
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageType {
+    Data = 0,
+    HandshakeInit = 1,
+    HandshakeResponse = 2,
+    PathList = 3, // 送信者→最初の中継ノード: 暗号化したパスリスト
+    Probe = 4,      // 中継ノード→転送先: reachabilityチェック
+    ProbeReply = 5, // プローブへの応答（本文をそのまま返す）
+    PathChange = 6, // 経路変更した中継ノード→送信者・新しい経路のノード: 署名付き経路変更通知
+    AlternativeOnion = 7, // 送信者→最初の中継ノード: 暗号化した代替経路のOnion
+    ReversePathChange = 8, // 経路変更した中継ノード→前ホップ→…→送信者: ホップごとに暗号化した経路変更通知
+}
+
+impl MessageType {
+    pub fn from_u8(value: u8) -> Result<Self, Error> {
+        match value {
+            0 => Ok(MessageType::Data),
+            1 => Ok(MessageType::HandshakeInit),
+            2 => Ok(MessageType::HandshakeResponse),
+            3 => Ok(MessageType::PathList),
+            4 => Ok(MessageType::Probe),
+            5 => Ok(MessageType::ProbeReply),
+            6 => Ok(MessageType::PathChange),
+            7 => Ok(MessageType::AlternativeOnion),
+            8 => Ok(MessageType::ReversePathChange),
+            _ => Err(CommunicationError::UnknownMessageType(value).into()),
+        }
+    }
+
+    // メッセージ種別を先頭に付加したデータグラムを構築
+    pub fn frame(self, body: &[u8]) -> Vec<u8> {
+        let mut datagram = Vec::with_capacity(1 + body.len());
+        datagram.push(self as u8);
+        datagram.extend_from_slice(body);
+        datagram
+    }
+}
+
+// ハンドシェイクメッセージ（README §3.2）
+// 送信者→ノード: {NonceS, g^a}、ノード→送信者: {NonceN, g^b, 署名}
+// 応答はノードの長期識別鍵で署名し、送信者は登録済みの公開鍵で検証する（一時公開鍵の差し替えを防ぐ）。
+// 確立済みセッションの鍵更新では、開始メッセージに現在のMAC鍵によるMACを付ける
+pub struct HandshakeMessage {
+    pub session_id: u32,
+    pub nonce: [u8; HANDSHAKE_NONCE_SIZE],
+    pub public_key: Vec<u8>, // SEC1非圧縮形式のP-384一時公開鍵
+    pub authenticator: Vec<u8>, // 応答: 識別鍵の署名、鍵更新の開始メッセージ: MAC、それ以外は空
+}
+
+impl HandshakeMessage {
+    pub fn new(session_id: u32, public_key: &PublicKey) -> Self {
+        let mut nonce = [0u8; HANDSHAKE_NONCE_SIZE];
+        rand::thread_rng().fill(&mut nonce);
+        Self {
+            session_id,
+            nonce,
+            public_key: public_key.to_encoded_point(false).as_bytes().to_vec(),
+            authenticator: Vec::new(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.unauthenticated_bytes();
+        bytes.extend_from_slice(&self.authenticator);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 4 + HANDSHAKE_NONCE_SIZE + HANDSHAKE_PUBLIC_KEY_SIZE {
+            return Err(CommunicationError::MalformedPacket("ハンドシェイクメッセージが短すぎます".into()).into());
+        }
+
+        let session_id = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+        let mut nonce = [0u8; HANDSHAKE_NONCE_SIZE];
+        nonce.copy_from_slice(&bytes[4..4 + HANDSHAKE_NONCE_SIZE]);
+
+        let (public_key, authenticator) = bytes[4 + HANDSHAKE_NONCE_SIZE..].split_at(HANDSHAKE_PUBLIC_KEY_SIZE);
+
+        Ok(Self {
+            session_id,
+            nonce,
+            public_key: public_key.to_vec(),
+            authenticator: authenticator.to_vec(),
+        })
+    }
+
+    // SessionID | ノンス | 一時公開鍵
+    fn unauthenticated_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 + HANDSHAKE_NONCE_SIZE + self.public_key.len() + self.authenticator.len());
+        bytes.extend_from_slice(&self.session_id.to_be_bytes());
+        bytes.extend_from_slice(&self.nonce);
+        bytes.extend_from_slice(&self.public_key);
+        bytes
+    }
+
+    // 応答の署名対象: コンテキスト || 開始メッセージ || 応答（いずれも署名・MACを除く）
+    fn response_transcript(&self, init: &HandshakeMessage) -> Vec<u8> {
+        let mut transcript = RESPONSE_SIGNATURE_CONTEXT.to_vec();
+        transcript.extend_from_slice(&init.unauthenticated_bytes());
+        transcript.extend_from_slice(&self.unauthenticated_bytes());
+        transcript
+    }
+
+    // 開始メッセージと組にして応答に署名する
+    pub fn sign_response(&mut self, init: &HandshakeMessage, key: &SecretKey) {
+        self.authenticator = sign_with_identity(key, &self.response_transcript(init)).to_vec();
+    }
+
+    pub fn verify_response(&self, init: &HandshakeMessage, key: &PublicKey) -> bool {
+        let Ok(signature) = <&[u8; SIGNATURE_SIZE]>::try_from(self.authenticator.as_slice()) else {
+            return false;
+        };
+        self.session_id == init.session_id && verify_identity_signature(key, &self.response_transcript(init), signature)
+    }
+
+    // 鍵更新の開始メッセージを現在のセッションのMAC鍵で認証する
+    pub fn authenticate_rekey(&mut self, mac_key: &[u8]) {
+        let mut data = REKEY_MAC_CONTEXT.to_vec();
+        data.extend_from_slice(&self.unauthenticated_bytes());
+        self.authenticator = compute_control_mac(mac_key, &data).to_vec();
+    }
+
+    pub fn verify_rekey(&self, mac_key: &[u8]) -> bool {
+        let mut data = REKEY_MAC_CONTEXT.to_vec();
+        data.extend_from_slice(&self.unauthenticated_bytes());
+        verify_control_mac(mac_key, &data, &self.authenticator)
+    }
+
+    pub fn peer_public_key(&self) -> Result<PublicKey, Error> {
+        let public_key = PublicKey::from_sec1_bytes(&self.public_key)
+            .map_err(|_| CryptoError::InvalidPublicKey)?;
+        Ok(public_key)
+    }
+}
+
+// セッション鍵で暗号化した制御メッセージ: SessionID | ノンス | 暗号文（認証タグ付き）
+pub struct SealedMessage {
+    pub session_id: u32,
+    pub nonce: [u8; NONCE_SIZE],
+    pub ciphertext: Vec<u8>,
+}
+
+impl SealedMessage {
+    pub fn seal(session_id: u32, key: &[u8], plaintext: &[u8]) -> Result<Self, Error> {
+        let (nonce, ciphertext) = seal_control(key, session_id, plaintext)?;
+        Ok(Self { session_id, nonce, ciphertext })
+    }
+
+    pub fn open(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
+        open_control(key, self.session_id, &self.nonce, &self.ciphertext)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 + NONCE_SIZE + self.ciphertext.len());
+        bytes.extend_from_slice(&self.session_id.to_be_bytes());
+        bytes.extend_from_slice(&self.nonce);
+        bytes.extend_from_slice(&self.ciphertext);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 4 + NONCE_SIZE {
+            return Err(CommunicationError::MalformedPacket("暗号化メッセージが短すぎます".into()).into());
+        }
+
+        let session_id = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+        let mut nonce = [0u8; NONCE_SIZE];
+        nonce.copy_from_slice(&bytes[4..4 + NONCE_SIZE]);
+
+        Ok(Self {
+            session_id,
+            nonce,
+            ciphertext: bytes[4 + NONCE_SIZE..].to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p384::ecdh::EphemeralSecret;
+    use rand::rngs::OsRng;
+
+    fn handshake(session_id: u32) -> HandshakeMessage {
+        HandshakeMessage::new(session_id, &EphemeralSecret::random(&mut OsRng).public_key())
+    }
+
+    #[test]
+    fn handshake_round_trip() {
+        let mut message = handshake(7);
+        message.authenticate_rekey(&[1u8; 32]);
+        let decoded = HandshakeMessage::from_bytes(&message.to_bytes()).unwrap();
+        assert_eq!(decoded.session_id, 7);
+        assert_eq!(decoded.nonce, message.nonce);
+        assert_eq!(decoded.public_key, message.public_key);
+        assert_eq!(decoded.authenticator, message.authenticator);
+        assert!(decoded.peer_public_key().is_ok());
+
+        let bytes = message.to_bytes();
+        let error = HandshakeMessage::from_bytes(&bytes[..4 + HANDSHAKE_NONCE_SIZE + HANDSHAKE_PUBLIC_KEY_SIZE - 1]).err().unwrap();
+        assert_eq!(error.code(), crate::error::ErrorCode::MalformedPacket);
+    }
+
+    #[test]
+    fn response_signature_binds_both_messages() {
+        let identity_key = SecretKey::random(&mut OsRng);
+        let init = handshake(7);
+        let mut response = handshake(7);
+        response.sign_response(&init, &identity_key);
+        assert!(response.verify_response(&init, &identity_key.public_key()));
+
+        assert!(!response.verify_response(&init, &SecretKey::random(&mut OsRng).public_key()));
+        assert!(!response.verify_response(&handshake(7), &identity_key.public_key()));
+        let mut replaced = HandshakeMessage::from_bytes(&response.to_bytes()).unwrap();
+        replaced.public_key = handshake(7).public_key;
+        assert!(!replaced.verify_response(&init, &identity_key.public_key()));
+        replaced.authenticator.clear();
+        assert!(!replaced.verify_response(&init, &identity_key.public_key()));
+    }
+
+    #[test]
+    fn rekey_mac_requires_current_mac_key() {
+        let mut init = handshake(7);
+        assert!(!init.verify_rekey(&[1u8; 32]));
+        init.authenticate_rekey(&[1u8; 32]);
+        assert!(init.verify_rekey(&[1u8; 32]));
+        assert!(!init.verify_rekey(&[2u8; 32]));
+        init.session_id = 8;
+        assert!(!init.verify_rekey(&[1u8; 32]));
+    }
+}
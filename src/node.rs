@@ -0,0 +1,1794 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use p384::{PublicKey, SecretKey};
+use p384::ecdh::EphemeralSecret;
+use rand::Rng;
+use rand::rngs::OsRng;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::time::{interval, timeout, Interval, MissedTickBehavior};
+use tracing::{debug, field, info, info_span, trace, warn, Instrument, Span};
+
+use crate::crypto::{
+    compute_mac, derive_keys, handshake_context, keystream, layer_nonce, payload_nonce, verify_mac,
+    xor_in_place, SessionKeys, SrhHmacKey, MAC_SIZE, NONCE_SIZE,
+};
+use crate::error::{CommunicationError, CryptoError, Error, InitError, PathError, SessionError};
+use crate::message::{HandshakeMessage, MessageType, SealedMessage, HANDSHAKE_TIMEOUT};
+use crate::ipv6::{to_ipv6, Ipv6Header, IPPROTO_ROUTING, IPV6_HEADER_SIZE, ONION_NEXT_HEADER};
+use crate::onion::{
+    onion_payload, open_payload, seal_payload, NextHop, OnionHeader, OnionLayer, HOP_SLOT_SIZE,
+    LAYER_STREAM_SIZE, MAX_HOPS, ROUTING_INFO_SIZE,
+};
+use crate::raw::RawSocket;
+use crate::replay::{ReplayWindow, DEFAULT_REPLAY_WINDOW_SIZE};
+use crate::pathchange::{PathChange, ReverseLayer, SignedPathChange, PATH_CHANGE_MAX_AGE};
+use crate::pathlist::{unix_time, PathList};
+use crate::policy::SRv6Policy;
+use crate::ranking::ScoreWeights;
+use crate::reroute::{
+    select_alternative_path, AlternativeOnion, AlternativeOnions, AlternativeRoute, Failure, Neighbors, RerouteReason,
+    DEFAULT_PROBE_INTERVAL,
+};
+use crate::sid::{SidStructure, UsidFormat};
+use crate::srv6::{SRv6Header, SidBehavior, UnknownSidPolicy, MAIN_TABLE};
+
+// ノードタイプ
+pub enum NodeType {
+    Sender,
+    Relay(Ipv6Addr), // ローカルSID
+    Receiver,
+}
+
+// ノードの処理統計
+#[derive(Default)]
+pub struct NodeStats {
+    mac_failures: AtomicU64, // Onion層のMAC検証に失敗して破棄したパケット数
+    srh_hmac_failures: AtomicU64, // SRH HMAC TLVの検証に失敗して破棄したパケット数
+    replays_rejected: AtomicU64, // リプレイとして破棄したパケット数
+}
+
+impl NodeStats {
+    pub fn mac_failures(&self) -> u64 {
+        self.mac_failures.load(Ordering::Relaxed)
+    }
+    
+    pub fn srh_hmac_failures(&self) -> u64 {
+        self.srh_hmac_failures.load(Ordering::Relaxed)
+    }
+    
+    pub fn replays_rejected(&self) -> u64 {
+        self.replays_rejected.load(Ordering::Relaxed)
+    }
+}
+
+// 受信者が復号したメッセージ
+#[derive(Debug)]
+pub struct DeliveredMessage {
+    pub session_id: u32,
+    pub message: Vec<u8>,
+}
+
+// 受信したデータグラム。データパケットはIPv6ヘッダーから始まる
+struct Datagram {
+    message_type: u8,
+    data: Vec<u8>,
+    src: SocketAddr,
+}
+
+// 経路表: (ロケータ, プレフィックス長) → 転送先
+type RouteTable = HashMap<(Ipv6Addr, u8), SocketAddr>;
+
+// ノード構造体
+pub struct Node {
+    node_type: NodeType,
+    address: SocketAddr,
+    session_keys: Mutex<HashMap<u32, Vec<u8>>>,
+    mac_keys: Mutex<HashMap<u32, Vec<u8>>>,
+    iv_bases: Mutex<HashMap<u32, [u8; NONCE_SIZE]>>,
+    session_established: Mutex<HashMap<u32, Instant>>,
+    max_sessions: usize,
+    key_lifetime: Option<Duration>, // セッション鍵の有効期間（鍵ローテーション間隔）
+    replay_windows: Mutex<HashMap<u32, ReplayWindow>>,
+    replay_window_size: usize,
+    send_sequences: Mutex<HashMap<u32, u64>>,
+    sent_path_keys: Mutex<HashMap<u32, Vec<Vec<u8>>>>, // 送信したセッションの経路上の中継ノードの暗号鍵（経路順。逆方向経路の経路変更通知を剥がす）
+    upstream_hops: Mutex<HashMap<u32, SocketAddr>>, // セッションのデータパケットの前ホップ（経路変更通知を逆方向経路で返す）
+    path_lists: Mutex<HashMap<u32, PathList>>, // 送信者から受け取ったセッションごとのパスリスト
+    rerouted_paths: Mutex<HashMap<u32, String>>, // 経路変更したセッションの現在のPathID
+    alternative_onions: Mutex<AlternativeOnions>, // 送信者が構築した代替経路のOnion（経路変更時に差し替える）
+    neighbors: Mutex<Neighbors>, // 転送先の到達性
+    probe_interval: Option<Duration>, // 転送先へのreachabilityチェックの間隔（UDP転送時のみ）
+    score_weights: ScoreWeights, // 代替経路の再評価に用いる重み
+    pending_path_changes: Mutex<Vec<(SignedPathChange, Vec<SocketAddr>)>>, // 送信待ちの経路変更通知と新しい経路の中継ノード
+    seen_path_changes: Mutex<HashSet<(Ipv6Addr, u32, u64, String)>>, // 受け付けた経路変更通知（署名者SID, セッションID, 時刻, 新しいPathID）
+    trusted_keys: Mutex<HashMap<Ipv6Addr, PublicKey>>, // 経路変更通知の検証に用いる中継ノードの識別公開鍵（SID → 公開鍵）
+    peer_keys: Mutex<HashMap<SocketAddr, PublicKey>>, // ハンドシェイク応答の検証に用いるノードの識別公開鍵（アドレス → 公開鍵）
+    local_sids: Mutex<HashMap<Ipv6Addr, SidBehavior>>, // SIDテーブル（Argumentを除いたローカルSID → エンドポイント動作）
+    sid_structure: Option<SidStructure>, // 未設定時はSIDを分解せず完全一致で扱う
+    usid_format: Option<UsidFormat>, // 設定時はuSIDキャリア（NEXT-C-SID）を送受信する
+    unknown_sid_policy: UnknownSidPolicy,
+    route_tables: Mutex<HashMap<u32, RouteTable>>, // テーブルIDごとの経路表
+    identity_key: Option<SecretKey>, // ノードの長期識別鍵（P-384）
+    message_sink: Option<mpsc::UnboundedSender<DeliveredMessage>>,
+    raw_socket: Option<RawSocket>, // 生ソケットのデータプレーン（ハンドシェイクは常にUDP）
+    srh_hmac_key: Option<SrhHmacKey>, // SRH HMAC TLVのSRドメイン共通鍵
+    stats: NodeStats,
+}
+
+impl Node {
+    pub fn new(node_type: NodeType, address: SocketAddr) -> Self {
+        // 中継ノードのSIDはEnd動作としてSIDテーブルに登録する
+        let mut local_sids = HashMap::new();
+        if let NodeType::Relay(sid) = &node_type {
+            local_sids.insert(*sid, SidBehavior::End);
+        }
+        
+        Self {
+            node_type,
+            address,
+            session_keys: Mutex::new(HashMap::new()),
+            mac_keys: Mutex::new(HashMap::new()),
+            iv_bases: Mutex::new(HashMap::new()),
+            session_established: Mutex::new(HashMap::new()),
+            max_sessions: usize::MAX,
+            key_lifetime: None,
+            replay_windows: Mutex::new(HashMap::new()),
+            replay_window_size: DEFAULT_REPLAY_WINDOW_SIZE,
+            send_sequences: Mutex::new(HashMap::new()),
+            sent_path_keys: Mutex::new(HashMap::new()),
+            upstream_hops: Mutex::new(HashMap::new()),
+            path_lists: Mutex::new(HashMap::new()),
+            rerouted_paths: Mutex::new(HashMap::new()),
+            alternative_onions: Mutex::new(AlternativeOnions::default()),
+            neighbors: Mutex::new(Neighbors::default()),
+            probe_interval: Some(DEFAULT_PROBE_INTERVAL),
+            score_weights: ScoreWeights::default(),
+            pending_path_changes: Mutex::new(Vec::new()),
+            seen_path_changes: Mutex::new(HashSet::new()),
+            trusted_keys: Mutex::new(HashMap::new()),
+            peer_keys: Mutex::new(HashMap::new()),
+            local_sids: Mutex::new(local_sids),
+            sid_structure: None,
+            usid_format: None,
+            unknown_sid_policy: UnknownSidPolicy::default(),
+            route_tables: Mutex::new(HashMap::new()),
+            identity_key: None,
+            message_sink: None,
+            raw_socket: None,
+            srh_hmac_key: None,
+            stats: NodeStats::default(),
+        }
+    }
+    
+    pub fn stats(&self) -> &NodeStats {
+        &self.stats
+    }
+    
+    // リプレイ検出ウィンドウのサイズ（パケット数）を設定
+    pub fn with_replay_window_size(mut self, size: usize) -> Self {
+        self.replay_window_size = size;
+        self
+    }
+    
+    // 同時に保持するセッション数の上限を設定
+    pub fn with_max_sessions(mut self, max_sessions: usize) -> Self {
+        self.max_sessions = max_sessions;
+        self
+    }
+    
+    // セッション鍵の有効期間を設定。期限切れのセッションは再ハンドシェイクが必要
+    pub fn with_key_lifetime(mut self, lifetime: Duration) -> Self {
+        self.key_lifetime = Some(lifetime);
+        self
+    }
+    
+    // 長期識別鍵を設定
+    pub fn with_identity_key(mut self, key: SecretKey) -> Self {
+        self.identity_key = Some(key);
+        self
+    }
+    
+    // 受信者が復号したメッセージの引き渡し先を設定
+    pub fn with_message_sink(mut self, sink: mpsc::UnboundedSender<DeliveredMessage>) -> Self {
+        self.message_sink = Some(sink);
+        self
+    }
+    
+    // データパケットの送受信に生ソケットを使用する
+    pub fn with_raw_socket(mut self, raw_socket: RawSocket) -> Self {
+        self.raw_socket = Some(raw_socket);
+        self
+    }
+    
+    // SIDをLocator:Function:Argumentに分解して扱う。SIDテーブルはArgumentを無視して照合する
+    pub fn with_sid_structure(mut self, structure: SidStructure) -> Self {
+        let local_sids = std::mem::take(self.local_sids.get_mut().unwrap());
+        *self.local_sids.get_mut().unwrap() = local_sids.into_iter()
+            .map(|(sid, behavior)| (structure.without_argument(&sid), behavior))
+            .collect();
+        self.sid_structure = Some(structure);
+        self
+    }
+    
+    // 圧縮SID（uSIDキャリア）を有効にする。送信時は経路をキャリアに詰め、
+    // 中継時はSIDテーブルにLocator-Block | uSID の形で登録したSIDをキャリアのアクティブuSIDと照合する
+    pub fn with_usid_format(mut self, format: UsidFormat) -> Self {
+        self.usid_format = Some(format);
+        self
+    }
+    
+    // SIDテーブルにないSID宛のパケットの扱いを設定
+    pub fn with_unknown_sid_policy(mut self, policy: UnknownSidPolicy) -> Self {
+        self.unknown_sid_policy = policy;
+        self
+    }
+    
+    // 転送先へのreachabilityチェックの間隔を設定。Noneなら行わない
+    pub fn with_probe_interval(mut self, probe_interval: Option<Duration>) -> Self {
+        self.probe_interval = probe_interval;
+        self
+    }
+    
+    // 代替経路を再評価する際の重みを設定
+    pub fn with_score_weights(mut self, weights: ScoreWeights) -> Self {
+        self.score_weights = weights;
+        self
+    }
+    
+    // SRH HMAC TLVによるセグメントリストの認証を有効にする。SRドメイン内の全ノードで同じ鍵を設定する
+    pub fn with_srh_hmac_key(mut self, key: SrhHmacKey) -> Self {
+        self.srh_hmac_key = Some(key);
+        self
+    }
+    
+    pub fn identity_public_key(&self) -> Option<PublicKey> {
+        self.identity_key.as_ref().map(|key| key.public_key())
+    }
+    
+    pub fn set_session_key(&self, session_id: u32, key: Vec<u8>) {
+        let mut keys = self.session_keys.lock().unwrap();
+        keys.insert(session_id, key);
+    }
+    
+    pub fn set_mac_key(&self, session_id: u32, key: Vec<u8>) {
+        let mut keys = self.mac_keys.lock().unwrap();
+        keys.insert(session_id, key);
+    }
+    
+    pub fn set_iv_base(&self, session_id: u32, iv_base: [u8; NONCE_SIZE]) {
+        let mut iv_bases = self.iv_bases.lock().unwrap();
+        iv_bases.insert(session_id, iv_base);
+    }
+    
+    // SIDから転送先アドレスへの経路を登録
+    pub fn add_sid_route(&self, sid: Ipv6Addr, address: SocketAddr) {
+        self.add_locator_route(sid, 128, address);
+    }
+    
+    // ロケータ（プレフィックス）から転送先アドレスへの経路をメインテーブルに登録
+    pub fn add_locator_route(&self, locator: Ipv6Addr, prefix_len: u8, address: SocketAddr) {
+        self.add_table_route(MAIN_TABLE, locator, prefix_len, address);
+    }
+    
+    // 指定テーブルに経路を登録（End.T / End.DT6 で使用）
+    pub fn add_table_route(&self, table: u32, locator: Ipv6Addr, prefix_len: u8, address: SocketAddr) {
+        let prefix_len = prefix_len.min(128);
+        let mut tables = self.route_tables.lock().unwrap();
+        tables.entry(table)
+            .or_default()
+            .insert((mask_prefix(locator, prefix_len), prefix_len), address);
+        self.neighbors.lock().unwrap().add(address);
+    }
+    
+    // 明示的なノード障害通知を反映する。次にプローブへ応答するまで、このノードへの転送は代替経路に切り替える
+    pub fn mark_neighbor_failed(&self, address: SocketAddr) {
+        warn!(neighbor = %address, "ノード障害通知を受信");
+        self.neighbors.lock().unwrap().mark_failed(address);
+    }
+    
+    // 転送先を障害とみなしている場合はその理由
+    pub fn neighbor_failure(&self, address: &SocketAddr) -> Option<RerouteReason> {
+        self.neighbors.lock().unwrap().failure(address)
+    }
+    
+    // 経路変更通知を検証するため、中継ノードの識別公開鍵をSIDと対応づけて登録
+    pub fn add_trusted_key(&self, sid: Ipv6Addr, key: PublicKey) {
+        self.trusted_keys.lock().unwrap().insert(sid, key);
+    }
+    
+    pub fn trusted_key(&self, sid: &Ipv6Addr) -> Option<PublicKey> {
+        self.trusted_keys.lock().unwrap().get(sid).copied()
+    }
+    
+    // ハンドシェイク応答の署名を検証するため、ノードの識別公開鍵をアドレスと対応づけて登録
+    pub fn add_peer_key(&self, address: SocketAddr, key: PublicKey) {
+        self.peer_keys.lock().unwrap().insert(address, key);
+    }
+    
+    pub fn peer_key(&self, address: &SocketAddr) -> Option<PublicKey> {
+        self.peer_keys.lock().unwrap().get(address).copied()
+    }
+    
+    // 経路変更したセッションの現在のPathID
+    pub fn rerouted_path(&self, session_id: u32) -> Option<String> {
+        self.rerouted_paths.lock().unwrap().get(&session_id).cloned()
+    }
+    
+    // SIDを最長一致でメインテーブルから引き、転送先アドレスを返す
+    pub fn resolve_sid(&self, sid: &Ipv6Addr) -> Result<SocketAddr, Error> {
+        self.find_route(MAIN_TABLE, sid)
+            .ok_or(PathError::UnknownSid(*sid).into())
+    }
+    
+    // 宛先を最長一致で指定テーブルから引き、転送先アドレスを返す
+    pub fn lookup_route(&self, table: u32, destination: &Ipv6Addr) -> Result<SocketAddr, Error> {
+        self.find_route(table, destination)
+            .ok_or(PathError::NoRoute { table, destination: *destination }.into())
+    }
+    
+    fn find_route(&self, table: u32, destination: &Ipv6Addr) -> Option<SocketAddr> {
+        let tables = self.route_tables.lock().unwrap();
+        tables.get(&table)?
+            .iter()
+            .filter(|((locator, prefix_len), _)| mask_prefix(*destination, *prefix_len) == *locator)
+            .max_by_key(|((_, prefix_len), _)| *prefix_len)
+            .map(|(_, address)| *address)
+    }
+    
+    // 宛先の転送先を決める。生ソケット時はカーネルの経路表で転送されるため、テーブルは参照せず宛先をそのまま返す
+    fn route_next_hop(&self, table: u32, destination: &Ipv6Addr) -> Result<SocketAddr, Error> {
+        if self.raw_socket.is_some() {
+            return Ok(SocketAddr::new((*destination).into(), 0));
+        }
+        if table == MAIN_TABLE {
+            self.resolve_sid(destination)
+        } else {
+            self.lookup_route(table, destination)
+        }
+    }
+    
+    // ローカルSIDとエンドポイント動作をSIDテーブルに登録
+    pub fn add_local_sid(&self, sid: Ipv6Addr, behavior: SidBehavior) {
+        if let SidBehavior::EndX(adjacency) = behavior {
+            self.neighbors.lock().unwrap().add(adjacency);
+        }
+        let mut local_sids = self.local_sids.lock().unwrap();
+        local_sids.insert(self.sid_key(&sid), behavior);
+    }
+    
+    pub fn local_sid_behavior(&self, sid: &Ipv6Addr) -> Option<SidBehavior> {
+        let local_sids = self.local_sids.lock().unwrap();
+        local_sids.get(&self.sid_key(sid)).copied()
+    }
+    
+    // 宛先をSIDテーブルで引く。完全一致しなければ、uSIDキャリアとしてアクティブuSIDで引き直す。
+    // キャリアとして一致した場合はuSID形式を併せて返す
+    fn match_local_sid(&self, destination: &Ipv6Addr) -> Option<(SidBehavior, Option<UsidFormat>)> {
+        if let Some(behavior) = self.local_sid_behavior(destination) {
+            return Some((behavior, None));
+        }
+        let format = self.usid_format?;
+        self.local_sid_behavior(&format.node_sid(destination))
+            .map(|behavior| (behavior, Some(format)))
+    }
+    
+    // SIDのArgument部（SID構造が未設定なら常に0）
+    pub fn sid_argument(&self, sid: &Ipv6Addr) -> u64 {
+        self.sid_structure.map_or(0, |structure| structure.argument(sid))
+    }
+    
+    // SIDのロケータがこのノードのローカルSIDのいずれかと一致するか
+    pub fn owns_locator(&self, sid: &Ipv6Addr) -> bool {
+        let Some(structure) = self.sid_structure else {
+            return false;
+        };
+        let local_sids = self.local_sids.lock().unwrap();
+        local_sids.keys().any(|local_sid| structure.same_locator(local_sid, sid))
+    }
+    
+    fn sid_key(&self, sid: &Ipv6Addr) -> Ipv6Addr {
+        self.sid_structure.map_or(*sid, |structure| structure.without_argument(sid))
+    }
+    
+    pub fn install_session_keys(&self, session_id: u32, keys: SessionKeys) {
+        self.set_session_key(session_id, keys.encryption_key);
+        self.set_mac_key(session_id, keys.mac_key);
+        self.set_iv_base(session_id, keys.iv_base);
+        let mut established = self.session_established.lock().unwrap();
+        established.insert(session_id, Instant::now());
+    }
+    
+    // セッション鍵の有効期限を確認
+    pub fn check_key_lifetime(&self, session_id: u32) -> Result<(), Error> {
+        let Some(lifetime) = self.key_lifetime else {
+            return Ok(());
+        };
+        let established = self.session_established.lock().unwrap();
+        match established.get(&session_id) {
+            Some(at) if at.elapsed() > lifetime => Err(SessionError::Expired(session_id).into()),
+            _ => Ok(()),
+        }
+    }
+    
+    // セッション鍵とIVBaseを取得
+    pub fn decryption_params(&self, session_id: u32) -> Result<(Vec<u8>, [u8; NONCE_SIZE]), Error> {
+        self.check_key_lifetime(session_id)?;
+        
+        let session_key = {
+            let keys = self.session_keys.lock().unwrap();
+            keys.get(&session_id)
+                .ok_or(SessionError::Unknown(session_id))?
+                .clone()
+        };
+        
+        let iv_base = {
+            let iv_bases = self.iv_bases.lock().unwrap();
+            *iv_bases.get(&session_id)
+                .ok_or(SessionError::Unknown(session_id))?
+        };
+        
+        Ok((session_key, iv_base))
+    }
+    
+    // SRH HMAC鍵が設定されていれば、受信したSRHのHMAC TLVを検証する
+    pub fn verify_srh_hmac(&self, ipv6_header: &Ipv6Header, srv6_header: &SRv6Header) -> Result<(), Error> {
+        let Some(key) = &self.srh_hmac_key else {
+            return Ok(());
+        };
+        srv6_header.verify_hmac(key, &ipv6_header.source).inspect_err(|_| {
+            self.stats.srh_hmac_failures.fetch_add(1, Ordering::Relaxed);
+        })
+    }
+    
+    // SRH HMAC鍵が設定されていれば、送信元アドレスに合わせてHMAC TLVを付与し直す
+    fn sign_srh(&self, ipv6_header: &Ipv6Header, srv6_header: &mut SRv6Header) -> Result<(), Error> {
+        match &self.srh_hmac_key {
+            Some(key) => srv6_header.set_hmac(key, &ipv6_header.source),
+            None => Ok(()),
+        }
+    }
+    
+    // 受信したOnion層のMACを定数時間で検証し、失敗時は統計に計上する
+    pub fn verify_layer_mac(&self, header: &OnionHeader, payload: &[u8]) -> Result<(), Error> {
+        let mac_key = {
+            let keys = self.mac_keys.lock().unwrap();
+            keys.get(&header.session_id)
+                .ok_or(SessionError::Unknown(header.session_id))?
+                .clone()
+        };
+        
+        if !verify_mac(&mac_key, &header.routing_info, payload, header.session_id, header.sequence, &header.mac) {
+            self.stats.mac_failures.fetch_add(1, Ordering::Relaxed);
+            return Err(CryptoError::MacVerification { session_id: header.session_id }.into());
+        }
+        
+        Ok(())
+    }
+    
+    // 復号前にシーケンス番号がリプレイでないかを確認する
+    pub fn check_replay(&self, header: &OnionHeader) -> Result<(), Error> {
+        let windows = self.replay_windows.lock().unwrap();
+        let result = match windows.get(&header.session_id) {
+            Some(window) => window.check(header.sequence),
+            None => ReplayWindow::new(self.replay_window_size).check(header.sequence),
+        };
+        
+        if result.is_err() {
+            self.stats.replays_rejected.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+    
+    // 認証に成功したパケットのシーケンス番号を記録する
+    pub fn commit_replay(&self, header: &OnionHeader) -> Result<(), Error> {
+        let mut windows = self.replay_windows.lock().unwrap();
+        let result = windows.entry(header.session_id)
+            .or_insert_with(|| ReplayWindow::new(self.replay_window_size))
+            .update(header.sequence);
+        
+        if result.is_err() {
+            self.stats.replays_rejected.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+    
+    // 送信者から受け取った暗号化パスリストを復号し、セッションに紐づけて保存する
+    pub fn receive_path_list(&self, data: &[u8]) -> Result<(), Error> {
+        let sealed = SealedMessage::from_bytes(data)?;
+        let (session_key, _) = self.decryption_params(sealed.session_id)?;
+        let path_list = PathList::from_bytes(&sealed.open(&session_key)?)?;
+        if path_list.session_id != sealed.session_id {
+            return Err(PathError::InvalidPathList("暗号化したセッションとパスリストのセッションIDが一致しません".into()).into());
+        }
+        if path_list.is_expired() {
+            return Err(PathError::PathListExpired(path_list.session_id).into());
+        }
+        
+        info!(session_id = path_list.session_id, paths = path_list.paths.len(), expiry = path_list.expiry, "パスリストを受信");
+        let mut path_lists = self.path_lists.lock().unwrap();
+        path_lists.retain(|_, stored| !stored.is_expired());
+        path_lists.insert(path_list.session_id, path_list);
+        self.alternative_onions.lock().unwrap().retain_sessions(|session_id| path_lists.contains_key(session_id));
+        Ok(())
+    }
+    
+    // 送信者から受け取った代替経路のOnionを復号し、経路変更に備えて保持する。
+    // パスリストを受け取っていないセッションでは経路を切り替えないため受け付けない
+    pub fn receive_alternative_onion(&self, data: &[u8]) -> Result<(), Error> {
+        let sealed = SealedMessage::from_bytes(data)?;
+        let (session_key, _) = self.decryption_params(sealed.session_id)?;
+        let onion = AlternativeOnion::from_bytes(&sealed.open(&session_key)?)?;
+        let header = OnionHeader::from_bytes(&onion.onion_header)?;
+        if header.session_id != sealed.session_id {
+            return Err(PathError::InvalidPathList("暗号化したセッションと代替経路のOnionのセッションIDが一致しません".into()).into());
+        }
+        if self.path_list(sealed.session_id).is_none() {
+            return Err(PathError::InvalidPathList("パスリストのないセッションの代替経路のOnionです".into()).into());
+        }
+        
+        trace!(session_id = header.session_id, seq = header.sequence, path = %onion.path_id, "代替経路のOnionを受信");
+        self.alternative_onions.lock().unwrap().insert(header.session_id, header.sequence, onion);
+        Ok(())
+    }
+    
+    // セッションのパスリスト。有効期限を過ぎたものは破棄してNoneを返す
+    pub fn path_list(&self, session_id: u32) -> Option<PathList> {
+        let mut path_lists = self.path_lists.lock().unwrap();
+        if path_lists.get(&session_id)?.is_expired() {
+            path_lists.remove(&session_id);
+            debug!(session_id, "パスリストの有効期限切れ");
+            return None;
+        }
+        path_lists.get(&session_id).cloned()
+    }
+    
+    // 送信用の次のシーケンス番号を払い出す
+    pub fn next_sequence(&self, session_id: u32) -> u64 {
+        let mut sequences = self.send_sequences.lock().unwrap();
+        let sequence = sequences.entry(session_id).or_insert(0);
+        *sequence += 1;
+        *sequence
+    }
+    
+    pub async fn run(&self, socket: Arc<UdpSocket>) -> Result<(), Error> {
+        let mut buf = vec![0u8; 65536];
+        
+        match &self.node_type {
+            NodeType::Sender => {
+                // 送信者の処理は別途実装
+                info!(address = %self.address, "送信者ノードを起動");
+            },
+            NodeType::Relay(local_sid) => {
+                info!(address = %self.address, sid = %local_sid, "中継ノードを起動");
+                if self.identity_key.is_none() {
+                    return Err(InitError::MissingIdentityKey.into());
+                }
+                // 生ソケット時の転送はカーネルの経路表に任せるため、到達性はUDP転送時のみ確認する
+                let mut probe_timer = self.probe_interval
+                    .filter(|_| self.raw_socket.is_none())
+                    .map(|period| {
+                        let mut timer = interval(period);
+                        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                        timer
+                    });
+                loop {
+                    let datagram = tokio::select! {
+                        datagram = self.recv_datagram(&socket, &mut buf) => datagram?,
+                        _ = next_tick(&mut probe_timer) => {
+                            self.probe_neighbors(&socket).await;
+                            continue;
+                        },
+                    };
+                    let span = packet_span(datagram.src, datagram.data.len());
+                    
+                    let result = async {
+                        trace!("パケット受信");
+                        match MessageType::from_u8(datagram.message_type)? {
+                            MessageType::Data => {
+                                let (processed_packet, next_hop) = self.process_relay_packet(&datagram.data, datagram.src).await?;
+                                self.send_data(&socket, &processed_packet, next_hop).await?;
+                                if self.raw_socket.is_none() {
+                                    self.neighbors.lock().unwrap().add(next_hop);
+                                }
+                                debug!(next_hop = %next_hop, bytes = processed_packet.len(), "パケット転送");
+                                self.send_path_changes(&socket).await;
+                                Ok(())
+                            },
+                            MessageType::HandshakeInit => {
+                                self.respond_handshake(&datagram.data, datagram.src, &socket).await
+                            },
+                            MessageType::PathList => self.receive_path_list(&datagram.data),
+                            MessageType::AlternativeOnion => self.receive_alternative_onion(&datagram.data),
+                            MessageType::Probe => self.reply_probe(&datagram.data, datagram.src, &socket).await,
+                            MessageType::PathChange => self.apply_path_change(&datagram.data),
+                            MessageType::ReversePathChange => self.forward_reverse_path_change(&datagram.data, &socket).await,
+                            MessageType::ProbeReply => {
+                                if self.neighbors.lock().unwrap().record_reply(datagram.src) {
+                                    info!(neighbor = %datagram.src, "転送先が応答を再開");
+                                }
+                                Ok(())
+                            },
+                            other => Err(CommunicationError::UnexpectedMessage(format!("{:?}", other)).into()),
+                        }
+                    }.instrument(span.clone()).await;
+                    
+                    if let Err(e) = result {
+                        span.in_scope(|| warn!(code = %e.code(), error = %e, "パケット処理エラー"));
+                    }
+                }
+            },
+            NodeType::Receiver => {
+                info!(address = %self.address, "受信ノードを起動");
+                if self.identity_key.is_none() {
+                    return Err(InitError::MissingIdentityKey.into());
+                }
+                loop {
+                    let datagram = self.recv_datagram(&socket, &mut buf).await?;
+                    let span = packet_span(datagram.src, datagram.data.len());
+                    
+                    let result = async {
+                        trace!("パケット受信");
+                        match MessageType::from_u8(datagram.message_type)? {
+                            MessageType::Data => {
+                                let delivered = self.process_receiver_packet(&datagram.data)?;
+                                self.deliver(delivered);
+                                Ok(())
+                            },
+                            MessageType::HandshakeInit => {
+                                self.respond_handshake(&datagram.data, datagram.src, &socket).await
+                            },
+                            MessageType::Probe => self.reply_probe(&datagram.data, datagram.src, &socket).await,
+                            MessageType::PathChange => self.apply_path_change(&datagram.data),
+                            other => Err(CommunicationError::UnexpectedMessage(format!("{:?}", other)).into()),
+                        }
+                    }.instrument(span.clone()).await;
+                    
+                    if let Err(e) = result {
+                        span.in_scope(|| warn!(code = %e.code(), error = %e, "パケット処理エラー"));
+                    }
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    // UDPと生ソケット（有効時）のどちらかからデータグラムを受信する
+    async fn recv_datagram(&self, socket: &UdpSocket, buf: &mut [u8]) -> Result<Datagram, Error> {
+        let raw_packet = async {
+            match &self.raw_socket {
+                Some(raw_socket) => raw_socket.recv_packet().await,
+                None => std::future::pending().await,
+            }
+        };
+        
+        tokio::select! {
+            received = socket.recv_from(buf) => {
+                let (len, src) = received?;
+                if len == 0 {
+                    return Err(CommunicationError::MalformedPacket("空のデータグラムです".into()).into());
+                }
+                Ok(Datagram { message_type: buf[0], data: buf[1..len].to_vec(), src })
+            },
+            received = raw_packet => {
+                // 生ソケットではIPv6ヘッダーが取り除かれて届くため、UDPで運ぶパケットと同じ形式に復元する。
+                // 宛先はIPV6_PKTINFOの値（取得できなければ自ノード）、Hop Limitは取得しないため既定値とする
+                let packet = received?;
+                let destination = packet.destination.unwrap_or_else(|| to_ipv6(self.address.ip()));
+                let mut header = Ipv6Header::new(packet.source, destination, packet.data.len());
+                header.next_header = packet.next_header;
+                let mut data = header.to_bytes();
+                data.extend_from_slice(&packet.data);
+                Ok(Datagram { message_type: MessageType::Data as u8, data, src: SocketAddr::new(packet.source.into(), 0) })
+            },
+        }
+    }
+    
+    // データパケットを次ホップへ送出する。生ソケット有効時は次ホップのIPアドレス宛に送り
+    // （通常はIPv6ヘッダーの宛先と同じで、End.Xでは隣接ノード）、それ以外はUDPで送る
+    async fn send_data(&self, socket: &UdpSocket, packet: &[u8], next_hop: SocketAddr) -> Result<(), Error> {
+        match &self.raw_socket {
+            Some(raw_socket) => {
+                raw_socket.send_packet(packet, to_ipv6(next_hop.ip())).await?;
+            },
+            None => {
+                socket.send_to(&MessageType::Data.frame(packet), next_hop).await?;
+            },
+        }
+        Ok(())
+    }
+    
+    // 転送先へreachabilityチェックを送る。前回のプローブに連続して応答がなければ障害とみなす（README §3.5.2）
+    async fn probe_neighbors(&self, socket: &UdpSocket) {
+        let targets = self.neighbors.lock().unwrap().probe_targets();
+        for target in targets {
+            if self.neighbor_failure(&target) == Some(RerouteReason::Unreachable) {
+                trace!(neighbor = %target, "転送先が応答しません");
+            }
+            let probe = rand::thread_rng().gen::<u64>().to_be_bytes();
+            if let Err(e) = socket.send_to(&MessageType::Probe.frame(&probe), target).await {
+                debug!(neighbor = %target, error = %e, "プローブを送信できません");
+            }
+        }
+    }
+    
+    async fn reply_probe(&self, data: &[u8], src: SocketAddr, socket: &UdpSocket) -> Result<(), Error> {
+        socket.send_to(&MessageType::ProbeReply.frame(data), src).await?;
+        Ok(())
+    }
+    
+    // 経路を切り替えたパケットの転送後に経路変更通知を送る。送信者へはセッション鍵で暗号化して
+    // データパケットの前ホップ（逆方向経路）に返すため、このノードが送信者のアドレスを知る必要はない。
+    // 新しい経路の中継ノードには署名付き通知をそのまま送る
+    async fn send_path_changes(&self, socket: &UdpSocket) {
+        let pending = std::mem::take(&mut *self.pending_path_changes.lock().unwrap());
+        for (notification, downstream) in pending {
+            let session_id = notification.change.session_id;
+            if let Err(e) = self.send_upstream(session_id, &ReverseLayer::Origin(notification.clone()), socket).await {
+                warn!(session_id, code = %e.code(), error = %e, "送信者へ経路変更通知を送信できません");
+            }
+            
+            let datagram = MessageType::PathChange.frame(&notification.to_bytes());
+            for target in downstream {
+                if let Err(e) = socket.send_to(&datagram, target).await {
+                    debug!(%target, error = %e, "経路変更通知を送信できません");
+                }
+            }
+            debug!(session_id, "経路変更通知を送信");
+        }
+    }
+    
+    // 後ろのホップから逆方向経路で届いた経路変更通知を自身のセッション鍵で包み、前ホップへ転送する
+    async fn forward_reverse_path_change(&self, data: &[u8], socket: &UdpSocket) -> Result<(), Error> {
+        let sealed = SealedMessage::from_bytes(data)?;
+        self.send_upstream(sealed.session_id, &ReverseLayer::Inner(data.to_vec()), socket).await?;
+        trace!(session_id = sealed.session_id, "経路変更通知を逆方向経路へ転送");
+        Ok(())
+    }
+    
+    // 逆方向経路の層をセッション鍵で暗号化し、セッションのデータパケットの前ホップへ送る
+    async fn send_upstream(&self, session_id: u32, layer: &ReverseLayer, socket: &UdpSocket) -> Result<(), Error> {
+        let (session_key, _) = self.decryption_params(session_id)?;
+        let upstream = self.upstream_hops.lock().unwrap()
+            .get(&session_id)
+            .copied()
+            .ok_or(SessionError::Unknown(session_id))?;
+        let sealed = SealedMessage::seal(session_id, &session_key, &layer.to_bytes())?;
+        socket.send_to(&MessageType::ReversePathChange.frame(&sealed.to_bytes()), upstream).await?;
+        Ok(())
+    }
+    
+    // 逆方向経路で届いた経路変更通知を、送信したセッションの経路順の鍵で外側から剥がす
+    pub fn open_reverse_path_change(&self, data: &[u8]) -> Result<PathChange, Error> {
+        let mut sealed = SealedMessage::from_bytes(data)?;
+        let session_id = sealed.session_id;
+        let keys = self.sent_path_keys.lock().unwrap()
+            .get(&session_id)
+            .cloned()
+            .ok_or(SessionError::Unknown(session_id))?;
+        for key in &keys {
+            match ReverseLayer::from_bytes(&sealed.open(key)?)? {
+                ReverseLayer::Inner(inner) => {
+                    sealed = SealedMessage::from_bytes(&inner)?;
+                    if sealed.session_id != session_id {
+                        return Err(CommunicationError::MalformedPacket("経路変更通知の層のセッションIDが一致しません".into()).into());
+                    }
+                },
+                ReverseLayer::Origin(notification) => {
+                    let change = self.verify_path_change(notification)?;
+                    if change.session_id != session_id {
+                        return Err(CommunicationError::MalformedPacket("経路変更通知のセッションIDが一致しません".into()).into());
+                    }
+                    return Ok(change);
+                },
+            }
+        }
+        Err(CommunicationError::MalformedPacket("経路変更通知の層が経路のホップ数を超えています".into()).into())
+    }
+    
+    // 経路変更通知の署名を署名者SIDに登録された公開鍵で検証する
+    pub fn receive_path_change(&self, data: &[u8]) -> Result<PathChange, Error> {
+        self.verify_path_change(SignedPathChange::from_bytes(data)?)
+    }
+    
+    // 新しい経路上のノードとして受け取った経路変更通知を検証し、セッションの現在の経路として記録する。
+    // 以後このノードが経路を切り替えたときは、記録した経路を切り替え前の経路として通知する
+    fn apply_path_change(&self, data: &[u8]) -> Result<(), Error> {
+        let change = self.receive_path_change(data)?;
+        if !self.session_keys.lock().unwrap().contains_key(&change.session_id) {
+            return Err(SessionError::Unknown(change.session_id).into());
+        }
+        self.rerouted_paths.lock().unwrap().insert(change.session_id, change.new_path_id);
+        Ok(())
+    }
+    
+    fn verify_path_change(&self, notification: SignedPathChange) -> Result<PathChange, Error> {
+        let key = self.trusted_keys.lock().unwrap()
+            .get(&notification.signer)
+            .copied()
+            .ok_or(CryptoError::UnknownSigner(notification.signer))?;
+        let change = notification.verify(&key)?;
+        // 署名済みの通知の再送は破棄する。時刻が許容範囲外になった記録は検証で弾かれるため忘れてよい
+        {
+            let mut seen = self.seen_path_changes.lock().unwrap();
+            let now = unix_time();
+            seen.retain(|(_, _, timestamp, _)| now.abs_diff(*timestamp) <= PATH_CHANGE_MAX_AGE.as_secs());
+            if !seen.insert((notification.signer, change.session_id, change.timestamp, change.new_path_id.clone())) {
+                return Err(SessionError::DuplicateNotification(change.session_id).into());
+            }
+        }
+        info!(session_id = change.session_id, signer = %notification.signer, reason = %change.reason,
+              old_path = %change.old_path_id, new_path = %change.new_path_id, "経路変更通知を受信");
+        Ok(notification.change)
+    }
+    
+    // 送信者が経路変更通知を待つ。自身が送信したセッションの検証済みの通知を受信順に返す
+    pub async fn collect_path_changes(&self, socket: &UdpSocket, wait: Duration) -> Vec<PathChange> {
+        let mut changes = Vec::new();
+        let mut buf = vec![0u8; 65536];
+        let deadline = Instant::now() + wait;
+        while let Ok(received) = timeout(deadline.saturating_duration_since(Instant::now()), socket.recv_from(&mut buf)).await {
+            let Ok((len, src)) = received else {
+                break;
+            };
+            if len == 0 || buf[0] != MessageType::ReversePathChange as u8 {
+                continue;
+            }
+            match self.open_reverse_path_change(&buf[1..len]) {
+                Ok(change) => changes.push(change),
+                Err(e) => warn!(%src, code = %e.code(), error = %e, "経路変更通知を破棄"),
+            }
+        }
+        changes
+    }
+    
+    // 復号したメッセージをアプリケーションへ引き渡す。平文はログに出力しない
+    fn deliver(&self, delivered: DeliveredMessage) {
+        debug!(bytes = delivered.message.len(), "メッセージ受信");
+        if let Some(sink) = &self.message_sink {
+            if sink.send(delivered).is_err() {
+                warn!("メッセージの受け取り先が閉じられています");
+            }
+        }
+    }
+    
+    // 送信者からのハンドシェイク開始メッセージに識別鍵で署名した応答を返し、セッション鍵を登録する。
+    // 確立済みセッションの鍵更新は、現在のMAC鍵で認証された開始メッセージだけを受け付ける
+    pub async fn respond_handshake(&self, data: &[u8], src: SocketAddr, socket: &UdpSocket) -> Result<(), Error> {
+        let identity_key = self.identity_key.as_ref().ok_or(InitError::MissingIdentityKey)?;
+        let init = HandshakeMessage::from_bytes(data)?;
+        let peer_public_key = init.peer_public_key()?;
+        
+        // 既存セッションの鍵更新は上限の対象外
+        match self.mac_keys.lock().unwrap().get(&init.session_id) {
+            Some(mac_key) if !init.verify_rekey(mac_key) => {
+                return Err(SessionError::UnauthenticatedRekey(init.session_id).into());
+            },
+            Some(_) => debug!(session_id = init.session_id, "セッション鍵を更新"),
+            None if self.session_keys.lock().unwrap().len() >= self.max_sessions => {
+                return Err(SessionError::LimitReached.into());
+            },
+            None => {},
+        }
+        
+        // 一時鍵ペアを生成してg^abを計算
+        let secret = EphemeralSecret::random(&mut OsRng);
+        let mut response = HandshakeMessage::new(init.session_id, &secret.public_key());
+        response.sign_response(&init, identity_key);
+        let shared_secret = secret.diffie_hellman(&peer_public_key);
+        
+        let context = handshake_context(&init.nonce, &response.nonce, init.session_id);
+        let keys = derive_keys(shared_secret.raw_secret_bytes(), &context);
+        self.install_session_keys(init.session_id, keys);
+        
+        socket.send_to(&MessageType::HandshakeResponse.frame(&response.to_bytes()), src).await?;
+        info!(session_id = init.session_id, peer = %src, "セッション確立");
+        
+        Ok(())
+    }
+    
+    // 指定ノードとECDH P-384ハンドシェイクを行い、導出したセッション鍵一式を返す。
+    // 応答の署名は add_peer_key で登録したノードの識別公開鍵で検証する
+    pub async fn establish_session(&self,
+                               session_id: u32,
+                               peer: SocketAddr,
+                               socket: &UdpSocket) -> Result<SessionKeys, Error> {
+        self.handshake(session_id, peer, None, socket).await
+    }
+    
+    // 確立済みセッションの鍵を更新する。開始メッセージは現在のセッション鍵で認証する
+    pub async fn rekey_session(&self,
+                           session_id: u32,
+                           peer: SocketAddr,
+                           current: &SessionKeys,
+                           socket: &UdpSocket) -> Result<SessionKeys, Error> {
+        self.handshake(session_id, peer, Some(current), socket).await
+    }
+    
+    async fn handshake(&self,
+                       session_id: u32,
+                       peer: SocketAddr,
+                       current: Option<&SessionKeys>,
+                       socket: &UdpSocket) -> Result<SessionKeys, Error> {
+        let peer_key = self.peer_key(&peer).ok_or(CryptoError::UnknownPeer(peer))?;
+        let secret = EphemeralSecret::random(&mut OsRng);
+        let mut init = HandshakeMessage::new(session_id, &secret.public_key());
+        if let Some(current) = current {
+            init.authenticate_rekey(&current.mac_key);
+        }
+        socket.send_to(&MessageType::HandshakeInit.frame(&init.to_bytes()), peer).await?;
+        
+        // 対応する応答を待つ
+        let mut buf = vec![0u8; 65536];
+        let response = timeout(HANDSHAKE_TIMEOUT, async {
+            loop {
+                let (len, src) = socket.recv_from(&mut buf).await?;
+                if src != peer || len == 0 || buf[0] != MessageType::HandshakeResponse as u8 {
+                    continue;
+                }
+                let response = HandshakeMessage::from_bytes(&buf[1..len])?;
+                if response.session_id == session_id {
+                    return Ok::<_, Error>(response);
+                }
+            }
+        })
+        .await
+        .map_err(|_| CommunicationError::HandshakeTimeout(peer))??;
+        if !response.verify_response(&init, &peer_key) {
+            return Err(CryptoError::HandshakeSignature(peer).into());
+        }
+        
+        let shared_secret = secret.diffie_hellman(&response.peer_public_key()?);
+        let context = handshake_context(&init.nonce, &response.nonce, session_id);
+        debug!(session_id, %peer, "ハンドシェイク完了");
+        
+        Ok(derive_keys(shared_secret.raw_secret_bytes(), &context))
+    }
+    
+    // パスリストを最初の中継ノードとのセッション鍵で暗号化して送る（ハンドシェイク後）
+    pub async fn send_path_list(&self,
+                            path_list: &PathList,
+                            keys: &SessionKeys,
+                            peer: SocketAddr,
+                            socket: &UdpSocket) -> Result<(), Error> {
+        path_list.validate()?;
+        let sealed = SealedMessage::seal(path_list.session_id, &keys.encryption_key, &path_list.to_bytes())?;
+        socket.send_to(&MessageType::PathList.frame(&sealed.to_bytes()), peer).await?;
+        debug!(session_id = path_list.session_id, %peer, paths = path_list.paths.len(), "パスリスト送信");
+        Ok(())
+    }
+    
+    pub async fn process_relay_packet(&self, packet: &[u8], previous_hop: SocketAddr) -> Result<(Vec<u8>, SocketAddr), Error> {
+        // IPv6ヘッダーとSRv6ヘッダーを解析
+        let (mut ipv6_header, mut srv6_header, onion_header_offset) = parse_ipv6_srh(packet)?;
+        
+        if !matches!(self.node_type, NodeType::Relay(_)) {
+            return Err(PathError::NotRelay.into());
+        }
+        
+        // アクティブSID（IPv6宛先）をSIDテーブルで引き、エンドポイント動作を決める。
+        // uSIDキャリアではシフト後の宛先がSRHのセグメントと異なるため、SRHではなく宛先を見る
+        let current_sid = ipv6_header.destination;
+        let Some((behavior, usid)) = self.match_local_sid(&current_sid) else {
+            return self.forward_unknown_sid(packet, ipv6_header, current_sid);
+        };
+        // キャリアのArgument位置には後続のuSIDが入っているため、Argumentとして扱わない
+        let argument = if usid.is_none() { self.sid_argument(&current_sid) } else { 0 };
+        if argument != 0 {
+            Span::current().record("sid_arg", argument);
+        }
+        
+        // セグメントリストそのものを認証（Onion層のMACとは独立）
+        self.verify_srh_hmac(&ipv6_header, &srv6_header)?;
+        
+        // RFC 8754のEnd動作と同じくHop Limitを減らし、尽きたパケットは破棄する（経路のループ対策）
+        if ipv6_header.hop_limit <= 1 {
+            return Err(PathError::HopLimitExceeded.into());
+        }
+        ipv6_header.hop_limit -= 1;
+        
+        // Onionヘッダーとペイロードを解析
+        let mut onion_header = OnionHeader::from_bytes(&packet[onion_header_offset..])?;
+        record_session(&onion_header);
+        let mut payload = onion_payload(packet, onion_header_offset)?;
+        
+        // セッション鍵とIVBaseを取得
+        let (session_key, iv_base) = self.decryption_params(onion_header.session_id)?;
+        
+        // 復号前にリプレイとMACを検証: MAC_i = HMAC(MACKey_i, Layer_i || SessionID || Seq)
+        self.check_replay(&onion_header)?;
+        self.verify_layer_mac(&onion_header, &payload)?;
+        self.commit_replay(&onion_header)?;
+        
+        // 経路変更通知を返す前ホップを記録する。生ソケットで届いたパケットは前ホップの
+        // 制御用UDPポートが分からないため記録しない
+        if previous_hop.port() != 0 {
+            self.upstream_hops.lock().unwrap().insert(onion_header.session_id, previous_hop);
+        }
+        
+        // このパケット用の代替経路のOnionは経路を切り替えるかどうかにかかわらずここで取り出す
+        let alternatives = self.alternative_onions.lock().unwrap().take(onion_header.session_id, onion_header.sequence);
+        
+        // IVBaseとシーケンス番号から自分の層の鍵ストリームを再計算し、
+        // ルーティング情報から自分のスロットを取り出してペイロードの層を1枚剥がす
+        let stream = keystream(&session_key, &layer_nonce(&iv_base, onion_header.sequence), LAYER_STREAM_SIZE)?;
+        let onion_layer = onion_header.peel(&stream[..ROUTING_INFO_SIZE + HOP_SLOT_SIZE])?;
+        xor_in_place(&mut payload, &stream[ROUTING_INFO_SIZE + HOP_SLOT_SIZE..]);
+        
+        // 次ホップ用のMACに差し替え
+        onion_header.set_mac(onion_layer.next_mac);
+        
+        // SRHとIPv6ヘッダーを更新し、エンドポイント動作に従って転送先を決定
+        // セグメントリストは経路制御のため平文のままだが、ホップごとに長さは変わらない
+        let mut decapsulate = false;
+        let next_hop = match &onion_layer.next_hop {
+            NextHop::Sid(sid) => {
+                // 宛先を次のSIDに更新する。Onionの次ホップ指示はSRHのステアリングと一致していなければならない
+                if let SidBehavior::EndDT6(_) = behavior {
+                    return Err(PathError::InvalidNextHop("End.DT6のSIDは最後のセグメントでのみ使用できます").into());
+                }
+                let active_sid = srv6_header.apply_end(&mut ipv6_header, usid.as_ref())?;
+                if *sid != active_sid {
+                    return Err(PathError::InvalidNextHop("Onionの次ホップSIDがSRHのアクティブSIDと一致しません").into());
+                }
+                let resolved = match behavior {
+                    SidBehavior::EndX(adjacency) => Ok(adjacency),
+                    SidBehavior::EndT(table) => self.route_next_hop(table, &active_sid),
+                    _ => self.route_next_hop(MAIN_TABLE, &active_sid),
+                };
+                // 障害ベースのトリガー（README §3.5.2）: 次ホップへの経路がないか、次ホップが障害なら代替経路に切り替える
+                match resolved {
+                    Ok(next_hop) => match self.neighbor_failure(&next_hop) {
+                        None => next_hop,
+                        Some(reason) => {
+                            let failure = Failure { sid: active_sid, address: Some(next_hop), reason };
+                            self.reroute(failure, &mut ipv6_header, &mut srv6_header, &mut onion_header, &mut payload, alternatives)?
+                        },
+                    },
+                    // パスリストがなければ経路がないことをそのまま報告する
+                    Err(e) => {
+                        let session_id = onion_header.session_id;
+                        let failure = Failure { sid: active_sid, address: None, reason: RerouteReason::NoRoute };
+                        self.reroute(failure, &mut ipv6_header, &mut srv6_header, &mut onion_header, &mut payload, alternatives)
+                            .map_err(|reroute_error| match self.path_list(session_id) {
+                                Some(_) => reroute_error,
+                                None => e,
+                            })?
+                    },
+                }
+            },
+            NextHop::Exit(addr) => {
+                // 出口ではSRv6セグメントを使い切っているはず。宛先は最終宛先に書き換える
+                if srv6_header.has_remaining(&ipv6_header.destination, usid.as_ref()) {
+                    return Err(PathError::InvalidNextHop("セグメントが残っている状態で出口指示を受信しました").into());
+                }
+                ipv6_header.destination = to_ipv6(addr.ip());
+                match behavior {
+                    // End.DT6: SRHを取り除き、最終宛先を指定テーブルで検索する
+                    SidBehavior::EndDT6(table) => {
+                        decapsulate = true;
+                        self.route_next_hop(table, &ipv6_header.destination)?
+                    },
+                    _ => *addr,
+                }
+            },
+            NextHop::Address(_) => {
+                return Err(PathError::InvalidNextHop("SRv6経路上の次ホップはSIDで指定する必要があります").into());
+            },
+            NextHop::DeliverLocal => {
+                return Err(PathError::InvalidNextHop("中継ノードで終端指示を受信しました").into());
+            },
+        };
+        
+        // 送信元は自ノードに書き換え、送信者のアドレスを後続ノードに露出しない
+        ipv6_header.source = to_ipv6(self.address.ip());
+        
+        // 新しいパケットを構築（SRHを取り除かない限り、経路変更時も受信時と同じサイズになる）
+        let mut body = Vec::with_capacity(packet.len());
+        if decapsulate {
+            ipv6_header.next_header = srv6_header.next_header;
+        } else {
+            // HMACは送信元アドレスを含むため、書き換えた送信元で付与し直す
+            self.sign_srh(&ipv6_header, &mut srv6_header)?;
+            body.extend_from_slice(&srv6_header.to_bytes());
+        }
+        body.extend_from_slice(&onion_header.to_bytes());
+        body.extend_from_slice(&payload);
+        ipv6_header.payload_length = body.len() as u16;
+        let mut new_packet = ipv6_header.to_bytes();
+        new_packet.extend_from_slice(&body);
+        
+        Ok((new_packet, next_hop))
+    }
+    
+    // 代替経路への切り替え（README §3.5.3, §3.5.4）。セッションのパスリストから、自ノードを通り
+    // 障害ノードを含まない経路を選び、未処理のセグメントを自ノードより後ろのセグメントで置き換えて
+    // 新しい経路の次のノードを返す。元のOnionの残りの層は元の経路のノードの鍵で作られているため、
+    // Onionヘッダーとペイロードは送信者がこのパケット用に構築した代替経路のOnionに差し替える。
+    // 長さは変わらないため、後続ノードからは経路変更の有無を区別できない
+    fn reroute(&self,
+               failure: Failure,
+               ipv6_header: &mut Ipv6Header,
+               srv6_header: &mut SRv6Header,
+               onion_header: &mut OnionHeader,
+               payload: &mut Vec<u8>,
+               mut alternatives: HashMap<String, AlternativeOnion>) -> Result<SocketAddr, Error> {
+        let Failure { sid: failed_sid, address: failed_address, reason } = failure;
+        let session_id = onion_header.session_id;
+        let no_alternative = || PathError::NoAlternativePath { session_id, failed: failed_sid };
+        let path_list = self.path_list(session_id).ok_or_else(no_alternative)?;
+        let previous = self.rerouted_path(session_id);
+        
+        // 送信者がOnionを用意した経路だけが候補になる
+        let mut candidates = path_list.clone();
+        candidates.paths.retain(|entry| alternatives.contains_key(&entry.path_id));
+        let alternative = select_alternative_path(&candidates,
+                                                  |sid| self.match_local_sid(sid).is_some(),
+                                                  &failed_sid,
+                                                  failed_address,
+                                                  |address| self.neighbor_failure(address).is_none(),
+                                                  previous.as_deref(),
+                                                  &self.score_weights)
+            .ok_or_else(no_alternative)?;
+        let onion = alternatives.remove(&alternative.path_id).ok_or_else(no_alternative)?;
+        *onion_header = OnionHeader::from_bytes(&onion.onion_header)?;
+        *payload = onion.payload;
+        
+        // uSID形式が設定されていれば置き換えるセグメントもキャリアに詰める
+        let segments = match &self.usid_format {
+            Some(format) => format.compress(&alternative.segments).segments,
+            None => alternative.segments.clone(),
+        };
+        let active_sid = srv6_header.replace_remaining(ipv6_header, &segments)?;
+        let next_hop = if self.raw_socket.is_some() {
+            SocketAddr::new(active_sid.into(), 0)
+        } else {
+            alternative.nodes[0]
+        };
+        
+        if previous.as_deref() != Some(alternative.path_id.as_str()) {
+            let old_path = previous.or_else(|| {
+                path_list.paths.iter()
+                    .find(|entry| entry.srv6_segments.contains(&failed_sid))
+                    .map(|entry| entry.path_id.clone())
+            });
+            warn!(session_id, %reason, failed = %failed_sid, old_path = ?old_path, new_path = %alternative.path_id,
+                  score = ?alternative.score.map(|score| score.to_string()), %next_hop, "代替経路に切り替え");
+            self.rerouted_paths.lock().unwrap().insert(session_id, alternative.path_id.clone());
+            
+            // 経路変更通知（README §3.5.5）は転送後に送る。署名には識別鍵が必要
+            let change = PathChange::new(session_id, old_path.unwrap_or_default(), alternative.path_id, reason);
+            match (&self.node_type, &self.identity_key) {
+                (NodeType::Relay(sid), Some(key)) => {
+                    let notification = SignedPathChange::sign(change, *sid, key);
+                    self.pending_path_changes.lock().unwrap().push((notification, alternative.nodes));
+                },
+                _ => debug!(session_id, "識別鍵がないため経路変更通知を送りません"),
+            }
+        }
+        Ok(next_hop)
+    }
+    
+    // SIDテーブルにないSID宛のパケットをポリシーに従って処理する。
+    // 転送する場合はOnion層に触れず、Hop Limitだけ減らして宛先のロケータ経路へ送る
+    fn forward_unknown_sid(&self, packet: &[u8], ipv6_header: Ipv6Header, sid: Ipv6Addr) -> Result<(Vec<u8>, SocketAddr), Error> {
+        // 自ノードのロケータ内で未登録のFunctionは、転送しても自ノードに戻るだけなので常に破棄する
+        if self.owns_locator(&sid) {
+            return Err(PathError::NotForThisNode(sid).into());
+        }
+        match self.unknown_sid_policy {
+            UnknownSidPolicy::Drop => Err(PathError::NotForThisNode(sid).into()),
+            UnknownSidPolicy::Forward => {
+                if ipv6_header.hop_limit <= 1 {
+                    return Err(PathError::HopLimitExceeded.into());
+                }
+                let next_hop = self.route_next_hop(MAIN_TABLE, &ipv6_header.destination)?;
+                let mut forwarded = packet.to_vec();
+                forwarded[7] = ipv6_header.hop_limit - 1;
+                debug!(%sid, "未知のSID宛パケットを転送");
+                Ok((forwarded, next_hop))
+            },
+        }
+    }
+    
+    pub fn process_receiver_packet(&self, packet: &[u8]) -> Result<DeliveredMessage, Error> {
+        // 受信者の処理は単純化
+        // SRv6ヘッダー（End.DT6で取り除かれていなければ）とOnionヘッダーを解析した後、最終ペイロードを取得
+        
+        let (ipv6_header, srv6_header, offset) = parse_onion_packet(packet)?;
+        // SRHが取り除かれている場合、セグメントリストの認証は取り除いた中継ノードで完了している
+        if let Some(srv6_header) = &srv6_header {
+            self.verify_srh_hmac(&ipv6_header, srv6_header)?;
+        }
+        let mut onion_header = OnionHeader::from_bytes(&packet[offset..])?;
+        record_session(&onion_header);
+        let payload = onion_payload(packet, offset)?;
+        
+        // セッション鍵とIVBaseを取得
+        let (session_key, iv_base) = self.decryption_params(onion_header.session_id)?;
+        
+        // 復号前にリプレイとMACを検証
+        self.check_replay(&onion_header)?;
+        self.verify_layer_mac(&onion_header, &payload)?;
+        self.commit_replay(&onion_header)?;
+        
+        // 自分のスロットを取り出し、最終宛先であることを確認
+        let stream = keystream(&session_key, &layer_nonce(&iv_base, onion_header.sequence), ROUTING_INFO_SIZE + HOP_SLOT_SIZE)?;
+        let onion_layer = onion_header.peel(&stream)?;
+        if onion_layer.next_hop != NextHop::DeliverLocal {
+            return Err(PathError::InvalidNextHop("最終宛先の層ではありません").into());
+        }
+        
+        // 最終ペイロードを復号して返す
+        let message = open_payload(&session_key, &payload_nonce(&iv_base, onion_header.sequence), &payload)?;
+        Ok(DeliveredMessage { session_id: onion_header.session_id, message })
+    }
+    
+    // SRv6ポリシーの経路でメッセージを送信する。鍵はポリシーの中継ノード順に受信者分まで並べる。
+    // 代替経路には同じシーケンス番号でOnionを構築し、データパケットより先に最初の中継ノードへ送る
+    pub async fn send_message(&self, 
+                         session_id: u32,
+                         policy: &SRv6Policy,
+                         keys: &[SessionKeys],
+                         alternatives: &[AlternativeRoute],
+                         message: &[u8],
+                         socket: &UdpSocket) -> Result<(), Error> {
+        policy.validate()?;
+        let node_addresses = policy.node_addresses();
+        // 鍵とアドレスは中継ノード分に加えて受信者分を含む
+        if keys.len() != node_addresses.len() {
+            return Err(PathError::LengthMismatch.into());
+        }
+        
+        let sequence = self.next_sequence(session_id);
+        let receiver = node_addresses[node_addresses.len() - 1];
+        let relay_keys = keys[..keys.len() - 1].iter().map(|k| k.encryption_key.clone()).collect();
+        self.sent_path_keys.lock().unwrap().insert(session_id, relay_keys);
+        
+        for alternative in alternatives {
+            let (_, active_sids) = self.carrier(&alternative.segments);
+            let next_hops = onion_next_hops(&active_sids, receiver);
+            let (onion_header, payload) = build_onion(session_id, sequence, &alternative.keys, &next_hops, message)?;
+            let onion = AlternativeOnion {
+                path_id: alternative.path_id.clone(),
+                onion_header: onion_header.to_bytes(),
+                payload,
+            };
+            let sealed = SealedMessage::seal(session_id, &keys[0].encryption_key, &onion.to_bytes())?;
+            socket.send_to(&MessageType::AlternativeOnion.frame(&sealed.to_bytes()), node_addresses[0]).await?;
+            trace!(session_id, seq = sequence, path = %alternative.path_id, "代替経路のOnion送信");
+        }
+        
+        // uSID形式が設定されていれば経路をキャリアに詰める。各中継ノードに届くときの宛先は
+        // キャリアのシフトで決まるため、Onionの次ホップ指示もそれに合わせる
+        let (segments, active_sids) = self.carrier(&policy.sids());
+        let next_hops = onion_next_hops(&active_sids, receiver);
+        let (onion_header, payload) = build_onion(session_id, sequence, keys, &next_hops, message)?;
+        
+        // SRv6ヘッダーを作成
+        let first_sid = active_sids[0];
+        let mut srv6_header = SRv6Header::new(segments)?;
+        
+        // 最終パケットを構築（宛先は最初のアクティブSID）
+        let mut ipv6_header = Ipv6Header::new(to_ipv6(self.address.ip()), first_sid, 0);
+        self.sign_srh(&ipv6_header, &mut srv6_header)?;
+        let mut body = srv6_header.to_bytes();
+        body.extend_from_slice(&onion_header.to_bytes());
+        body.extend_from_slice(&payload);
+        ipv6_header.payload_length = body.len() as u16;
+        let mut packet = ipv6_header.to_bytes();
+        packet.extend_from_slice(&body);
+        
+        // 送信。生ソケットでは最初のSIDへ、UDPでは最初の中継ノードへ
+        let first_hop = if self.raw_socket.is_some() {
+            SocketAddr::new(first_sid.into(), 0)
+        } else {
+            node_addresses[0]
+        };
+        self.send_data(socket, &packet, first_hop).await?;
+        debug!(session_id, seq = sequence, policy = %policy.policy_id, color = policy.color,
+               first_hop = %node_addresses[0], alternatives = alternatives.len(), bytes = packet.len(), "パケット送信");
+        
+        Ok(())
+    }
+    
+    // 経路のSRHに載せるセグメントと、各中継ノードに届くときの宛先（uSID形式が設定されていればキャリアに詰める）
+    fn carrier(&self, path: &[Ipv6Addr]) -> (Vec<Ipv6Addr>, Vec<Ipv6Addr>) {
+        match &self.usid_format {
+            Some(format) => {
+                let compressed = format.compress(path);
+                (compressed.segments, compressed.active_sids)
+            },
+            None => (path.to_vec(), path.to_vec()),
+        }
+    }
+}
+
+// 各中継ノードの次ホップ指示: 最後の中継ノードは最終宛先（受信者）への出口、
+// それ以外は次の中継ノードに届くときの宛先
+fn onion_next_hops(active_sids: &[Ipv6Addr], receiver: SocketAddr) -> Vec<NextHop> {
+    (0..active_sids.len())
+        .map(|i| match active_sids.get(i + 1) {
+            Some(sid) => NextHop::Sid(*sid),
+            None => NextHop::Exit(receiver),
+        })
+        .collect()
+}
+
+// Onionヘッダーとペイロードを構築する。鍵は経路順に受信者分まで並べ、次ホップ指示は中継ノード分を並べる
+fn build_onion(session_id: u32,
+               sequence: u64,
+               keys: &[SessionKeys],
+               next_hops: &[NextHop],
+               message: &[u8]) -> Result<(OnionHeader, Vec<u8>), Error> {
+    let hops = keys.len();
+    if hops != next_hops.len() + 1 {
+        return Err(PathError::LengthMismatch.into());
+    }
+    if hops > MAX_HOPS {
+        return Err(PathError::TooManyHops { hops, max: MAX_HOPS }.into());
+    }
+    
+    // 各ノードが再計算できるよう、そのノードのIVBaseとシーケンス番号から鍵ストリームを導出
+    let streams = keys.iter()
+        .map(|k| keystream(&k.encryption_key, &layer_nonce(&k.iv_base, sequence), LAYER_STREAM_SIZE))
+        .collect::<Result<Vec<_>, _>>()?;
+    
+    // 最終ペイロードは受信者の鍵で認証付き暗号化し、内側から外側へ中継ノードの層を重ねる
+    let receiver_keys = &keys[hops - 1];
+    let mut payloads = vec![Vec::new(); hops];
+    payloads[hops - 1] = seal_payload(
+        &receiver_keys.encryption_key,
+        &payload_nonce(&receiver_keys.iv_base, sequence),
+        message
+    )?;
+    for i in (0..hops - 1).rev() {
+        let mut payload = payloads[i + 1].clone();
+        xor_in_place(&mut payload, &streams[i][ROUTING_INFO_SIZE + HOP_SLOT_SIZE..]);
+        payloads[i] = payload;
+    }
+    
+    // フィラー: 各中継ノードがシフト時に埋め戻す擬似乱数を事前に再現する
+    let mut filler = Vec::new();
+    for stream in &streams[..hops - 1] {
+        filler.resize(filler.len() + HOP_SLOT_SIZE, 0);
+        let start = ROUTING_INFO_SIZE + HOP_SLOT_SIZE - filler.len();
+        xor_in_place(&mut filler, &stream[start..ROUTING_INFO_SIZE + HOP_SLOT_SIZE]);
+    }
+    
+    // 最も内側は受信者のスロット（自ノードで終端、MACは空）と乱数パディング
+    let mut routing_info = OnionLayer::new(NextHop::DeliverLocal, [0u8; MAC_SIZE]).to_bytes();
+    let mut padding = vec![0u8; ROUTING_INFO_SIZE - hops * HOP_SLOT_SIZE];
+    rand::thread_rng().fill(&mut padding[..]);
+    routing_info.extend_from_slice(&padding);
+    xor_in_place(&mut routing_info, &streams[hops - 1]);
+    routing_info.extend_from_slice(&filler);
+    
+    let mut mac = compute_mac(&receiver_keys.mac_key, &routing_info, &payloads[hops - 1], session_id, sequence);
+    
+    // 内側から外側へスロットを積み、各ノード用のMACを一つ外側のスロットに埋め込む
+    for i in (0..hops - 1).rev() {
+        let mut next_routing_info = OnionLayer::new(next_hops[i].clone(), mac).to_bytes();
+        next_routing_info.extend_from_slice(&routing_info[..ROUTING_INFO_SIZE - HOP_SLOT_SIZE]);
+        xor_in_place(&mut next_routing_info, &streams[i]);
+        routing_info = next_routing_info;
+        
+        mac = compute_mac(&keys[i].mac_key, &routing_info, &payloads[i], session_id, sequence);
+    }
+    
+    // Onionヘッダーを作成し、最初のノード用のMACを設定
+    let mut onion_header = OnionHeader::new(session_id, sequence, routing_info);
+    onion_header.set_mac(mac);
+    
+    let payload = payloads.swap_remove(0);
+    Ok((onion_header, payload))
+}
+
+// IPv6ヘッダーとSRHを解析し、Onionヘッダーの開始位置を返す
+fn parse_ipv6_srh(packet: &[u8]) -> Result<(Ipv6Header, SRv6Header, usize), Error> {
+    let ipv6_header = Ipv6Header::from_bytes(packet)?;
+    if ipv6_header.next_header != IPPROTO_ROUTING {
+        return Err(CommunicationError::MalformedPacket(
+            format!("ルーティングヘッダーがありません (Next Header: {})", ipv6_header.next_header)).into());
+    }
+    
+    let srv6_header = SRv6Header::from_bytes(&packet[IPV6_HEADER_SIZE..])?;
+    if srv6_header.next_header != ONION_NEXT_HEADER {
+        return Err(CommunicationError::MalformedSrh(
+            format!("次ヘッダーがOnionヘッダーではありません: {}", srv6_header.next_header)).into());
+    }
+    
+    let onion_header_offset = IPV6_HEADER_SIZE + srv6_header.header_len();
+    Ok((ipv6_header, srv6_header, onion_header_offset))
+}
+
+// 次のプローブ時刻まで待つ。タイマーがなければ待ち続ける
+async fn next_tick(timer: &mut Option<Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        },
+        None => std::future::pending().await,
+    }
+}
+
+// アドレスの先頭 prefix_len ビットを残す
+fn mask_prefix(address: Ipv6Addr, prefix_len: u8) -> Ipv6Addr {
+    let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+    Ipv6Addr::from(u128::from(address) & mask)
+}
+
+// 受信者向けパケットを解析する。End.DT6でSRHが取り除かれている場合はOnionヘッダーが直接続く
+fn parse_onion_packet(packet: &[u8]) -> Result<(Ipv6Header, Option<SRv6Header>, usize), Error> {
+    let ipv6_header = Ipv6Header::from_bytes(packet)?;
+    if ipv6_header.next_header == ONION_NEXT_HEADER {
+        return Ok((ipv6_header, None, IPV6_HEADER_SIZE));
+    }
+    let (ipv6_header, srv6_header, offset) = parse_ipv6_srh(packet)?;
+    Ok((ipv6_header, Some(srv6_header), offset))
+}
+
+// データグラムごとのスパン。セッションID、シーケンス番号、SIDのArgumentはヘッダー解析後に記録する
+fn packet_span(src: SocketAddr, len: usize) -> Span {
+    info_span!("packet", %src, len, session_id = field::Empty, seq = field::Empty, sid_arg = field::Empty)
+}
+
+fn record_session(header: &OnionHeader) {
+    let span = Span::current();
+    span.record("session_id", header.session_id);
+    span.record("seq", header.sequence);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+    use crate::ipv6::DEFAULT_HOP_LIMIT;
+    use crate::pathlist::{unix_time, DEFAULT_PATH_LIST_LIFETIME};
+    use crate::policy::{PolicySegment, COLOR_DEFAULT};
+
+    const SESSION_ID: u32 = 7;
+
+    fn sid(n: u16) -> Ipv6Addr {
+        Ipv6Addr::new(0x2001, 0xdb8, n, 0, 0, 0, 0, 1)
+    }
+
+    fn address(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    fn keys(n: u8) -> SessionKeys {
+        derive_keys(&[n; 48], b"test")
+    }
+
+    // 中継ノードにパケットを渡した直前のノード
+    fn previous_hop() -> SocketAddr {
+        address(9000)
+    }
+
+    // 中継ノード sid(1) から始まる経路のパケットを送信者に作らせ、最初の中継ノード宛に届いたIPv6パケットを返す。
+    // 鍵は中継ノードごとに keys(1), keys(2), ...、受信者は keys(0)
+    async fn first_packet(path: &[Ipv6Addr], receiver: SocketAddr) -> Vec<u8> {
+        let first_hop = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let segments = path.iter()
+            .map(|&sid| PolicySegment { sid, address: first_hop.local_addr().unwrap() })
+            .collect();
+        let policy = SRv6Policy::new(receiver, COLOR_DEFAULT, segments);
+        let mut hop_keys: Vec<SessionKeys> = (1..=path.len() as u8).map(keys).collect();
+        hop_keys.push(keys(0));
+
+        let sender_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sender = Node::new(NodeType::Sender, sender_socket.local_addr().unwrap());
+        sender.send_message(SESSION_ID, &policy, &hop_keys, &[], b"hello", &sender_socket).await.unwrap();
+
+        let mut buf = vec![0u8; 4096];
+        let (len, _) = first_hop.recv_from(&mut buf).await.unwrap();
+        assert_eq!(buf[0], MessageType::Data as u8);
+        buf[1..len].to_vec()
+    }
+
+    fn relay_with(behavior: SidBehavior) -> Node {
+        let relay = Node::new(NodeType::Relay(sid(1)), address(9001));
+        relay.add_local_sid(sid(1), behavior);
+        relay.install_session_keys(SESSION_ID, keys(1));
+        relay
+    }
+
+    #[tokio::test]
+    async fn tampered_layer_fails_mac_verification() {
+        let mut packet = first_packet(&[sid(1), sid(2)], address(9100)).await;
+        let relay = relay_with(SidBehavior::End);
+        relay.add_sid_route(sid(2), address(9002));
+        *packet.last_mut().unwrap() ^= 1;
+
+        let error = relay.process_relay_packet(&packet, previous_hop()).await.unwrap_err();
+        assert!(matches!(error, Error::Crypto(CryptoError::MacVerification { session_id: SESSION_ID })));
+        assert_eq!(relay.stats().mac_failures(), 1);
+        assert_eq!(relay.stats().srh_hmac_failures(), 0);
+    }
+
+    #[tokio::test]
+    async fn srh_hmac_failures_are_counted_separately() {
+        let packet = first_packet(&[sid(1), sid(2)], address(9100)).await;
+        let relay = relay_with(SidBehavior::End).with_srh_hmac_key(SrhHmacKey { key_id: 1, key: vec![1; 32] });
+        relay.add_sid_route(sid(2), address(9002));
+
+        let error = relay.process_relay_packet(&packet, previous_hop()).await.unwrap_err();
+        assert_eq!(error.code(), ErrorCode::SrhHmacFailure);
+        assert_eq!(relay.stats().srh_hmac_failures(), 1);
+        assert_eq!(relay.stats().mac_failures(), 0);
+    }
+
+    #[tokio::test]
+    async fn end_x_forwards_to_adjacency() {
+        let packet = first_packet(&[sid(1), sid(2)], address(9100)).await;
+        let relay = relay_with(SidBehavior::EndX(address(9002)));
+        relay.add_sid_route(sid(2), address(9999));
+
+        let (forwarded, next_hop) = relay.process_relay_packet(&packet, previous_hop()).await.unwrap();
+        assert_eq!(next_hop, address(9002));
+        assert_eq!(Ipv6Header::from_bytes(&forwarded).unwrap().destination, sid(2));
+    }
+
+    #[tokio::test]
+    async fn end_decrements_hop_limit_and_drops_exhausted_packet() {
+        let mut packet = first_packet(&[sid(1), sid(2)], address(9100)).await;
+        let relay = relay_with(SidBehavior::End);
+        relay.add_sid_route(sid(2), address(9002));
+
+        let (forwarded, _) = relay.process_relay_packet(&packet, previous_hop()).await.unwrap();
+        assert_eq!(Ipv6Header::from_bytes(&forwarded).unwrap().hop_limit, DEFAULT_HOP_LIMIT - 1);
+
+        // Hop Limitが尽きたパケットはリプレイ検出より前に破棄する
+        packet[7] = 1;
+        let relay = relay_with(SidBehavior::End);
+        relay.add_sid_route(sid(2), address(9002));
+        let error = relay.process_relay_packet(&packet, previous_hop()).await.unwrap_err();
+        assert_eq!(error.code(), ErrorCode::HopLimitExceeded);
+        packet[7] = 2;
+        assert!(relay.process_relay_packet(&packet, previous_hop()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn end_t_looks_up_specified_table() {
+        let packet = first_packet(&[sid(1), sid(2)], address(9100)).await;
+        let relay = relay_with(SidBehavior::EndT(10));
+        relay.add_sid_route(sid(2), address(9999));
+        relay.add_table_route(10, sid(2), 48, address(9010));
+
+        let (_, next_hop) = relay.process_relay_packet(&packet, previous_hop()).await.unwrap();
+        assert_eq!(next_hop, address(9010));
+
+        // 指定テーブルに経路がなければメインテーブルは参照しない
+        let relay = relay_with(SidBehavior::EndT(20));
+        relay.add_sid_route(sid(2), address(9999));
+        let error = relay.process_relay_packet(&packet, previous_hop()).await.unwrap_err();
+        assert_eq!(error.code(), ErrorCode::NoRoute);
+    }
+
+    #[tokio::test]
+    async fn end_dt6_decapsulates_and_looks_up_final_destination() {
+        let receiver_address = address(9100);
+        let packet = first_packet(&[sid(1)], receiver_address).await;
+        let relay = relay_with(SidBehavior::EndDT6(10));
+        relay.add_table_route(10, to_ipv6(receiver_address.ip()), 128, address(9020));
+
+        let (forwarded, next_hop) = relay.process_relay_packet(&packet, previous_hop()).await.unwrap();
+        assert_eq!(next_hop, address(9020));
+        let ipv6_header = Ipv6Header::from_bytes(&forwarded).unwrap();
+        assert_eq!(ipv6_header.next_header, ONION_NEXT_HEADER);
+        assert_eq!(ipv6_header.destination, to_ipv6(receiver_address.ip()));
+
+        // SRHが取り除かれたパケットを受信者が復号できる
+        let receiver = Node::new(NodeType::Receiver, receiver_address);
+        receiver.install_session_keys(SESSION_ID, keys(0));
+        assert_eq!(receiver.process_receiver_packet(&forwarded).unwrap().message, b"hello");
+    }
+
+    #[tokio::test]
+    async fn end_dt6_is_rejected_before_last_segment() {
+        let packet = first_packet(&[sid(1), sid(2)], address(9100)).await;
+        let relay = relay_with(SidBehavior::EndDT6(10));
+        let error = relay.process_relay_packet(&packet, previous_hop()).await.unwrap_err();
+        assert_eq!(error.code(), ErrorCode::InvalidNextHop);
+    }
+
+    #[tokio::test]
+    async fn unknown_sid_follows_policy() {
+        let packet = first_packet(&[sid(1), sid(2)], address(9100)).await;
+
+        // 既定では破棄する
+        let relay = Node::new(NodeType::Relay(sid(3)), address(9003));
+        relay.add_sid_route(sid(1), address(9001));
+        let error = relay.process_relay_packet(&packet, previous_hop()).await.unwrap_err();
+        assert_eq!(error.code(), ErrorCode::SidMismatch);
+
+        // 転送する場合はOnion層に触れず、Hop Limitだけ減らす
+        let relay = relay.with_unknown_sid_policy(UnknownSidPolicy::Forward);
+        let (forwarded, next_hop) = relay.process_relay_packet(&packet, previous_hop()).await.unwrap();
+        assert_eq!(next_hop, address(9001));
+        assert_eq!(forwarded[7], packet[7] - 1);
+        assert_eq!(forwarded[8..], packet[8..]);
+    }
+
+    // 送信者が送ったパスリストを中継ノードが受け取るまで
+    async fn deliver_path_list(relay: &Node, path_list: &PathList, keys: &SessionKeys) -> Result<(), Error> {
+        let relay_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sender_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sender = Node::new(NodeType::Sender, sender_socket.local_addr().unwrap());
+        sender.send_path_list(path_list, keys, relay_socket.local_addr().unwrap(), &sender_socket).await?;
+
+        let mut buf = vec![0u8; 4096];
+        let (len, _) = relay_socket.recv_from(&mut buf).await.unwrap();
+        assert_eq!(buf[0], MessageType::PathList as u8);
+        relay.receive_path_list(&buf[1..len])
+    }
+
+    fn two_hop_path_list(session_id: u32) -> PathList {
+        let segments = vec![PolicySegment { sid: sid(1), address: address(9001) }, PolicySegment { sid: sid(2), address: address(9002) }];
+        PathList::from_policies(session_id, [&SRv6Policy::new(address(9100), COLOR_DEFAULT, segments)], DEFAULT_PATH_LIST_LIFETIME)
+    }
+
+    #[tokio::test]
+    async fn relay_stores_path_list_sealed_with_session_key() {
+        let relay = relay_with(SidBehavior::End);
+        let path_list = two_hop_path_list(SESSION_ID);
+        deliver_path_list(&relay, &path_list, &keys(1)).await.unwrap();
+        assert_eq!(relay.path_list(SESSION_ID), Some(path_list));
+
+        // 別のセッション鍵で暗号化したものは復号できない
+        let error = deliver_path_list(&relay, &two_hop_path_list(SESSION_ID), &keys(2)).await.unwrap_err();
+        assert_eq!(error.code(), ErrorCode::DecryptionFailure);
+        // セッションを確立していない中継ノードは受け取らない
+        let error = deliver_path_list(&relay, &two_hop_path_list(SESSION_ID + 1), &keys(1)).await.unwrap_err();
+        assert_eq!(error.code(), ErrorCode::UnknownSession);
+    }
+
+    #[tokio::test]
+    async fn relay_rejects_expired_path_list() {
+        let relay = relay_with(SidBehavior::End);
+        let mut path_list = two_hop_path_list(SESSION_ID);
+        path_list.timestamp -= 120;
+        path_list.expiry = path_list.timestamp + 60;
+        let sealed = SealedMessage::seal(SESSION_ID, &keys(1).encryption_key, &path_list.to_bytes()).unwrap();
+        let error = relay.receive_path_list(&sealed.to_bytes()).unwrap_err();
+        assert_eq!(error.code(), ErrorCode::PathListExpired);
+        assert_eq!(relay.path_list(SESSION_ID), None);
+    }
+
+    #[tokio::test]
+    async fn expired_path_list_is_evicted() {
+        let relay = relay_with(SidBehavior::End);
+        deliver_path_list(&relay, &two_hop_path_list(SESSION_ID), &keys(1)).await.unwrap();
+
+        relay.path_lists.lock().unwrap().get_mut(&SESSION_ID).unwrap().expiry = unix_time();
+        assert_eq!(relay.path_list(SESSION_ID), None);
+        assert!(relay.path_lists.lock().unwrap().is_empty());
+    }
+
+    // 識別鍵を持つ中継ノードを起動し、送信者にその公開鍵を登録する
+    async fn handshake_peers(registered_key: Option<PublicKey>) -> (Arc<Node>, Node, UdpSocket) {
+        let relay_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let relay_address = relay_socket.local_addr().unwrap();
+        let identity_key = SecretKey::random(&mut OsRng);
+        let public_key = identity_key.public_key();
+        let relay = Arc::new(Node::new(NodeType::Relay(sid(1)), relay_address)
+            .with_probe_interval(None)
+            .with_identity_key(identity_key));
+        let running = Arc::clone(&relay);
+        tokio::spawn(async move { running.run(Arc::new(relay_socket)).await });
+
+        let sender_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sender = Node::new(NodeType::Sender, sender_socket.local_addr().unwrap());
+        sender.add_peer_key(relay_address, registered_key.unwrap_or(public_key));
+        (relay, sender, sender_socket)
+    }
+
+    #[tokio::test]
+    async fn handshake_response_is_verified_with_peer_key() {
+        let (relay, sender, socket) = handshake_peers(None).await;
+        let keys = sender.establish_session(SESSION_ID, relay.address, &socket).await.unwrap();
+        assert_eq!(relay.session_keys.lock().unwrap()[&SESSION_ID], keys.encryption_key);
+        assert_eq!(relay.mac_keys.lock().unwrap()[&SESSION_ID], keys.mac_key);
+    }
+
+    #[tokio::test]
+    async fn handshake_rejects_response_signed_by_another_key() {
+        let (relay, sender, socket) = handshake_peers(Some(SecretKey::random(&mut OsRng).public_key())).await;
+        let error = sender.establish_session(SESSION_ID, relay.address, &socket).await.err().unwrap();
+        assert_eq!(error.code(), ErrorCode::HandshakeSignatureFailure);
+    }
+
+    #[tokio::test]
+    async fn handshake_requires_registered_peer_key() {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sender = Node::new(NodeType::Sender, socket.local_addr().unwrap());
+        let error = sender.establish_session(SESSION_ID, address(9001), &socket).await.err().unwrap();
+        assert_eq!(error.code(), ErrorCode::UnknownPeer);
+    }
+
+    #[tokio::test]
+    async fn rekey_is_authenticated_with_current_session_keys() {
+        let (relay, sender, socket) = handshake_peers(None).await;
+        let keys = sender.establish_session(SESSION_ID, relay.address, &socket).await.unwrap();
+        let rekeyed = sender.rekey_session(SESSION_ID, relay.address, &keys, &socket).await.unwrap();
+        assert_ne!(rekeyed.encryption_key, keys.encryption_key);
+        assert_eq!(relay.session_keys.lock().unwrap()[&SESSION_ID], rekeyed.encryption_key);
+    }
+
+    #[tokio::test]
+    async fn unauthenticated_rekey_keeps_session_keys() {
+        let relay = Node::new(NodeType::Relay(sid(1)), address(9001)).with_identity_key(SecretKey::random(&mut OsRng));
+        relay.install_session_keys(SESSION_ID, keys(1));
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let secret = EphemeralSecret::random(&mut OsRng);
+
+        // MACなし、または別の鍵によるMACの開始メッセージでは鍵を差し替えない
+        let mut init = HandshakeMessage::new(SESSION_ID, &secret.public_key());
+        let error = relay.respond_handshake(&init.to_bytes(), address(9000), &socket).await.unwrap_err();
+        assert_eq!(error.code(), ErrorCode::UnauthenticatedRekey);
+        init.authenticate_rekey(&keys(2).mac_key);
+        let error = relay.respond_handshake(&init.to_bytes(), address(9000), &socket).await.unwrap_err();
+        assert_eq!(error.code(), ErrorCode::UnauthenticatedRekey);
+        assert_eq!(relay.session_keys.lock().unwrap()[&SESSION_ID], keys(1).encryption_key);
+
+        // 新しいセッションの開始メッセージには認証を求めない
+        let init = HandshakeMessage::new(SESSION_ID + 1, &secret.public_key());
+        relay.respond_handshake(&init.to_bytes(), address(9000), &socket).await.unwrap();
+        assert!(relay.session_keys.lock().unwrap().contains_key(&(SESSION_ID + 1)));
+    }
+
+    // sid(2) の中継ノードが経路を切り替えた通知
+    fn signed_path_change(identity_key: &SecretKey, new_path_id: &str) -> SignedPathChange {
+        let change = PathChange::new(SESSION_ID, "old".into(), new_path_id.into(), RerouteReason::NodeFailure);
+        SignedPathChange::sign(change, sid(2), identity_key)
+    }
+
+    #[test]
+    fn duplicate_path_change_is_rejected() {
+        let identity_key = SecretKey::random(&mut OsRng);
+        let node = Node::new(NodeType::Sender, address(9000));
+        node.add_trusted_key(sid(2), identity_key.public_key());
+
+        let notification = signed_path_change(&identity_key, "new").to_bytes();
+        assert_eq!(node.receive_path_change(&notification).unwrap().new_path_id, "new");
+        let error = node.receive_path_change(&notification).unwrap_err();
+        assert!(matches!(error, Error::Session(SessionError::DuplicateNotification(SESSION_ID))));
+        assert_eq!(error.code(), ErrorCode::Replay);
+
+        // 同じ中継ノードでも別の経路への切り替えは受け付ける
+        assert!(node.receive_path_change(&signed_path_change(&identity_key, "newer").to_bytes()).is_ok());
+    }
+
+    #[test]
+    fn path_change_updates_current_path_of_known_session() {
+        let identity_key = SecretKey::random(&mut OsRng);
+        let relay = relay_with(SidBehavior::End);
+        relay.add_trusted_key(sid(2), identity_key.public_key());
+
+        relay.apply_path_change(&signed_path_change(&identity_key, "new").to_bytes()).unwrap();
+        assert_eq!(relay.rerouted_path(SESSION_ID), Some("new".into()));
+
+        // セッションを持たないノードは記録しない
+        let other = Node::new(NodeType::Relay(sid(3)), address(9003));
+        other.add_trusted_key(sid(2), identity_key.public_key());
+        let error = other.apply_path_change(&signed_path_change(&identity_key, "new").to_bytes()).unwrap_err();
+        assert_eq!(error.code(), ErrorCode::UnknownSession);
+        assert_eq!(other.rerouted_path(SESSION_ID), None);
+    }
+
+    #[tokio::test]
+    async fn nodes_without_identity_key_do_not_start() {
+        for node_type in [NodeType::Relay(sid(1)), NodeType::Receiver] {
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let node = Node::new(node_type, socket.local_addr().unwrap());
+            let error = node.run(Arc::new(socket)).await.unwrap_err();
+            assert_eq!(error.code(), ErrorCode::InitMissingIdentityKey);
+        }
+    }
+}
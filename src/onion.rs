@@ -0,0 +1,360 @@
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+
+use aes_gcm::{Aes256Gcm, Nonce};
+use aes_gcm::aead::{Aead, KeyInit};
+
+use crate::crypto::{xor_in_place, MAC_SIZE, NONCE_SIZE, TAG_SIZE};
+use crate::error::{CommunicationError, CryptoError, Error};
+
+// 定数
+pub const PROTOCOL_VERSION: u8 = 1;
+pub const MAX_HOPS: usize = 6; // 受信者を含む最大ホップ数
+pub const NEXT_HOP_FIELD_SIZE: usize = 32;
+pub const HOP_SLOT_SIZE: usize = NEXT_HOP_FIELD_SIZE + MAC_SIZE;
+pub const ROUTING_INFO_SIZE: usize = MAX_HOPS * HOP_SLOT_SIZE;
+pub const PAYLOAD_SIZE: usize = 512; // 全ホップで一定のペイロード長
+pub const LAYER_STREAM_SIZE: usize = ROUTING_INFO_SIZE + HOP_SLOT_SIZE + PAYLOAD_SIZE;
+pub const ONION_HEADER_SIZE: usize = 48 + ROUTING_INFO_SIZE;
+
+// 次ホップ指示（Onion層スロット内のTLV: Type(1) | Length(1) | Value）
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NextHop {
+    Address(SocketAddr), // 次の中継ノードのIPv6アドレスとポート
+    Sid(Ipv6Addr),       // 次の中継ノードのSID（SID経路表で転送先を解決）
+    DeliverLocal,        // このノードで終端し、ペイロードを受け取る
+    Exit(SocketAddr),    // Onion網を出て最終宛先へ送出
+}
+
+impl NextHop {
+    const TYPE_ADDRESS: u8 = 1;
+    const TYPE_SID: u8 = 2;
+    const TYPE_DELIVER_LOCAL: u8 = 3;
+    const TYPE_EXIT: u8 = 4;
+    
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (tlv_type, value) = match self {
+            NextHop::Address(addr) => (Self::TYPE_ADDRESS, encode_socket_addr(addr)),
+            NextHop::Sid(sid) => (Self::TYPE_SID, sid.octets().to_vec()),
+            NextHop::DeliverLocal => (Self::TYPE_DELIVER_LOCAL, Vec::new()),
+            NextHop::Exit(addr) => (Self::TYPE_EXIT, encode_socket_addr(addr)),
+        };
+        
+        let mut bytes = Vec::with_capacity(2 + value.len());
+        bytes.push(tlv_type);
+        bytes.push(value.len() as u8);
+        bytes.extend_from_slice(&value);
+        bytes
+    }
+    
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 2 {
+            return Err(CommunicationError::MalformedPacket("次ホップTLVが短すぎます".into()).into());
+        }
+        
+        let tlv_type = bytes[0];
+        let length = bytes[1] as usize;
+        if bytes.len() < 2 + length {
+            return Err(CommunicationError::MalformedPacket("次ホップTLVのデータが不足しています".into()).into());
+        }
+        let value = &bytes[2..2 + length];
+        
+        match (tlv_type, length) {
+            (Self::TYPE_ADDRESS, 18) => Ok(NextHop::Address(decode_socket_addr(value))),
+            (Self::TYPE_SID, 16) => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(value);
+                Ok(NextHop::Sid(Ipv6Addr::from(octets)))
+            },
+            (Self::TYPE_DELIVER_LOCAL, 0) => Ok(NextHop::DeliverLocal),
+            (Self::TYPE_EXIT, 18) => Ok(NextHop::Exit(decode_socket_addr(value))),
+            _ => Err(CommunicationError::MalformedPacket(format!("不正な次ホップTLV: type={}, length={}", tlv_type, length)).into()),
+        }
+    }
+}
+
+// ソケットアドレスをIPv6アドレス(16) + ポート(2)にエンコード（IPv4はIPv4射影アドレス）
+pub fn encode_socket_addr(addr: &SocketAddr) -> Vec<u8> {
+    let ip = match addr.ip() {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+        IpAddr::V6(v6) => v6,
+    };
+    let mut bytes = ip.octets().to_vec();
+    bytes.extend_from_slice(&addr.port().to_be_bytes());
+    bytes
+}
+
+pub fn decode_socket_addr(bytes: &[u8]) -> SocketAddr {
+    let mut octets = [0u8; 16];
+    octets.copy_from_slice(&bytes[..16]);
+    let port = u16::from_be_bytes([bytes[16], bytes[17]]);
+    SocketAddr::new(Ipv6Addr::from(octets).to_canonical(), port)
+}
+
+// Onion層構造体
+// ルーティング情報内の1ホップ分のスロット（次ホップ指示と次ホップ用MAC）
+pub struct OnionLayer {
+    pub next_hop: NextHop,
+    pub next_mac: [u8; MAC_SIZE], // 次ホップ用のMAC（送信者が事前計算）
+}
+
+impl OnionLayer {
+    pub fn new(next_hop: NextHop, next_mac: [u8; MAC_SIZE]) -> Self {
+        Self { next_hop, next_mac }
+    }
+    
+    // 固定長スロット（HOP_SLOT_SIZEバイト）にエンコード
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HOP_SLOT_SIZE);
+        bytes.extend_from_slice(&self.next_hop.to_bytes());
+        bytes.resize(NEXT_HOP_FIELD_SIZE, 0);
+        bytes.extend_from_slice(&self.next_mac);
+        bytes
+    }
+    
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < HOP_SLOT_SIZE {
+            return Err(CommunicationError::MalformedPacket("Onion層スロットが短すぎます".into()).into());
+        }
+        
+        // 次ホップ指示と次ホップ用MACを分離
+        let next_hop = NextHop::from_bytes(&bytes[..NEXT_HOP_FIELD_SIZE])?;
+        let mut next_mac = [0u8; MAC_SIZE];
+        next_mac.copy_from_slice(&bytes[NEXT_HOP_FIELD_SIZE..HOP_SLOT_SIZE]);
+        
+        Ok(Self { next_hop, next_mac })
+    }
+}
+
+// Onionルーティングヘッダー
+// ルーティング情報は固定長で、各ノードは先頭スロットを取り出して左シフトし、
+// 末尾を擬似乱数で埋め戻す（Sphinx方式）。ルーティング情報のサイズからは経路長や経路上の位置を推測できない。
+// ただしSession IDとシーケンス番号はホップごとに変換せず平文のまま全ホップで同じ値を運ぶ（README §3.1.3）。
+// 複数のリンクを観測できる者は同じパケットを区間をまたいで対応づけられ、経路と各ノードの位置を知り得る。
+// SRH（セグメントリストとSegments Left）も経路と現在位置を平文で示すため、ここで隠すのはペイロードと次ホップ指示のみ
+pub struct OnionHeader {
+    pub version: u8,
+    pub session_id: u32, // 全ホップ共通（ノードはこの値でセッション鍵を引く）
+    pub sequence: u64, // リプレイ防止用のシーケンス番号（1から単調増加）、各層のノンス導出にも使用。全ホップ共通
+    pub mac: [u8; MAC_SIZE],
+    pub routing_info: Vec<u8>, // ROUTING_INFO_SIZEバイト
+}
+
+impl OnionHeader {
+    pub fn new(session_id: u32, sequence: u64, routing_info: Vec<u8>) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            session_id,
+            sequence,
+            mac: [0; MAC_SIZE], // 初期値、後で計算
+            routing_info,
+        }
+    }
+    
+    pub fn set_mac(&mut self, mac: [u8; MAC_SIZE]) {
+        self.mac = mac;
+    }
+    
+    // 自分のスロットを復号して取り出し、ルーティング情報をシフトして埋め戻す
+    // streamはこのノードの鍵ストリームのうち先頭ROUTING_INFO_SIZE + HOP_SLOT_SIZEバイト
+    pub fn peel(&mut self, stream: &[u8]) -> Result<OnionLayer, Error> {
+        let mut routing_info = self.routing_info.clone();
+        routing_info.resize(ROUTING_INFO_SIZE + HOP_SLOT_SIZE, 0);
+        xor_in_place(&mut routing_info, stream);
+        
+        let layer = OnionLayer::from_bytes(&routing_info[..HOP_SLOT_SIZE])?;
+        self.routing_info = routing_info[HOP_SLOT_SIZE..].to_vec();
+        Ok(layer)
+    }
+    
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(ONION_HEADER_SIZE);
+        bytes.push(self.version);
+        bytes.extend_from_slice(&[0, 0, 0]); // 予約済み
+        bytes.extend_from_slice(&self.session_id.to_be_bytes());
+        bytes.extend_from_slice(&self.sequence.to_be_bytes());
+        bytes.extend_from_slice(&self.mac);
+        bytes.extend_from_slice(&self.routing_info);
+        bytes
+    }
+    
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < ONION_HEADER_SIZE {
+            return Err(CommunicationError::MalformedPacket("Onionヘッダーが短すぎます".into()).into());
+        }
+        
+        let version = bytes[0];
+        let session_id = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        
+        let mut sequence = [0u8; 8];
+        sequence.copy_from_slice(&bytes[8..16]);
+        let sequence = u64::from_be_bytes(sequence);
+        
+        let mut mac = [0u8; MAC_SIZE];
+        mac.copy_from_slice(&bytes[16..16 + MAC_SIZE]);
+        
+        let routing_info = bytes[16 + MAC_SIZE..ONION_HEADER_SIZE].to_vec();
+        
+        Ok(Self {
+            version,
+            session_id,
+            sequence,
+            mac,
+            routing_info,
+        })
+    }
+}
+
+// 受信者向けの最終ペイロードを固定長に詰めて認証付き暗号化する
+// 平文: メッセージ長(2バイト) || メッセージ || ゼロパディング
+pub fn seal_payload(key: &[u8], nonce: &[u8; NONCE_SIZE], message: &[u8]) -> Result<Vec<u8>, Error> {
+    if message.len() > PAYLOAD_SIZE - TAG_SIZE - 2 {
+        return Err(CommunicationError::MessageTooLong { len: message.len(), max: PAYLOAD_SIZE - TAG_SIZE - 2 }.into());
+    }
+    
+    let cipher = Aes256Gcm::new_from_slice(key)
+        .map_err(|_| CryptoError::InvalidKeyLength)?;
+    
+    let mut plaintext = Vec::with_capacity(PAYLOAD_SIZE - TAG_SIZE);
+    plaintext.extend_from_slice(&(message.len() as u16).to_be_bytes());
+    plaintext.extend_from_slice(message);
+    plaintext.resize(PAYLOAD_SIZE - TAG_SIZE, 0);
+    
+    let payload = cipher.encrypt(&Nonce::from(*nonce), plaintext.as_ref())
+        .map_err(|_| CryptoError::Encryption)?;
+    Ok(payload)
+}
+
+pub fn open_payload(key: &[u8], nonce: &[u8; NONCE_SIZE], payload: &[u8]) -> Result<Vec<u8>, Error> {
+    let cipher = Aes256Gcm::new_from_slice(key)
+        .map_err(|_| CryptoError::InvalidKeyLength)?;
+    
+    let plaintext = cipher.decrypt(&Nonce::from(*nonce), payload)
+        .map_err(|_| CryptoError::Decryption)?;
+    
+    // 先頭2バイトのメッセージ長が平文に収まっているか
+    let [len_high, len_low, body @ ..] = plaintext.as_slice() else {
+        return Err(CryptoError::MalformedPayload.into());
+    };
+    let message_len = u16::from_be_bytes([*len_high, *len_low]) as usize;
+    let message = body.get(..message_len).ok_or(CryptoError::MalformedPayload)?;
+    
+    Ok(message.to_vec())
+}
+
+// Onionヘッダーに続く固定長ペイロードを取り出す
+pub fn onion_payload(packet: &[u8], onion_header_offset: usize) -> Result<Vec<u8>, Error> {
+    let offset = onion_header_offset + ONION_HEADER_SIZE;
+    if packet.len() != offset + PAYLOAD_SIZE {
+        return Err(CommunicationError::MalformedPacket("Onionペイロード長が不正です".into()).into());
+    }
+    Ok(packet[offset..].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+
+    const KEY: [u8; 32] = [7; 32];
+    const NONCE: [u8; NONCE_SIZE] = [3; NONCE_SIZE];
+
+    fn encrypt(plaintext: &[u8]) -> Vec<u8> {
+        Aes256Gcm::new_from_slice(&KEY).unwrap()
+            .encrypt(&Nonce::from(NONCE), plaintext)
+            .unwrap()
+    }
+
+    #[test]
+    fn payload_round_trip() {
+        let payload = seal_payload(&KEY, &NONCE, b"hello").unwrap();
+        assert_eq!(payload.len(), PAYLOAD_SIZE);
+        assert_eq!(open_payload(&KEY, &NONCE, &payload).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn open_payload_rejects_short_plaintext() {
+        for plaintext in [&[][..], &[0][..]] {
+            let error = open_payload(&KEY, &NONCE, &encrypt(plaintext)).unwrap_err();
+            assert_eq!(error.code(), ErrorCode::MalformedPayload);
+        }
+    }
+
+    #[test]
+    fn open_payload_rejects_length_beyond_plaintext() {
+        let error = open_payload(&KEY, &NONCE, &encrypt(&[0, 4, b'a', b'b'])).unwrap_err();
+        assert_eq!(error.code(), ErrorCode::MalformedPayload);
+    }
+
+    #[test]
+    fn open_payload_rejects_tampered_ciphertext() {
+        let mut payload = seal_payload(&KEY, &NONCE, b"hello").unwrap();
+        payload[0] ^= 1;
+        assert_eq!(open_payload(&KEY, &NONCE, &payload).unwrap_err().code(), ErrorCode::DecryptionFailure);
+    }
+
+    #[test]
+    fn next_hop_round_trip() {
+        let next_hops = [
+            NextHop::Address("[2001:db8::1]:9001".parse().unwrap()),
+            NextHop::Sid("2001:db8::2".parse().unwrap()),
+            NextHop::DeliverLocal,
+            NextHop::Exit("127.0.0.1:9004".parse().unwrap()),
+        ];
+        for next_hop in next_hops {
+            assert_eq!(NextHop::from_bytes(&next_hop.to_bytes()).unwrap(), next_hop);
+        }
+    }
+
+    #[test]
+    fn next_hop_rejects_malformed_tlv() {
+        let sid = NextHop::Sid("2001:db8::2".parse().unwrap()).to_bytes();
+        assert!(NextHop::from_bytes(&sid[..1]).is_err());
+        assert!(NextHop::from_bytes(&sid[..sid.len() - 1]).is_err());
+        assert!(NextHop::from_bytes(&[NextHop::TYPE_SID, 4, 0, 0, 0, 0]).is_err());
+        assert!(NextHop::from_bytes(&[0, 0]).is_err());
+    }
+
+    #[test]
+    fn onion_layer_round_trip() {
+        let layer = OnionLayer::new(NextHop::Sid("2001:db8::3".parse().unwrap()), [5; MAC_SIZE]);
+        let bytes = layer.to_bytes();
+        assert_eq!(bytes.len(), HOP_SLOT_SIZE);
+
+        let decoded = OnionLayer::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.next_hop, layer.next_hop);
+        assert_eq!(decoded.next_mac, layer.next_mac);
+    }
+
+    #[test]
+    fn onion_header_round_trip() {
+        let routing_info: Vec<u8> = (0..ROUTING_INFO_SIZE).map(|i| i as u8).collect();
+        let mut header = OnionHeader::new(0x01020304, 42, routing_info.clone());
+        header.set_mac([9; MAC_SIZE]);
+        let bytes = header.to_bytes();
+        assert_eq!(bytes.len(), ONION_HEADER_SIZE);
+
+        let decoded = OnionHeader::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.version, PROTOCOL_VERSION);
+        assert_eq!(decoded.session_id, 0x01020304);
+        assert_eq!(decoded.sequence, 42);
+        assert_eq!(decoded.mac, [9; MAC_SIZE]);
+        assert_eq!(decoded.routing_info, routing_info);
+        assert!(OnionHeader::from_bytes(&bytes[..ONION_HEADER_SIZE - 1]).is_err());
+    }
+
+    #[test]
+    fn peel_shifts_routing_info() {
+        let first = OnionLayer::new(NextHop::DeliverLocal, [1; MAC_SIZE]).to_bytes();
+        let mut routing_info = first.clone();
+        routing_info.resize(ROUTING_INFO_SIZE, 2);
+        let mut header = OnionHeader::new(1, 1, routing_info.clone());
+
+        // 鍵ストリームが0なら先頭スロットを取り出し、残りを左に詰めて末尾を0で埋め戻す
+        let layer = header.peel(&[0; ROUTING_INFO_SIZE + HOP_SLOT_SIZE]).unwrap();
+        assert_eq!(layer.next_hop, NextHop::DeliverLocal);
+        assert_eq!(layer.next_mac, [1; MAC_SIZE]);
+        assert_eq!(header.routing_info.len(), ROUTING_INFO_SIZE);
+        assert_eq!(header.routing_info[..ROUTING_INFO_SIZE - HOP_SLOT_SIZE], routing_info[HOP_SLOT_SIZE..]);
+        assert!(header.routing_info[ROUTING_INFO_SIZE - HOP_SLOT_SIZE..].iter().all(|&byte| byte == 0));
+    }
+}
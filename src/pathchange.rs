@@ -0,0 +1,249 @@
+use std::net::Ipv6Addr;
+use std::time::Duration;
+
+use p384::{PublicKey, SecretKey};
+
+use crate::crypto::{sign_with_identity, verify_identity_signature, SIGNATURE_SIZE};
+use crate::error::{CommunicationError, CryptoError, Error, SessionError};
+use crate::pathlist::unix_time;
+use crate::reroute::RerouteReason;
+
+// 定数
+pub const PATH_CHANGE_MAX_AGE: Duration = Duration::from_secs(60); // 通知の時刻と受信時刻の許容差（リプレイ対策）
+const SIGNATURE_CONTEXT: &[u8] = b"HORNET+ PathChange";
+const LAYER_INNER: u8 = 0; // 後ろのホップが暗号化した層が続く
+const LAYER_ORIGIN: u8 = 1; // 経路を切り替えた中継ノードの署名付き通知
+
+// 経路変更通知（README §3.5.5）: 中継ノードが経路を切り替えたことを送信者と新しい経路のノードに知らせる
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathChange {
+    pub session_id: u32,
+    pub old_path_id: String, // 切り替え前の経路（不明なら空）
+    pub new_path_id: String,
+    pub timestamp: u64, // 経路を切り替えた時刻（UNIX秒）
+    pub reason: RerouteReason,
+}
+
+impl PathChange {
+    pub fn new(session_id: u32, old_path_id: String, new_path_id: String, reason: RerouteReason) -> Self {
+        Self {
+            session_id,
+            old_path_id,
+            new_path_id,
+            timestamp: unix_time(),
+            reason,
+        }
+    }
+
+    // SessionID | Timestamp | Reason | OldPathID長(2) | OldPathID | NewPathID長(2) | NewPathID
+    // PathIDはパスリスト（1データグラム）に収まっているため長さは2バイトで足りる
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(17 + self.old_path_id.len() + self.new_path_id.len());
+        bytes.extend_from_slice(&self.session_id.to_be_bytes());
+        bytes.extend_from_slice(&self.timestamp.to_be_bytes());
+        bytes.push(self.reason as u8);
+        for path_id in [&self.old_path_id, &self.new_path_id] {
+            bytes.extend_from_slice(&(path_id.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(path_id.as_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let malformed = |reason: &str| CommunicationError::MalformedPacket(format!("経路変更通知を解析できません: {}", reason));
+        if bytes.len() < 13 {
+            return Err(malformed("短すぎます").into());
+        }
+
+        let session_id = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let mut timestamp = [0u8; 8];
+        timestamp.copy_from_slice(&bytes[4..12]);
+        let reason = RerouteReason::from_u8(bytes[12]).ok_or_else(|| malformed("不明な理由です"))?;
+
+        let mut rest = &bytes[13..];
+        let mut path_ids = Vec::with_capacity(2);
+        for _ in 0..2 {
+            if rest.len() < 2 {
+                return Err(malformed("PathIDが途中で切れています").into());
+            }
+            let len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+            let path_id = rest.get(2..2 + len).ok_or_else(|| malformed("PathIDが途中で切れています"))?;
+            path_ids.push(String::from_utf8(path_id.to_vec()).map_err(|_| malformed("PathIDがUTF-8ではありません"))?);
+            rest = &rest[2 + len..];
+        }
+        if !rest.is_empty() {
+            return Err(malformed("余分なデータがあります").into());
+        }
+
+        let new_path_id = path_ids.pop().expect("2件読み込み済み");
+        let old_path_id = path_ids.pop().expect("2件読み込み済み");
+        Ok(Self {
+            session_id,
+            old_path_id,
+            new_path_id,
+            timestamp: u64::from_be_bytes(timestamp),
+            reason,
+        })
+    }
+
+    // 通知の時刻が受信時刻から許容差以内か
+    pub fn is_fresh(&self) -> bool {
+        unix_time().abs_diff(self.timestamp) <= PATH_CHANGE_MAX_AGE.as_secs()
+    }
+}
+
+// 経路を切り替えた中継ノードが長期識別鍵で署名した経路変更通知: 署名者SID | 署名 | 経路変更通知
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignedPathChange {
+    pub signer: Ipv6Addr, // 署名した中継ノードのSID（検証に使う公開鍵を引く）
+    pub change: PathChange,
+    pub signature: [u8; SIGNATURE_SIZE],
+}
+
+impl SignedPathChange {
+    pub fn sign(change: PathChange, signer: Ipv6Addr, key: &SecretKey) -> Self {
+        let signature = sign_with_identity(key, &signed_data(&signer, &change));
+        Self { signer, change, signature }
+    }
+
+    // 署名者の公開鍵で署名を検証し、通知の時刻を確認する
+    pub fn verify(&self, key: &PublicKey) -> Result<&PathChange, Error> {
+        if !verify_identity_signature(key, &signed_data(&self.signer, &self.change), &self.signature) {
+            return Err(CryptoError::Signature(self.signer).into());
+        }
+        if !self.change.is_fresh() {
+            return Err(SessionError::StaleNotification(self.change.session_id).into());
+        }
+        Ok(&self.change)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let change = self.change.to_bytes();
+        let mut bytes = Vec::with_capacity(16 + SIGNATURE_SIZE + change.len());
+        bytes.extend_from_slice(&self.signer.octets());
+        bytes.extend_from_slice(&self.signature);
+        bytes.extend_from_slice(&change);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 16 + SIGNATURE_SIZE {
+            return Err(CommunicationError::MalformedPacket("署名付き経路変更通知が短すぎます".into()).into());
+        }
+
+        let mut signer = [0u8; 16];
+        signer.copy_from_slice(&bytes[..16]);
+
+        let mut signature = [0u8; SIGNATURE_SIZE];
+        signature.copy_from_slice(&bytes[16..16 + SIGNATURE_SIZE]);
+
+        Ok(Self {
+            signer: Ipv6Addr::from(signer),
+            change: PathChange::from_bytes(&bytes[16 + SIGNATURE_SIZE..])?,
+            signature,
+        })
+    }
+}
+
+// 署名対象: 用途を示すコンテキスト | 署名者SID | 経路変更通知
+fn signed_data(signer: &Ipv6Addr, change: &PathChange) -> Vec<u8> {
+    let mut data = SIGNATURE_CONTEXT.to_vec();
+    data.extend_from_slice(&signer.octets());
+    data.extend_from_slice(&change.to_bytes());
+    data
+}
+
+// 逆方向経路で送信者に返す経路変更通知の1層（暗号化する平文）: 種別(1) | 内容
+// 経路を切り替えた中継ノードが署名付き通知をセッション鍵で暗号化して前ホップに渡し、前ホップも
+// 受け取ったものを自身のセッション鍵で暗号化して送信者側へ転送する。送信者は経路順の鍵で外側から剥がす
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReverseLayer {
+    Inner(Vec<u8>), // 後ろのホップが暗号化した層（SealedMessage）
+    Origin(SignedPathChange),
+}
+
+impl ReverseLayer {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            ReverseLayer::Inner(sealed) => [&[LAYER_INNER], sealed.as_slice()].concat(),
+            ReverseLayer::Origin(notification) => [&[LAYER_ORIGIN], notification.to_bytes().as_slice()].concat(),
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        match bytes.split_first() {
+            Some((&LAYER_INNER, sealed)) => Ok(ReverseLayer::Inner(sealed.to_vec())),
+            Some((&LAYER_ORIGIN, notification)) => Ok(ReverseLayer::Origin(SignedPathChange::from_bytes(notification)?)),
+            _ => Err(CommunicationError::MalformedPacket("逆方向経路の経路変更通知の種別が不正です".into()).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    fn signer() -> Ipv6Addr {
+        "2001:db8::1".parse().unwrap()
+    }
+
+    fn change() -> PathChange {
+        PathChange::new(7, "primary".into(), "backup".into(), RerouteReason::Unreachable)
+    }
+
+    #[test]
+    fn path_change_round_trip() {
+        let change = change();
+        assert_eq!(PathChange::from_bytes(&change.to_bytes()).unwrap(), change);
+    }
+
+    #[test]
+    fn path_change_rejects_trailing_bytes() {
+        let mut bytes = change().to_bytes();
+        bytes.push(0);
+        assert!(PathChange::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn signed_path_change_round_trip_and_verify() {
+        let key = SecretKey::random(&mut OsRng);
+        let signed = SignedPathChange::sign(change(), signer(), &key);
+        let decoded = SignedPathChange::from_bytes(&signed.to_bytes()).unwrap();
+        assert_eq!(decoded, signed);
+        assert_eq!(decoded.verify(&key.public_key()).unwrap(), &signed.change);
+    }
+
+    #[test]
+    fn rejects_tampered_notification() {
+        let key = SecretKey::random(&mut OsRng);
+        let mut signed = SignedPathChange::sign(change(), signer(), &key);
+        signed.change.new_path_id = "attacker".into();
+        assert!(matches!(signed.verify(&key.public_key()), Err(Error::Crypto(CryptoError::Signature(_)))));
+    }
+
+    #[test]
+    fn rejects_other_signer_key() {
+        let signed = SignedPathChange::sign(change(), signer(), &SecretKey::random(&mut OsRng));
+        let other = SecretKey::random(&mut OsRng).public_key();
+        assert!(matches!(signed.verify(&other), Err(Error::Crypto(CryptoError::Signature(_)))));
+    }
+
+    #[test]
+    fn rejects_stale_notification() {
+        let key = SecretKey::random(&mut OsRng);
+        let stale = PathChange { timestamp: unix_time() - PATH_CHANGE_MAX_AGE.as_secs() - 1, ..change() };
+        let signed = SignedPathChange::sign(stale, signer(), &key);
+        assert!(matches!(signed.verify(&key.public_key()), Err(Error::Session(SessionError::StaleNotification(7)))));
+    }
+
+    #[test]
+    fn reverse_layer_round_trip() {
+        let signed = SignedPathChange::sign(change(), signer(), &SecretKey::random(&mut OsRng));
+        for layer in [ReverseLayer::Inner(vec![1, 2, 3]), ReverseLayer::Origin(signed)] {
+            assert_eq!(ReverseLayer::from_bytes(&layer.to_bytes()).unwrap(), layer);
+        }
+        assert!(ReverseLayer::from_bytes(&[9]).is_err());
+        assert!(ReverseLayer::from_bytes(&[]).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashSet;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{CommunicationError, Error, PathError};
+use crate::onion::MAX_HOPS;
+use crate::policy::SRv6Policy;
+
+// 定数
+pub const DEFAULT_PATH_LIST_LIFETIME: Duration = Duration::from_secs(30 * 60); // 定期更新間隔（README §3.4.3）
+
+// パスリスト（README §3.4.3）: セッションごとの優先順位付き経路候補。
+// 送信者が作成してセッション鍵で暗号化し、最初の中継ノードに送る
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PathList {
+    pub session_id: u32,
+    pub timestamp: u64, // 作成時刻（UNIX秒）
+    pub expiry: u64,    // 有効期限（UNIX秒）。この時刻以降は使用しない
+    pub paths: Vec<PathEntry>,
+}
+
+// 経路候補
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PathEntry {
+    pub priority: u8, // 1が最優先
+    pub path_id: String,
+    pub nodes: Vec<SocketAddr>,        // 中継ノードのアドレス（経路順）
+    pub srv6_segments: Vec<Ipv6Addr>,  // nodes に対応するSID
+    #[serde(default)]
+    pub metrics: PathMetrics,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PathMetrics {
+    #[serde(default)]
+    pub latency_ms: Option<u32>,
+    #[serde(default)]
+    pub bandwidth_mbps: Option<u32>,
+    #[serde(default)]
+    pub trust_score: Option<f64>,  // 経路上のノードの信頼スコアの集計（0.0〜1.0）
+    #[serde(default)]
+    pub reliability: Option<f64>,  // 経路の信頼性（0.0〜1.0）
+}
+
+impl PathEntry {
+    pub fn from_policy(priority: u8, policy: &SRv6Policy) -> Self {
+        Self {
+            priority,
+            path_id: policy.policy_id.clone(),
+            nodes: policy.segment_list.iter().map(|segment| segment.address).collect(),
+            srv6_segments: policy.sids(),
+            metrics: PathMetrics {
+                latency_ms: policy.metadata.latency_ms,
+                bandwidth_mbps: policy.metadata.bandwidth_mbps,
+                ..PathMetrics::default()
+            },
+        }
+    }
+}
+
+impl PathList {
+    pub fn new(session_id: u32, mut paths: Vec<PathEntry>, lifetime: Duration) -> Self {
+        paths.sort_by_key(|path| path.priority);
+        let timestamp = unix_time();
+        Self {
+            session_id,
+            timestamp,
+            expiry: timestamp.saturating_add(lifetime.as_secs()),
+            paths,
+        }
+    }
+
+    // 選択順に並んだポリシーから作成する（先頭を優先度1とする）
+    pub fn from_policies<'a>(session_id: u32,
+                             policies: impl IntoIterator<Item = &'a SRv6Policy>,
+                             lifetime: Duration) -> Self {
+        let paths = policies.into_iter()
+            .zip(1..=u8::MAX)
+            .map(|(policy, priority)| PathEntry::from_policy(priority, policy))
+            .collect();
+        Self::new(session_id, paths, lifetime)
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.paths.is_empty() {
+            return Err(PathError::InvalidPathList("経路候補がありません".into()).into());
+        }
+        // 経路IDは代替経路のOnionと経路変更通知で経路を特定するため、パスリスト内で一意でなければならない
+        let mut path_ids = HashSet::new();
+        for path in &self.paths {
+            if !path_ids.insert(path.path_id.as_str()) {
+                return Err(PathError::InvalidPathList(format!("経路IDが重複しています: {}", path.path_id)).into());
+            }
+            if path.nodes.is_empty() || path.nodes.len() != path.srv6_segments.len() {
+                return Err(PathError::InvalidPathList(
+                    format!("ノードとSIDの数が一致しません: {}", path.path_id)).into());
+            }
+            if path.nodes.len() + 1 > MAX_HOPS {
+                return Err(PathError::TooManyHops { hops: path.nodes.len() + 1, max: MAX_HOPS }.into());
+            }
+        }
+        if self.expiry <= self.timestamp {
+            return Err(PathError::InvalidPathList("有効期限が作成時刻以前です".into()).into());
+        }
+        Ok(())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        unix_time() >= self.expiry
+    }
+
+    // 最優先の経路
+    pub fn primary(&self) -> Option<&PathEntry> {
+        self.paths.first()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("パスリストのシリアライズに失敗")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let path_list: Self = serde_json::from_slice(bytes)
+            .map_err(|e| CommunicationError::MalformedPacket(format!("パスリストを解析できません: {}", e)))?;
+        path_list.validate()?;
+        Ok(path_list)
+    }
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+    use crate::policy::{PolicySegment, COLOR_DEFAULT};
+
+    fn policy(hops: u16) -> SRv6Policy {
+        let segments = (1..=hops)
+            .map(|n| PolicySegment { sid: Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, n), address: SocketAddr::from(([127, 0, 0, 1], 9000 + n)) })
+            .collect();
+        SRv6Policy::new("[::1]:9100".parse().unwrap(), COLOR_DEFAULT, segments)
+    }
+
+    fn path_list() -> PathList {
+        PathList::from_policies(7, [&policy(2), &policy(3)], DEFAULT_PATH_LIST_LIFETIME)
+    }
+
+    #[test]
+    fn from_policies_assigns_priorities_in_order() {
+        let (first, second) = (policy(2), policy(3));
+        let path_list = PathList::from_policies(7, [&first, &second], DEFAULT_PATH_LIST_LIFETIME);
+        assert_eq!(path_list.primary().unwrap().path_id, first.policy_id);
+        assert_eq!(path_list.paths.iter().map(|path| path.priority).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(path_list.paths[1].srv6_segments, second.sids());
+        assert_eq!(path_list.expiry - path_list.timestamp, DEFAULT_PATH_LIST_LIFETIME.as_secs());
+        path_list.validate().unwrap();
+    }
+
+    #[test]
+    fn new_sorts_paths_by_priority() {
+        let mut paths = path_list().paths;
+        paths[0].priority = 3;
+        let path_list = PathList::new(7, paths, DEFAULT_PATH_LIST_LIFETIME);
+        assert_eq!(path_list.paths.iter().map(|path| path.priority).collect::<Vec<_>>(), [2, 3]);
+    }
+
+    #[test]
+    fn validate_rejects_inconsistent_paths() {
+        let invalid = |modify: fn(&mut PathList)| {
+            let mut path_list = path_list();
+            modify(&mut path_list);
+            path_list.validate().unwrap_err().code()
+        };
+        assert_eq!(invalid(|list| list.paths.clear()), ErrorCode::InvalidPathList);
+        assert_eq!(invalid(|list| { list.paths[1].srv6_segments.pop(); }), ErrorCode::InvalidPathList);
+        assert_eq!(invalid(|list| { list.paths[0].nodes.clear(); list.paths[0].srv6_segments.clear(); }), ErrorCode::InvalidPathList);
+        assert_eq!(invalid(|list| list.expiry = list.timestamp), ErrorCode::InvalidPathList);
+        assert_eq!(invalid(|list| list.paths[1].path_id = list.paths[0].path_id.clone()), ErrorCode::InvalidPathList);
+        assert_eq!(invalid(|list| list.paths[0] = PathEntry::from_policy(1, &policy(MAX_HOPS as u16))), ErrorCode::PathTooLong);
+    }
+
+    #[test]
+    fn is_expired_at_expiry() {
+        let mut path_list = path_list();
+        path_list.expiry = unix_time();
+        assert!(path_list.is_expired());
+        path_list.expiry = unix_time() + 60;
+        assert!(!path_list.is_expired());
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let path_list = path_list();
+        assert_eq!(PathList::from_bytes(&path_list.to_bytes()).unwrap(), path_list);
+        assert_eq!(PathList::from_bytes(b"{}").unwrap_err().code(), ErrorCode::MalformedPacket);
+
+        let mut invalid = path_list;
+        invalid.paths.clear();
+        assert_eq!(PathList::from_bytes(&invalid.to_bytes()).unwrap_err().code(), ErrorCode::InvalidPathList);
+    }
+}
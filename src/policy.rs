@@ -0,0 +1,443 @@
+use std::cmp::Reverse;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::str::FromStr;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::encode_hex;
+use crate::error::{ConfigError, Error, PathError};
+use crate::onion::MAX_HOPS;
+
+// 定数（カラー値は運用者がSRドメイン内で割り当てる。以下は意図ごとの既定値）
+pub const COLOR_DEFAULT: u32 = 0;
+pub const COLOR_LOW_LATENCY: u32 = 100;
+pub const COLOR_HIGH_BANDWIDTH: u32 = 200;
+
+// 経路選択の意図（カラーに対応）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Intent {
+    LowLatency,    // 遅延の小さい経路を優先
+    HighBandwidth, // 帯域の大きい経路を優先
+}
+
+impl Intent {
+    pub fn color(&self) -> u32 {
+        match self {
+            Intent::LowLatency => COLOR_LOW_LATENCY,
+            Intent::HighBandwidth => COLOR_HIGH_BANDWIDTH,
+        }
+    }
+
+    pub fn from_color(color: u32) -> Option<Self> {
+        match color {
+            COLOR_LOW_LATENCY => Some(Intent::LowLatency),
+            COLOR_HIGH_BANDWIDTH => Some(Intent::HighBandwidth),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Intent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "low-latency" => Ok(Intent::LowLatency),
+            "high-bandwidth" => Ok(Intent::HighBandwidth),
+            _ => Err(format!("不明な意図です: {} (使用可能: low-latency, high-bandwidth)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Intent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Intent::LowLatency => write!(f, "low-latency"),
+            Intent::HighBandwidth => write!(f, "high-bandwidth"),
+        }
+    }
+}
+
+// "low-latency", "high-bandwidth" または数値のカラー
+pub fn parse_color(text: &str) -> Result<u32, String> {
+    text.parse::<u32>().or_else(|_| text.parse::<Intent>().map(|intent| intent.color()))
+}
+
+// 経路上の中継ノード: SIDとハンドシェイク先アドレス
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicySegment {
+    pub sid: Ipv6Addr,
+    pub address: SocketAddr,
+}
+
+// "SID@アドレス" 形式（例: 2001:db8::1@[::1]:9001）
+impl FromStr for PolicySegment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (sid, address) = s
+            .split_once('@')
+            .ok_or_else(|| format!("SID@アドレス の形式で指定してください: {}", s))?;
+        Ok(Self {
+            sid: sid.parse().map_err(|_| format!("SIDが不正です: {}", sid))?,
+            address: address.parse().map_err(|_| format!("アドレスが不正です: {}", address))?,
+        })
+    }
+}
+
+impl fmt::Display for PolicySegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.sid, self.address)
+    }
+}
+
+// 経路の性能指標と優先度
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyMetadata {
+    #[serde(default)]
+    pub latency_ms: Option<u32>,     // 想定遅延
+    #[serde(default)]
+    pub bandwidth_mbps: Option<u32>, // 最低帯域
+    #[serde(default)]
+    pub priority: u8,                // 大きいほど優先
+}
+
+// SRv6ポリシー（README §3.6.3）
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SRv6Policy {
+    pub policy_id: String,
+    #[serde(default)]
+    pub headend: Option<SocketAddr>, // 送信元ノード（未設定ならどの送信者でも使用できる）
+    pub endpoint: SocketAddr,        // 最終宛先（受信ノード）
+    #[serde(default)]
+    pub color: u32,
+    pub segment_list: Vec<PolicySegment>, // 経路順の中継ノード
+    #[serde(default)]
+    pub metadata: PolicyMetadata,
+}
+
+impl SRv6Policy {
+    pub fn new(endpoint: SocketAddr, color: u32, segment_list: Vec<PolicySegment>) -> Self {
+        Self {
+            policy_id: generate_policy_id(),
+            headend: None,
+            endpoint,
+            color,
+            segment_list,
+            metadata: PolicyMetadata::default(),
+        }
+    }
+
+    pub fn with_headend(mut self, headend: SocketAddr) -> Self {
+        self.headend = Some(headend);
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: PolicyMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    // 中継ノードが1つ以上あり、受信者を含めてOnionヘッダーのホップ数に収まるか
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.segment_list.is_empty() {
+            return Err(PathError::InvalidPolicy(format!("セグメントリストが空です: {}", self.policy_id)).into());
+        }
+        let hops = self.segment_list.len() + 1;
+        if hops > MAX_HOPS {
+            return Err(PathError::TooManyHops { hops, max: MAX_HOPS }.into());
+        }
+        Ok(())
+    }
+
+    pub fn sids(&self) -> Vec<Ipv6Addr> {
+        self.segment_list.iter().map(|segment| segment.sid).collect()
+    }
+
+    // 中継ノードと受信者のアドレス（ハンドシェイクと鍵の順序）
+    pub fn node_addresses(&self) -> Vec<SocketAddr> {
+        self.segment_list.iter()
+            .map(|segment| segment.address)
+            .chain(std::iter::once(self.endpoint))
+            .collect()
+    }
+}
+
+impl fmt::Display for SRv6Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let segments = self.segment_list.iter().map(|segment| segment.to_string()).collect::<Vec<_>>();
+        write!(f, "{} カラー={}", self.policy_id, self.color)?;
+        if let Some(intent) = Intent::from_color(self.color) {
+            write!(f, "({})", intent)?;
+        }
+        write!(f, " 宛先={} 経路=[{}] 優先度={}", self.endpoint, segments.join(", "), self.metadata.priority)?;
+        if let Some(latency) = self.metadata.latency_ms {
+            write!(f, " 遅延={}ms", latency)?;
+        }
+        if let Some(bandwidth) = self.metadata.bandwidth_mbps {
+            write!(f, " 帯域={}Mbps", bandwidth)?;
+        }
+        Ok(())
+    }
+}
+
+// SRv6ポリシーの集合（JSONファイルに永続化する）
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyStore {
+    #[serde(default)]
+    policies: Vec<SRv6Policy>,
+}
+
+impl PolicyStore {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = fs::read_to_string(path)
+            .map_err(|source| ConfigError::Read { path: path.to_path_buf(), source })?;
+        let store: Self = serde_json::from_str(&text)
+            .map_err(|e| ConfigError::Parse(format!("JSON ({}): {}", path.display(), e)))?;
+        for policy in &store.policies {
+            policy.validate()?;
+        }
+        Ok(store)
+    }
+
+    // ファイルがなければ空の集合を返す
+    pub fn load_or_default(path: &Path) -> Result<Self, Error> {
+        match fs::metadata(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            _ => Self::load(path),
+        }
+    }
+
+    // 一時ファイルに書いてから置き換え、書き込み途中のファイルを残さない
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let write_error = |source| ConfigError::Write { path: path.to_path_buf(), source };
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|e| write_error(io::Error::other(e)))?;
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, text + "\n").map_err(write_error)?;
+        fs::rename(&temporary, path).map_err(write_error)?;
+        Ok(())
+    }
+
+    pub fn policies(&self) -> &[SRv6Policy] {
+        &self.policies
+    }
+
+    pub fn get(&self, policy_id: &str) -> Option<&SRv6Policy> {
+        self.policies.iter().find(|policy| policy.policy_id == policy_id)
+    }
+
+    // 同じPolicyIDのポリシーがあれば置き換える
+    pub fn insert(&mut self, policy: SRv6Policy) -> Result<(), Error> {
+        policy.validate()?;
+        self.policies.retain(|existing| existing.policy_id != policy.policy_id);
+        self.policies.push(policy);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, policy_id: &str) -> Option<SRv6Policy> {
+        let index = self.policies.iter().position(|policy| policy.policy_id == policy_id)?;
+        Some(self.policies.remove(index))
+    }
+
+    // 宛先とカラーが一致するポリシーのうち、優先度の最も高いものを選ぶ
+    pub fn select(&self, headend: Option<SocketAddr>, endpoint: SocketAddr, color: u32) -> Result<&SRv6Policy, Error> {
+        self.candidates(headend, endpoint, color)
+            .into_iter()
+            .next()
+            .ok_or(PathError::NoPolicy { endpoint, color }.into())
+    }
+
+    // 宛先とカラーが一致するポリシーを選択順に並べる。優先度の高い順に、同じ優先度では
+    // カラーの意図に沿った指標（低遅延なら遅延、高帯域なら帯域）で比べ、それでも並ばなければ
+    // 中継ノードの少ない順とする。headend を指定した場合、別の送信元に限定されたポリシーは除く
+    pub fn candidates(&self, headend: Option<SocketAddr>, endpoint: SocketAddr, color: u32) -> Vec<&SRv6Policy> {
+        let intent = Intent::from_color(color);
+        let mut candidates: Vec<&SRv6Policy> = self.policies.iter()
+            .filter(|policy| policy.endpoint == endpoint && policy.color == color)
+            .filter(|policy| match (policy.headend, headend) {
+                (Some(policy_headend), Some(headend)) => policy_headend == headend,
+                _ => true,
+            })
+            .collect();
+        candidates.sort_by_key(|policy| {
+            let metric = match intent {
+                Some(Intent::LowLatency) => policy.metadata.latency_ms.map_or(u64::MAX, u64::from),
+                Some(Intent::HighBandwidth) => policy.metadata.bandwidth_mbps
+                    .map_or(u64::MAX, |bandwidth| u64::from(u32::MAX - bandwidth)),
+                None => 0,
+            };
+            (Reverse(policy.metadata.priority), metric, policy.segment_list.len())
+        });
+        candidates
+    }
+}
+
+// ランダムなUUID（バージョン4）形式のPolicyID
+pub fn generate_policy_id() -> String {
+    let mut bytes: [u8; 16] = rand::thread_rng().gen();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = encode_hex(&bytes);
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+
+    fn endpoint() -> SocketAddr {
+        "[::1]:9100".parse().unwrap()
+    }
+
+    fn segments(count: u16) -> Vec<PolicySegment> {
+        (1..=count)
+            .map(|n| PolicySegment { sid: Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, n), address: SocketAddr::from(([127, 0, 0, 1], 9000 + n)) })
+            .collect()
+    }
+
+    fn policy(color: u32, hops: u16, metadata: PolicyMetadata) -> SRv6Policy {
+        SRv6Policy::new(endpoint(), color, segments(hops)).with_metadata(metadata)
+    }
+
+    fn store(policies: &[&SRv6Policy]) -> PolicyStore {
+        let mut store = PolicyStore::default();
+        for policy in policies {
+            store.insert((*policy).clone()).unwrap();
+        }
+        store
+    }
+
+    #[test]
+    fn parses_colors_and_segments() {
+        assert_eq!(parse_color("low-latency"), Ok(COLOR_LOW_LATENCY));
+        assert_eq!(parse_color("High-Bandwidth"), Ok(COLOR_HIGH_BANDWIDTH));
+        assert_eq!(parse_color("42"), Ok(42));
+        assert!(parse_color("fast").is_err());
+
+        let segment: PolicySegment = "2001:db8::1@[::1]:9001".parse().unwrap();
+        assert_eq!(segment.to_string().parse::<PolicySegment>(), Ok(segment));
+        assert!("2001:db8::1".parse::<PolicySegment>().is_err());
+    }
+
+    #[test]
+    fn select_matches_endpoint_and_color() {
+        let default = policy(COLOR_DEFAULT, 2, PolicyMetadata::default());
+        let low_latency = policy(COLOR_LOW_LATENCY, 3, PolicyMetadata::default());
+        let store = store(&[&default, &low_latency]);
+
+        assert_eq!(store.select(None, endpoint(), COLOR_DEFAULT).unwrap(), &default);
+        assert_eq!(store.select(None, endpoint(), COLOR_LOW_LATENCY).unwrap(), &low_latency);
+        assert_eq!(store.select(None, endpoint(), COLOR_HIGH_BANDWIDTH).unwrap_err().code(), ErrorCode::NoPolicy);
+        assert!(store.select(None, "[::1]:9200".parse().unwrap(), COLOR_DEFAULT).is_err());
+    }
+
+    #[test]
+    fn select_orders_candidates_by_priority_then_intent() {
+        let metadata = |latency_ms, bandwidth_mbps, priority| PolicyMetadata {
+            latency_ms: Some(latency_ms),
+            bandwidth_mbps: Some(bandwidth_mbps),
+            priority,
+        };
+
+        // 低遅延: 同じ優先度なら遅延の小さいもの
+        let slow = policy(COLOR_LOW_LATENCY, 2, metadata(30, 1000, 0));
+        let fast = policy(COLOR_LOW_LATENCY, 2, metadata(10, 100, 0));
+        assert_eq!(store(&[&slow, &fast]).select(None, endpoint(), COLOR_LOW_LATENCY).unwrap(), &fast);
+
+        // 優先度は意図の指標より優先する
+        let preferred = policy(COLOR_LOW_LATENCY, 2, metadata(50, 100, 1));
+        assert_eq!(store(&[&slow, &fast, &preferred]).select(None, endpoint(), COLOR_LOW_LATENCY).unwrap(), &preferred);
+
+        // 高帯域: 帯域の大きいもの
+        let narrow = policy(COLOR_HIGH_BANDWIDTH, 2, metadata(10, 100, 0));
+        let wide = policy(COLOR_HIGH_BANDWIDTH, 2, metadata(30, 1000, 0));
+        assert_eq!(store(&[&narrow, &wide]).select(None, endpoint(), COLOR_HIGH_BANDWIDTH).unwrap(), &wide);
+
+        // 指標で決まらなければ中継ノードの少ないもの
+        let long = policy(COLOR_DEFAULT, 3, PolicyMetadata::default());
+        let short = policy(COLOR_DEFAULT, 1, PolicyMetadata::default());
+        assert_eq!(store(&[&long, &short]).select(None, endpoint(), COLOR_DEFAULT).unwrap(), &short);
+    }
+
+    #[test]
+    fn select_excludes_policies_for_other_headends() {
+        let headend: SocketAddr = "[::1]:9500".parse().unwrap();
+        let other = policy(COLOR_DEFAULT, 1, PolicyMetadata::default()).with_headend("[::1]:9501".parse().unwrap());
+        let any = policy(COLOR_DEFAULT, 3, PolicyMetadata::default());
+        let store = store(&[&other, &any]);
+        assert_eq!(store.select(Some(headend), endpoint(), COLOR_DEFAULT).unwrap(), &any);
+        assert_eq!(store.select(None, endpoint(), COLOR_DEFAULT).unwrap(), &other);
+    }
+
+    #[test]
+    fn rejects_empty_or_too_long_segment_list() {
+        let empty = policy(COLOR_DEFAULT, 0, PolicyMetadata::default());
+        assert_eq!(empty.validate().unwrap_err().code(), ErrorCode::InvalidPolicy);
+        assert_eq!(PolicyStore::default().insert(empty).unwrap_err().code(), ErrorCode::InvalidPolicy);
+
+        let too_long = policy(COLOR_DEFAULT, MAX_HOPS as u16, PolicyMetadata::default());
+        assert_eq!(too_long.validate().unwrap_err().code(), ErrorCode::PathTooLong);
+    }
+
+    #[test]
+    fn insert_replaces_policy_with_same_id() {
+        let original = policy(COLOR_DEFAULT, 1, PolicyMetadata::default());
+        let mut replaced = policy(COLOR_LOW_LATENCY, 2, PolicyMetadata::default());
+        replaced.policy_id = original.policy_id.clone();
+        let mut store = store(&[&original, &replaced]);
+        assert_eq!(store.policies(), [replaced.clone()]);
+        assert_eq!(store.remove(&original.policy_id), Some(replaced));
+        assert!(store.policies().is_empty());
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let path = std::env::temp_dir().join(format!("hornet-policies-{}.json", generate_policy_id()));
+        assert!(PolicyStore::load_or_default(&path).unwrap().policies().is_empty());
+
+        let metadata = PolicyMetadata { latency_ms: Some(5), bandwidth_mbps: None, priority: 2 };
+        let saved = store(&[
+            &policy(COLOR_LOW_LATENCY, 2, metadata).with_headend("[::1]:9500".parse().unwrap()),
+            &policy(COLOR_DEFAULT, 1, PolicyMetadata::default()),
+        ]);
+        saved.save(&path).unwrap();
+        // 一時ファイルは置き換え後に残らない
+        assert!(!path.with_extension("tmp").exists());
+
+        let loaded = PolicyStore::load(&path).unwrap();
+        assert_eq!(loaded.policies(), saved.policies());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn load_rejects_invalid_policies() {
+        let path = std::env::temp_dir().join(format!("hornet-policies-{}.json", generate_policy_id()));
+        let mut empty = policy(COLOR_DEFAULT, 1, PolicyMetadata::default());
+        empty.segment_list.clear();
+        fs::write(&path, serde_json::to_string(&PolicyStore { policies: vec![empty] }).unwrap()).unwrap();
+        assert_eq!(PolicyStore::load(&path).unwrap_err().code(), ErrorCode::InvalidPolicy);
+
+        fs::write(&path, r#"{"policies": [], "extra": 1}"#).unwrap();
+        assert_eq!(PolicyStore::load(&path).unwrap_err().code(), ErrorCode::ConfigParse);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn policy_ids_are_uuid_v4() {
+        let id = generate_policy_id();
+        assert_eq!(id.len(), 36);
+        assert_eq!(&id[14..15], "4");
+        assert_ne!(id, generate_policy_id());
+    }
+}
@@ -0,0 +1,456 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::error::{ConfigError, Error, PathError};
+use crate::onion::MAX_HOPS;
+use crate::policy::{PolicyMetadata, SRv6Policy};
+use crate::topology::{PathConstraints, Topology, TopologyPath};
+
+// 定数（README §3.4.2 のデフォルト重み）
+pub const DEFAULT_WEIGHT_LATENCY: f64 = 0.3;
+pub const DEFAULT_WEIGHT_BANDWIDTH: f64 = 0.25;
+pub const DEFAULT_WEIGHT_PACKET_LOSS: f64 = 0.2;
+pub const DEFAULT_WEIGHT_NODE_TRUST: f64 = 0.15;
+pub const DEFAULT_WEIGHT_PATH_DIVERSITY: f64 = 0.1;
+pub const CANDIDATES_PER_PATH: usize = 4; // 選ぶ経路1本あたりにK最短経路で集める候補数
+pub const DEFAULT_PATH_COUNT: usize = 3;  // 送信者が選ぶ経路数（優先度1の経路と代替経路）
+
+// 経路評価の重み（README §3.4.2）
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScoreWeights {
+    #[serde(default = "default_weight_latency")]
+    pub latency: f64,
+    #[serde(default = "default_weight_bandwidth")]
+    pub bandwidth: f64,
+    #[serde(default = "default_weight_packet_loss")]
+    pub packet_loss: f64,
+    #[serde(default = "default_weight_node_trust")]
+    pub node_trust: f64,
+    #[serde(default = "default_weight_path_diversity")]
+    pub path_diversity: f64,
+}
+
+// 経路選択の設定（トポロジーファイルの path_selection）
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PathSelection {
+    #[serde(default = "default_path_count")]
+    pub paths: usize,
+    #[serde(default)]
+    pub constraints: PathConstraints,
+    #[serde(default)]
+    pub weights: ScoreWeights,
+}
+
+// メトリクスごとの値（正規化値、または重みを掛けた寄与）
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MetricScores {
+    pub latency: f64,
+    pub bandwidth: f64,
+    pub packet_loss: f64,
+    pub node_trust: f64,
+    pub path_diversity: f64,
+}
+
+// 経路のスコアと内訳
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PathScore {
+    pub normalized: MetricScores,    // 0〜1に正規化したメトリクス（大きいほど良い）
+    pub contributions: MetricScores, // 正規化値に重みを掛けたもの
+    pub total: f64,                  // PathScore（contributions の合計）
+}
+
+// 選択順に並んだ経路（先頭が優先度1）
+#[derive(Clone, Debug, PartialEq)]
+pub struct RankedPath {
+    pub priority: u8,
+    pub path: TopologyPath,
+    pub score: PathScore,
+}
+
+impl Default for ScoreWeights {
+    fn default() -> Self {
+        Self {
+            latency: DEFAULT_WEIGHT_LATENCY,
+            bandwidth: DEFAULT_WEIGHT_BANDWIDTH,
+            packet_loss: DEFAULT_WEIGHT_PACKET_LOSS,
+            node_trust: DEFAULT_WEIGHT_NODE_TRUST,
+            path_diversity: DEFAULT_WEIGHT_PATH_DIVERSITY,
+        }
+    }
+}
+
+impl Default for PathSelection {
+    fn default() -> Self {
+        Self {
+            paths: DEFAULT_PATH_COUNT,
+            constraints: PathConstraints::default(),
+            weights: ScoreWeights::default(),
+        }
+    }
+}
+
+impl PathSelection {
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut problems = Vec::new();
+        if !(1..=usize::from(u8::MAX)).contains(&self.paths) {
+            problems.push(format!("path_selection.paths は1〜{}の範囲で指定してください: {}", u8::MAX, self.paths));
+        }
+        if !(1..MAX_HOPS).contains(&self.constraints.max_relays) {
+            problems.push(format!("path_selection.constraints.max_relays は1〜{}の範囲で指定してください: {}",
+                                  MAX_HOPS - 1, self.constraints.max_relays));
+        }
+        if let Err(Error::Config(ConfigError::Invalid(weight_problems))) = self.weights.validate() {
+            problems.extend(weight_problems.into_iter().map(|problem| format!("path_selection.weights: {}", problem)));
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems).into())
+        }
+    }
+}
+
+impl ScoreWeights {
+    // 各重みが0以上の有限値で、合計が正であること
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut problems = Vec::new();
+        for (name, weight) in self.entries() {
+            if !weight.is_finite() || weight < 0.0 {
+                problems.push(format!("重み {} は0以上の数値が必要です: {}", name, weight));
+            }
+        }
+        if problems.is_empty() && self.entries().iter().map(|(_, weight)| weight).sum::<f64>() <= 0.0 {
+            problems.push("重みの合計は正の値が必要です".to_string());
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems).into())
+        }
+    }
+
+    fn entries(&self) -> [(&'static str, f64); 5] {
+        [
+            ("latency", self.latency),
+            ("bandwidth", self.bandwidth),
+            ("packet_loss", self.packet_loss),
+            ("node_trust", self.node_trust),
+            ("path_diversity", self.path_diversity),
+        ]
+    }
+
+    fn apply(&self, normalized: &MetricScores) -> MetricScores {
+        MetricScores {
+            latency: self.latency * normalized.latency,
+            bandwidth: self.bandwidth * normalized.bandwidth,
+            packet_loss: self.packet_loss * normalized.packet_loss,
+            node_trust: self.node_trust * normalized.node_trust,
+            path_diversity: self.path_diversity * normalized.path_diversity,
+        }
+    }
+}
+
+// "名前=値" のカンマ区切り。指定しなかった重みはデフォルト値とする（例: latency=0.5,path_diversity=0.3）
+impl FromStr for ScoreWeights {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut weights = Self::default();
+        for item in s.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (name, value) = item
+                .split_once('=')
+                .ok_or_else(|| format!("名前=値 の形式で指定してください: {}", item))?;
+            let value: f64 = value.trim().parse().map_err(|_| format!("重みが不正です: {}", item))?;
+            let weight = match name.trim() {
+                "latency" => &mut weights.latency,
+                "bandwidth" => &mut weights.bandwidth,
+                "packet_loss" => &mut weights.packet_loss,
+                "node_trust" => &mut weights.node_trust,
+                "path_diversity" => &mut weights.path_diversity,
+                other => {
+                    return Err(format!(
+                        "不明な重みです: {} (使用可能: latency, bandwidth, packet_loss, node_trust, path_diversity)",
+                        other));
+                },
+            };
+            *weight = value;
+        }
+        weights.validate().map_err(|e| e.to_string())?;
+        Ok(weights)
+    }
+}
+
+impl fmt::Display for ScoreWeights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries = self.entries().map(|(name, weight)| format!("{}={}", name, weight));
+        write!(f, "{}", entries.join(","))
+    }
+}
+
+impl MetricScores {
+    pub fn sum(&self) -> f64 {
+        self.latency + self.bandwidth + self.packet_loss + self.node_trust + self.path_diversity
+    }
+}
+
+// ログに出す内訳（メトリクスごとの寄与）
+impl fmt::Display for PathScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let c = &self.contributions;
+        write!(f, "{:.3} (遅延 {:.3}, 帯域 {:.3}, ロス {:.3}, 信頼度 {:.3}, 多様性 {:.3})",
+               self.total, c.latency, c.bandwidth, c.packet_loss, c.node_trust, c.path_diversity)
+    }
+}
+
+impl RankedPath {
+    // 優先度1の経路ほどSRv6ポリシーの優先度（大きいほど優先）を高くする
+    pub fn to_policy(&self, topology: &Topology, color: u32) -> Result<SRv6Policy, Error> {
+        let policy = topology.to_policy(&self.path, color)?;
+        let metadata = PolicyMetadata {
+            priority: u8::MAX - (self.priority - 1),
+            ..policy.metadata
+        };
+        Ok(policy.with_metadata(metadata))
+    }
+}
+
+// 中継ノード集合のJaccard距離。送信元と宛先はすべての経路に共通なので比較に含めない
+pub fn node_diversity(a: &TopologyPath, b: &TopologyPath) -> f64 {
+    jaccard_distance(a.relays().iter().collect(), b.relays().iter().collect())
+}
+
+// 有向エッジ集合のJaccard距離
+pub fn edge_diversity(a: &TopologyPath, b: &TopologyPath) -> f64 {
+    jaccard_distance(a.edges().collect(), b.edges().collect())
+}
+
+// 選択済みの経路集合に対する多様性。選択済みの経路がなければ1とする
+pub fn diversity(path: &TopologyPath, selected: &[RankedPath]) -> f64 {
+    selected.iter()
+        .map(|other| node_diversity(path, &other.path).min(edge_diversity(path, &other.path)))
+        .fold(1.0, f64::min)
+}
+
+// 候補経路を多目的評価し、最大 count 本を選択順に返す（README §3.4.2）。
+// 多様性は選択済みの経路に依存するため、1本選ぶごとに残りの候補を評価し直す
+pub fn rank_paths(candidates: Vec<TopologyPath>, weights: &ScoreWeights, count: usize) -> Vec<RankedPath> {
+    let count = count.min(usize::from(u8::MAX));
+    let ranges = MetricRanges::new(&candidates);
+    let mut remaining = candidates;
+    let mut selected: Vec<RankedPath> = Vec::with_capacity(count.min(remaining.len()));
+
+    while selected.len() < count && !remaining.is_empty() {
+        let scores: Vec<PathScore> = remaining.iter()
+            .map(|path| {
+                let normalized = ranges.normalize(path, diversity(path, &selected));
+                let contributions = weights.apply(&normalized);
+                PathScore { normalized, contributions, total: contributions.sum() }
+            })
+            .collect();
+        // 同点なら候補の並び（遅延の小さい順）で先のものを選ぶ
+        let best = scores.iter()
+            .enumerate()
+            .reduce(|best, candidate| if candidate.1.total > best.1.total { candidate } else { best })
+            .map(|(index, _)| index)
+            .expect("候補あり");
+
+        let path = remaining.remove(best);
+        let score = scores[best];
+        let priority = selected.len() as u8 + 1;
+        debug!(priority,
+               nodes = %path.nodes.join(" -> "),
+               score = %score,
+               latency_ms = path.latency_ms,
+               bandwidth_mbps = path.bandwidth_mbps,
+               loss = path.loss,
+               trust = path.min_trust_score,
+               diversity = score.normalized.path_diversity,
+               "経路を選択");
+        selected.push(RankedPath { priority, path, score });
+    }
+
+    selected
+}
+
+// K最短経路で候補を集め、多様性を考慮して最大 count 本を選ぶ。1本も見つからなければエラー
+pub fn select_paths(topology: &Topology,
+                    source: &str,
+                    destination: &str,
+                    count: usize,
+                    constraints: &PathConstraints,
+                    weights: &ScoreWeights) -> Result<Vec<RankedPath>, Error> {
+    weights.validate()?;
+    let candidates = topology.k_shortest_paths(source,
+                                               destination,
+                                               count.saturating_mul(CANDIDATES_PER_PATH),
+                                               constraints)?;
+    let ranked = rank_paths(candidates, weights, count);
+    if ranked.is_empty() {
+        return Err(PathError::NoPath { from: source.to_string(), to: destination.to_string() }.into());
+    }
+    Ok(ranked)
+}
+
+// 候補全体での各メトリクスの最小値・最大値
+struct MetricRanges {
+    latency: (f64, f64),
+    bandwidth: (f64, f64),
+    loss: (f64, f64),
+    trust: (f64, f64),
+}
+
+impl MetricRanges {
+    fn new(paths: &[TopologyPath]) -> Self {
+        let range = |metric: fn(&TopologyPath) -> f64| {
+            paths.iter()
+                .map(metric)
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| (min.min(value), max.max(value)))
+        };
+        Self {
+            latency: range(|path| path.latency_ms),
+            bandwidth: range(|path| path.bandwidth_mbps),
+            loss: range(|path| path.loss),
+            trust: range(|path| path.min_trust_score),
+        }
+    }
+
+    // 遅延とロスは低いほど良いので反転する（逆メトリクス）
+    fn normalize(&self, path: &TopologyPath, diversity: f64) -> MetricScores {
+        MetricScores {
+            latency: normalize(path.latency_ms, self.latency, true),
+            bandwidth: normalize(path.bandwidth_mbps, self.bandwidth, false),
+            packet_loss: normalize(path.loss, self.loss, true),
+            node_trust: normalize(path.min_trust_score, self.trust, false),
+            path_diversity: diversity,
+        }
+    }
+}
+
+// 最も良い値を1、最も悪い値を0とする。全候補が同じ値ならどれも最良として1とする
+fn normalize(value: f64, (min, max): (f64, f64), lower_is_better: bool) -> f64 {
+    if max <= min {
+        return 1.0;
+    }
+    let normalized = ((value - min) / (max - min)).clamp(0.0, 1.0);
+    if lower_is_better {
+        1.0 - normalized
+    } else {
+        normalized
+    }
+}
+
+fn jaccard_distance<T: Eq + std::hash::Hash>(a: HashSet<T>, b: HashSet<T>) -> f64 {
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    1.0 - a.intersection(&b).count() as f64 / union as f64
+}
+
+fn default_path_count() -> usize {
+    DEFAULT_PATH_COUNT
+}
+
+fn default_weight_latency() -> f64 {
+    DEFAULT_WEIGHT_LATENCY
+}
+
+fn default_weight_bandwidth() -> f64 {
+    DEFAULT_WEIGHT_BANDWIDTH
+}
+
+fn default_weight_packet_loss() -> f64 {
+    DEFAULT_WEIGHT_PACKET_LOSS
+}
+
+fn default_weight_node_trust() -> f64 {
+    DEFAULT_WEIGHT_NODE_TRUST
+}
+
+fn default_weight_path_diversity() -> f64 {
+    DEFAULT_WEIGHT_PATH_DIVERSITY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Role;
+    use crate::error::PathError;
+    use crate::topology::TopologyNode;
+
+    fn path(nodes: &[&str], latency_ms: f64) -> TopologyPath {
+        TopologyPath {
+            nodes: nodes.iter().map(|id| id.to_string()).collect(),
+            latency_ms,
+            bandwidth_mbps: 100.0,
+            loss: 0.0,
+            min_trust_score: 0.5,
+        }
+    }
+
+    // p1 と p2 は中継ノード a を共有し、p3 はどちらとも重ならない
+    fn candidates() -> Vec<TopologyPath> {
+        vec![
+            path(&["s", "a", "b", "d"], 10.0),
+            path(&["s", "a", "c", "d"], 11.0),
+            path(&["s", "x", "y", "d"], 12.0),
+        ]
+    }
+
+    fn weights(path_diversity: f64) -> ScoreWeights {
+        ScoreWeights { latency: 1.0, bandwidth: 0.0, packet_loss: 0.0, node_trust: 0.0, path_diversity }
+    }
+
+    fn ranked_relays(weights: &ScoreWeights, count: usize) -> Vec<Vec<String>> {
+        rank_paths(candidates(), weights, count).into_iter().map(|ranked| ranked.path.relays().to_vec()).collect()
+    }
+
+    #[test]
+    fn diversity_measures_shared_relays_and_edges() {
+        let [p1, p2, p3] = <[TopologyPath; 3]>::try_from(candidates()).unwrap();
+        assert!((node_diversity(&p1, &p2) - 2.0 / 3.0).abs() < 1e-9);
+        assert!((edge_diversity(&p1, &p2) - 0.8).abs() < 1e-9);
+        assert_eq!(node_diversity(&p1, &p3), 1.0);
+        assert_eq!(node_diversity(&p1, &p1), 0.0);
+    }
+
+    #[test]
+    fn ranks_by_score_without_diversity() {
+        assert_eq!(ranked_relays(&weights(0.0), 3), [["a", "b"], ["a", "c"], ["x", "y"]]);
+    }
+
+    #[test]
+    fn rescores_diversity_after_each_selection() {
+        let ranked = rank_paths(candidates(), &weights(2.0), 3);
+        let relays: Vec<_> = ranked.iter().map(|ranked| ranked.path.relays().to_vec()).collect();
+        assert_eq!(relays, [["a", "b"], ["x", "y"], ["a", "c"]]);
+        assert_eq!(ranked.iter().map(|ranked| ranked.priority).collect::<Vec<_>>(), [1, 2, 3]);
+
+        // 1本目は比較対象がないため多様性1、2本目は1本目と重ならない
+        assert_eq!(ranked[0].score.normalized.path_diversity, 1.0);
+        assert_eq!(ranked[1].score.normalized.path_diversity, 1.0);
+        assert!((ranked[2].score.normalized.path_diversity - 2.0 / 3.0).abs() < 1e-9);
+        assert!((ranked[0].score.total - ranked[0].score.contributions.sum()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn returns_at_most_count_paths() {
+        assert_eq!(ranked_relays(&weights(2.0), 2).len(), 2);
+        assert!(rank_paths(Vec::new(), &weights(2.0), 3).is_empty());
+    }
+
+    #[test]
+    fn select_paths_reports_missing_path() {
+        let mut topology = Topology::new();
+        topology.add_node(TopologyNode::new("s").with_role(Role::Entry));
+        topology.add_node(TopologyNode::new("d").with_role(Role::Exit));
+        let result = select_paths(&topology, "s", "d", 3, &PathConstraints::default(), &ScoreWeights::default());
+        assert!(matches!(result, Err(Error::Path(PathError::NoPath { .. }))));
+    }
+}
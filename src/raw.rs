@@ -0,0 +1,180 @@
+// 生ソケット（AF_INET6 / SOCK_RAW）によるデータプレーン
+// 有効にするには raw-socket フィーチャーとLinux、CAP_NET_RAW が必要。
+// ソケットはルーティングヘッダー（Next Header 43）を持つIPv6パケットと、End.DT6でSRHを
+// 取り除かれたOnionパケット（Next Header 253）を受信し、IPV6_HDRINCL により自前で組み立てた
+// IPv6ヘッダーごと送信する。
+// カーネルのSRv6処理（seg6_enabled）は無効のままにしておくこと。有効だとカーネル自身がSRHを処理してしまう。
+
+use std::net::Ipv6Addr;
+
+#[cfg(all(feature = "raw-socket", target_os = "linux"))]
+pub use self::linux::RawSocket;
+
+#[cfg(not(all(feature = "raw-socket", target_os = "linux")))]
+pub use self::unsupported::RawSocket;
+
+// 生ソケットで受信したパケット（IPv6ヘッダーはカーネルが取り除いている）
+pub struct RawPacket {
+    pub data: Vec<u8>,                    // IPv6ヘッダーより後ろ
+    pub source: Ipv6Addr,
+    pub destination: Option<Ipv6Addr>,    // IPV6_PKTINFO から取得した宛先アドレス
+    pub next_header: u8,                  // IPv6ヘッダーのNext Header
+}
+
+#[cfg(all(feature = "raw-socket", target_os = "linux"))]
+mod linux {
+    use std::io;
+    use std::mem;
+    use std::net::{Ipv6Addr, SocketAddrV6};
+    use std::os::fd::AsRawFd;
+
+    use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+    use tokio::io::unix::AsyncFd;
+
+    use super::RawPacket;
+    use crate::error::{Error, InitError};
+    use crate::ipv6::{IPPROTO_ROUTING, ONION_NEXT_HEADER};
+
+    pub struct RawSocket {
+        routing: AsyncFd<Socket>, // SRH付きパケットの受信と全パケットの送信
+        onion: AsyncFd<Socket>,   // SRHを取り除かれたOnionパケットの受信
+    }
+
+    impl RawSocket {
+        pub fn open() -> Result<Self, Error> {
+            Ok(Self {
+                routing: open_socket(IPPROTO_ROUTING).map_err(InitError::RawSocket)?,
+                onion: open_socket(ONION_NEXT_HEADER).map_err(InitError::RawSocket)?,
+            })
+        }
+
+        pub async fn recv_packet(&self) -> io::Result<RawPacket> {
+            tokio::select! {
+                received = recv_packet(&self.routing, IPPROTO_ROUTING) => received,
+                received = recv_packet(&self.onion, ONION_NEXT_HEADER) => received,
+            }
+        }
+
+        // IPv6ヘッダーを含むパケットを宛先アドレスへ送信
+        pub async fn send_packet(&self, packet: &[u8], destination: Ipv6Addr) -> io::Result<()> {
+            let address = SockAddr::from(SocketAddrV6::new(destination, 0, 0, 0));
+            loop {
+                let mut guard = self.routing.writable().await?;
+                match guard.try_io(|socket| socket.get_ref().send_to(packet, &address)) {
+                    Ok(result) => return result.map(|_| ()),
+                    Err(_would_block) => continue,
+                }
+            }
+        }
+    }
+
+    fn open_socket(protocol: u8) -> io::Result<AsyncFd<Socket>> {
+        let socket = Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::from(protocol as i32)))?;
+        socket.set_nonblocking(true)?;
+        set_ipv6_option(&socket, libc::IPV6_HDRINCL)?;
+        // SIDの照合には実際の宛先アドレスが必要なため、受信時に IPV6_PKTINFO を受け取る
+        set_ipv6_option(&socket, libc::IPV6_RECVPKTINFO)?;
+        // SAFETY: socket2::Socket は有効な記述子を所有し、AsyncFd に所有されている間は閉じたり差し替えたりしない
+        unsafe { AsyncFd::register(socket) }.map_err(|e| e.into_parts().1)
+    }
+
+    async fn recv_packet(socket: &AsyncFd<Socket>, next_header: u8) -> io::Result<RawPacket> {
+        let mut buf = vec![0u8; 65536];
+        loop {
+            let mut guard = socket.readable().await?;
+            match guard.try_io(|socket| recv_msg(socket.get_ref(), &mut buf)) {
+                Ok(result) => {
+                    let (len, source, destination) = result?;
+                    buf.truncate(len);
+                    return Ok(RawPacket { data: buf, source, destination, next_header });
+                },
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    // recvmsg で受信し、送信元アドレスと補助データ（IPV6_PKTINFO）の宛先アドレスを返す
+    fn recv_msg(socket: &Socket, buf: &mut [u8]) -> io::Result<(usize, Ipv6Addr, Option<Ipv6Addr>)> {
+        // SAFETY: sockaddr_in6 と msghdr はゼロ初期化が有効な値のC構造体
+        let mut source: libc::sockaddr_in6 = unsafe { mem::zeroed() };
+        let mut control = [0u64; 16]; // cmsghdr のアラインメントを満たす補助データ領域
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_name = &mut source as *mut libc::sockaddr_in6 as *mut libc::c_void;
+        msg.msg_namelen = mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+
+        // SAFETY: msg が指す各領域は呼び出し中有効で、長さも正しく設定している
+        let len = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut destination = None;
+        // SAFETY: CMSG_* はrecvmsgが設定した msg_controllen の範囲内のみを走査する
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::IPPROTO_IPV6 && (*cmsg).cmsg_type == libc::IPV6_PKTINFO {
+                    let info = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::in6_pktinfo);
+                    destination = Some(Ipv6Addr::from(info.ipi6_addr.s6_addr));
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+        Ok((len as usize, Ipv6Addr::from(source.sin6_addr.s6_addr), destination))
+    }
+
+    fn set_ipv6_option(socket: &Socket, option: libc::c_int) -> io::Result<()> {
+        let enable: libc::c_int = 1;
+        // SAFETY: 有効なソケット記述子に対し、c_int サイズの値を渡している
+        let result = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::IPPROTO_IPV6,
+                option,
+                &enable as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[cfg(not(all(feature = "raw-socket", target_os = "linux")))]
+mod unsupported {
+    use std::io;
+    use std::net::Ipv6Addr;
+
+    use super::RawPacket;
+    use crate::error::{Error, InitError};
+
+    // raw-socket フィーチャー無効時のスタブ。open() は常に失敗する
+    pub struct RawSocket {
+        _private: (),
+    }
+
+    impl RawSocket {
+        pub fn open() -> Result<Self, Error> {
+            Err(InitError::RawSocketUnsupported.into())
+        }
+
+        pub async fn recv_packet(&self) -> io::Result<RawPacket> {
+            std::future::pending().await
+        }
+
+        pub async fn send_packet(&self, _packet: &[u8], _destination: Ipv6Addr) -> io::Result<()> {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "生ソケットは無効です"))
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use crate::error::{Error, SessionError};
+
+// 定数
+pub const DEFAULT_REPLAY_WINDOW_SIZE: usize = 64;
+
+// スライディングウィンドウ方式のリプレイ検出（IPsecアンチリプレイと同様）
+// ビットマップはシーケンス番号を法としたリングとして扱う
+pub struct ReplayWindow {
+    size: u64,
+    highest: u64, // 受理済みの最大シーケンス番号
+    bitmap: Vec<u64>,
+}
+
+impl ReplayWindow {
+    pub fn new(size: usize) -> Self {
+        let words = size.max(1).div_ceil(64);
+        Self {
+            size: (words * 64) as u64,
+            highest: 0,
+            bitmap: vec![0; words],
+        }
+    }
+    
+    fn bit(&self, sequence: u64) -> (usize, u64) {
+        let index = sequence % self.size;
+        ((index / 64) as usize, 1 << (index % 64))
+    }
+    
+    // 受理可能かを判定する（ウィンドウは更新しない）
+    pub fn check(&self, sequence: u64) -> Result<(), Error> {
+        if sequence == 0 {
+            return Err(SessionError::InvalidSequence(sequence).into());
+        }
+        if sequence > self.highest {
+            return Ok(());
+        }
+        if self.highest - sequence >= self.size {
+            return Err(SessionError::StaleSequence(sequence).into());
+        }
+        
+        let (word, mask) = self.bit(sequence);
+        if self.bitmap[word] & mask != 0 {
+            return Err(SessionError::DuplicateSequence(sequence).into());
+        }
+        Ok(())
+    }
+    
+    // 認証済みパケットのシーケンス番号をウィンドウに記録する
+    pub fn update(&mut self, sequence: u64) -> Result<(), Error> {
+        self.check(sequence)?;
+        
+        if sequence > self.highest {
+            // ウィンドウを前進させ、新たに範囲に入った番号のビットを消去
+            if sequence - self.highest >= self.size {
+                self.bitmap.iter_mut().for_each(|word| *word = 0);
+            } else {
+                for skipped in self.highest + 1..sequence {
+                    let (word, mask) = self.bit(skipped);
+                    self.bitmap[word] &= !mask;
+                }
+            }
+            self.highest = sequence;
+        }
+        
+        let (word, mask) = self.bit(sequence);
+        self.bitmap[word] |= mask;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+
+    fn code(result: Result<(), Error>) -> ErrorCode {
+        result.expect_err("拒否されるはず").code()
+    }
+
+    #[test]
+    fn accepts_increasing_and_out_of_order_sequences() {
+        let mut window = ReplayWindow::new(64);
+        for sequence in [1, 2, 5, 3, 4] {
+            window.update(sequence).unwrap();
+        }
+        assert_eq!(window.highest, 5);
+    }
+
+    #[test]
+    fn rejects_zero_sequence() {
+        let mut window = ReplayWindow::new(64);
+        assert_eq!(code(window.update(0)), ErrorCode::InvalidSequence);
+    }
+
+    #[test]
+    fn rejects_duplicates() {
+        let mut window = ReplayWindow::new(64);
+        window.update(10).unwrap();
+        window.update(7).unwrap();
+        assert_eq!(code(window.update(10)), ErrorCode::Replay);
+        assert_eq!(code(window.update(7)), ErrorCode::Replay);
+        // check はウィンドウを更新しない
+        window.check(8).unwrap();
+        window.check(8).unwrap();
+    }
+
+    #[test]
+    fn rejects_sequences_older_than_window() {
+        let mut window = ReplayWindow::new(64);
+        window.update(100).unwrap();
+        assert_eq!(code(window.update(36)), ErrorCode::StaleSequence);
+        window.update(37).unwrap();
+    }
+
+    #[test]
+    fn slide_clears_bits_of_skipped_sequences() {
+        let mut window = ReplayWindow::new(64);
+        window.update(1).unwrap();
+        window.update(3).unwrap();
+        // 65 はリング上で 1 と同じビット。前進時に消去されるので受理できる
+        window.update(66).unwrap();
+        window.update(65).unwrap();
+        assert_eq!(code(window.update(2)), ErrorCode::StaleSequence);
+        assert_eq!(code(window.update(65)), ErrorCode::Replay);
+    }
+
+    #[test]
+    fn large_jump_resets_window() {
+        let mut window = ReplayWindow::new(64);
+        for sequence in 1..=64 {
+            window.update(sequence).unwrap();
+        }
+        window.update(1000).unwrap();
+        window.update(999).unwrap();
+        window.update(937).unwrap();
+        assert_eq!(code(window.update(936)), ErrorCode::StaleSequence);
+    }
+
+    #[test]
+    fn size_is_rounded_up_to_whole_words() {
+        let window = ReplayWindow::new(10);
+        assert_eq!(window.size, 64);
+        assert_eq!(ReplayWindow::new(0).size, 64);
+        assert_eq!(ReplayWindow::new(65).size, 128);
+    }
+}
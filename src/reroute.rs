@@ -0,0 +1,529 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::SessionKeys;
+use crate::error::{CommunicationError, Error};
+use crate::onion::{ONION_HEADER_SIZE, PAYLOAD_SIZE};
+use crate::pathlist::{PathEntry, PathList};
+use crate::policy::SRv6Policy;
+use crate::ranking::{rank_paths, PathScore, ScoreWeights};
+use crate::topology::{TopologyPath, DEFAULT_TRUST_SCORE};
+
+// 定数（README §3.5.2）
+pub const REACHABILITY_FAILURE_THRESHOLD: u32 = 3; // 連続して応答がなければ障害とみなす回数
+pub const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_secs(1);
+pub const ALTERNATIVE_ONION_CACHE_SIZE: usize = 64; // セッションごとに代替経路のOnionを保持するパケット数
+
+// 経路変更のトリガー
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RerouteReason {
+    NoRoute = 0,     // 次ホップのSIDへの経路がない
+    Unreachable = 1, // 次ホップがreachabilityチェックに連続して失敗した
+    NodeFailure = 2, // 明示的なノード障害通知を受けた
+}
+
+impl RerouteReason {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(RerouteReason::NoRoute),
+            1 => Some(RerouteReason::Unreachable),
+            2 => Some(RerouteReason::NodeFailure),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RerouteReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RerouteReason::NoRoute => write!(f, "no_route"),
+            RerouteReason::Unreachable => write!(f, "unreachable"),
+            RerouteReason::NodeFailure => write!(f, "node_failure"),
+        }
+    }
+}
+
+// 経路変更を引き起こした転送先の障害
+#[derive(Clone, Copy, Debug)]
+pub struct Failure {
+    pub sid: Ipv6Addr,               // 到達できない次ホップのSID
+    pub address: Option<SocketAddr>, // その転送先（経路がなければNone）
+    pub reason: RerouteReason,
+}
+
+// 隣接ノード（転送先）の到達性
+#[derive(Clone, Copy, Debug, Default)]
+struct NeighborState {
+    pending: bool,  // 直前のプローブに応答がない
+    failures: u32,  // 連続して応答がなかったプローブ数
+    reported: bool, // 明示的な障害通知を受けた
+}
+
+// 隣接ノードの到達性の管理（プローブの送信先と応答の記録）
+#[derive(Debug, Default)]
+pub struct Neighbors {
+    states: HashMap<SocketAddr, NeighborState>,
+}
+
+impl Neighbors {
+    pub fn add(&mut self, address: SocketAddr) {
+        self.states.entry(address).or_default();
+    }
+
+    // 前回のプローブに応答がなかったノードの失敗を数え、次のプローブの送信先を返す
+    pub fn probe_targets(&mut self) -> Vec<SocketAddr> {
+        self.states.iter_mut()
+            .map(|(address, state)| {
+                if state.pending {
+                    state.failures = state.failures.saturating_add(1);
+                }
+                state.pending = true;
+                *address
+            })
+            .collect()
+    }
+
+    // プローブ応答を受けたら障害状態を解除する。障害から復旧した場合はtrue
+    pub fn record_reply(&mut self, address: SocketAddr) -> bool {
+        let Some(state) = self.states.get_mut(&address) else {
+            return false;
+        };
+        let recovered = state.reported || state.failures >= REACHABILITY_FAILURE_THRESHOLD;
+        *state = NeighborState::default();
+        recovered
+    }
+
+    // 明示的なノード障害通知（次にプローブへ応答するまで障害とみなす）
+    pub fn mark_failed(&mut self, address: SocketAddr) {
+        self.states.entry(address).or_default().reported = true;
+    }
+
+    // 障害とみなす場合はその理由
+    pub fn failure(&self, address: &SocketAddr) -> Option<RerouteReason> {
+        let state = self.states.get(address)?;
+        if state.reported {
+            Some(RerouteReason::NodeFailure)
+        } else if state.failures >= REACHABILITY_FAILURE_THRESHOLD {
+            Some(RerouteReason::Unreachable)
+        } else {
+            None
+        }
+    }
+}
+
+// 選ばれた代替経路のうち、経路変更したノードより後ろの部分
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alternative {
+    pub path_id: String,
+    pub segments: Vec<Ipv6Addr>, // 置き換え後の未処理セグメント（経路順）
+    pub nodes: Vec<SocketAddr>,  // segments に対応する中継ノードのアドレス
+    pub score: Option<PathScore>, // 再評価した場合のスコア
+}
+
+// 代替経路の選択（README §3.5.3）。自ノードを通り、自ノードより後ろに障害ノードも
+// 使用不可のノードも含まない経路を候補とし、スコアの高いものを選ぶ。
+// 既に切り替えた経路（preferred）が候補に残っていれば、経路を揺らさないようそれを使う
+pub fn select_alternative_path(path_list: &PathList,
+                               is_local: impl Fn(&Ipv6Addr) -> bool,
+                               failed_sid: &Ipv6Addr,
+                               failed_address: Option<SocketAddr>,
+                               is_available: impl Fn(&SocketAddr) -> bool,
+                               preferred: Option<&str>,
+                               weights: &ScoreWeights) -> Option<Alternative> {
+    let candidates: Vec<(&PathEntry, usize)> = path_list.paths.iter()
+        .filter_map(|entry| {
+            let position = entry.srv6_segments.iter().position(&is_local)?;
+            let segments = &entry.srv6_segments[position + 1..];
+            let nodes = entry.nodes.get(position + 1..)?;
+            let usable = !segments.is_empty()
+                && !segments.contains(failed_sid)
+                && failed_address.is_none_or(|failed| !nodes.contains(&failed))
+                && nodes.iter().all(&is_available);
+            usable.then_some((entry, position + 1))
+        })
+        .collect();
+
+    let alternative = |entry: &PathEntry, start: usize, score: Option<PathScore>| Alternative {
+        path_id: entry.path_id.clone(),
+        segments: entry.srv6_segments[start..].to_vec(),
+        nodes: entry.nodes[start..].to_vec(),
+        score,
+    };
+    if let Some((entry, start)) = candidates.iter().find(|(entry, _)| Some(entry.path_id.as_str()) == preferred) {
+        return Some(alternative(entry, *start, None));
+    }
+
+    // 現在のネットワーク状況で再評価する。パスリストの経路は優先度順に並んでおり、同点なら優先度の高いものを選ぶ
+    let paths = candidates.iter().map(|(entry, _)| entry_path(entry)).collect();
+    let best = rank_paths(paths, weights, 1).into_iter().next()?;
+    let (entry, start) = candidates.iter().find(|(entry, _)| entry_path(entry) == best.path)?;
+    Some(alternative(entry, *start, Some(best.score)))
+}
+
+// 送信者が代替経路ごとに構築するOnionの経路: 分岐点（パスリストを受け取る最初の中継ノード）より後ろの部分。
+// 分岐点の中継ノードは後続ノードの鍵を持たないため、経路を切り替えるには送信者が事前に構築した
+// Onionヘッダーとペイロード（後続ノードの層とMACの連鎖）が必要になる
+#[derive(Clone)]
+pub struct AlternativeRoute {
+    pub path_id: String,
+    pub segments: Vec<Ipv6Addr>, // 分岐点より後ろの中継ノードのSID（経路順）
+    pub keys: Vec<SessionKeys>,  // segments の中継ノードと受信者の鍵
+}
+
+// 主経路の最初の中継ノードを通る代替経路について、その中継ノードより後ろの経路と鍵を集める。
+// 分岐点を通らない経路や、鍵のない（ハンドシェイクできなかった）ノードを含む経路は使えないので除く
+pub fn alternative_routes(primary: &SRv6Policy,
+                          alternatives: &[SRv6Policy],
+                          keys: &HashMap<SocketAddr, SessionKeys>) -> Vec<AlternativeRoute> {
+    let Some(branch) = primary.segment_list.first() else {
+        return Vec::new();
+    };
+    alternatives.iter()
+        .filter_map(|policy| {
+            let position = policy.segment_list.iter().position(|segment| segment.sid == branch.sid)?;
+            let suffix = &policy.segment_list[position + 1..];
+            if suffix.is_empty() {
+                return None;
+            }
+            let keys = policy.node_addresses()[position + 1..].iter()
+                .map(|address| keys.get(address).cloned())
+                .collect::<Option<Vec<_>>>()?;
+            Some(AlternativeRoute {
+                path_id: policy.policy_id.clone(),
+                segments: suffix.iter().map(|segment| segment.sid).collect(),
+                keys,
+            })
+        })
+        .collect()
+}
+
+// 分岐点の中継ノードに送る代替経路のOnion: PathID長(2) | PathID | Onionヘッダー | ペイロード
+// （分岐点とのセッション鍵で暗号化して送る。セッションIDとシーケンス番号はOnionヘッダーに含まれる）
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlternativeOnion {
+    pub path_id: String,
+    pub onion_header: Vec<u8>, // ONION_HEADER_SIZEバイト。MACは代替経路の最初のノード用
+    pub payload: Vec<u8>,      // PAYLOAD_SIZEバイト
+}
+
+impl AlternativeOnion {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(2 + self.path_id.len() + ONION_HEADER_SIZE + PAYLOAD_SIZE);
+        bytes.extend_from_slice(&(self.path_id.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(self.path_id.as_bytes());
+        bytes.extend_from_slice(&self.onion_header);
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let malformed = |reason: &str| CommunicationError::MalformedPacket(format!("代替経路のOnionを解析できません: {}", reason));
+        if bytes.len() < 2 {
+            return Err(malformed("短すぎます").into());
+        }
+        let path_id_len = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+        if bytes.len() != 2 + path_id_len + ONION_HEADER_SIZE + PAYLOAD_SIZE {
+            return Err(malformed("長さが不正です").into());
+        }
+        let path_id = String::from_utf8(bytes[2..2 + path_id_len].to_vec())
+            .map_err(|_| malformed("PathIDがUTF-8ではありません"))?;
+        let (onion_header, payload) = bytes[2 + path_id_len..].split_at(ONION_HEADER_SIZE);
+        Ok(Self {
+            path_id,
+            onion_header: onion_header.to_vec(),
+            payload: payload.to_vec(),
+        })
+    }
+}
+
+// 分岐点の中継ノードが保持する代替経路のOnion（セッション → シーケンス番号 → PathID）
+#[derive(Debug, Default)]
+pub struct AlternativeOnions {
+    sessions: HashMap<u32, BTreeMap<u64, HashMap<String, AlternativeOnion>>>,
+}
+
+impl AlternativeOnions {
+    // 保持するパケット数を超えたら古いシーケンス番号の分から捨てる
+    pub fn insert(&mut self, session_id: u32, sequence: u64, onion: AlternativeOnion) {
+        let sequences = self.sessions.entry(session_id).or_default();
+        sequences.entry(sequence).or_default().insert(onion.path_id.clone(), onion);
+        while sequences.len() > ALTERNATIVE_ONION_CACHE_SIZE {
+            sequences.pop_first();
+        }
+    }
+
+    // パケットの処理時に、そのシーケンス番号の代替経路のOnionをすべて取り出す
+    pub fn take(&mut self, session_id: u32, sequence: u64) -> HashMap<String, AlternativeOnion> {
+        let Some(sequences) = self.sessions.get_mut(&session_id) else {
+            return HashMap::new();
+        };
+        let onions = sequences.remove(&sequence).unwrap_or_default();
+        if sequences.is_empty() {
+            self.sessions.remove(&session_id);
+        }
+        onions
+    }
+
+    // パスリストを失ったセッションの分を捨てる
+    pub fn retain_sessions(&mut self, keep: impl Fn(&u32) -> bool) {
+        self.sessions.retain(|session_id, _| keep(session_id));
+    }
+}
+
+// 評価用にパスリストの経路を表す。指標のない項目は0（信頼度は既定値）とする
+fn entry_path(entry: &PathEntry) -> TopologyPath {
+    let metrics = &entry.metrics;
+    TopologyPath {
+        nodes: entry.nodes.iter().map(|address| address.to_string()).collect(),
+        latency_ms: metrics.latency_ms.map_or(0.0, f64::from),
+        bandwidth_mbps: metrics.bandwidth_mbps.map_or(0.0, f64::from),
+        loss: metrics.reliability.map_or(0.0, |reliability| (1.0 - reliability) * 100.0),
+        min_trust_score: metrics.trust_score.unwrap_or(DEFAULT_TRUST_SCORE),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::derive_keys;
+    use crate::error::ErrorCode;
+    use crate::pathlist::{PathMetrics, DEFAULT_PATH_LIST_LIFETIME};
+    use crate::policy::{PolicySegment, COLOR_DEFAULT};
+
+    fn sid(n: u16) -> Ipv6Addr {
+        Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, n)
+    }
+
+    fn address(n: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 9000 + n))
+    }
+
+    fn entry(path_id: &str, priority: u8, relays: &[u16], latency_ms: u32) -> PathEntry {
+        PathEntry {
+            priority,
+            path_id: path_id.to_string(),
+            nodes: relays.iter().map(|&n| address(n)).collect(),
+            srv6_segments: relays.iter().map(|&n| sid(n)).collect(),
+            metrics: PathMetrics { latency_ms: Some(latency_ms), ..PathMetrics::default() },
+        }
+    }
+
+    // 中継ノード1を共有する経路: 主経路 1→2、代替経路 1→3→4（遅い）と 1→5（速い）、1を通らない 6→7
+    fn path_list() -> PathList {
+        PathList::new(7, vec![
+            entry("primary", 1, &[1, 2], 10),
+            entry("slow", 2, &[1, 3, 4], 50),
+            entry("fast", 3, &[1, 5], 5),
+            entry("other", 4, &[6, 7], 1),
+        ], DEFAULT_PATH_LIST_LIFETIME)
+    }
+
+    fn select(failed: u16, is_available: impl Fn(&SocketAddr) -> bool, preferred: Option<&str>) -> Option<Alternative> {
+        select_alternative_path(&path_list(),
+                                |candidate| *candidate == sid(1),
+                                &sid(failed),
+                                Some(address(failed)),
+                                is_available,
+                                preferred,
+                                &ScoreWeights::default())
+    }
+
+    #[test]
+    fn neighbors_count_unanswered_probe_rounds() {
+        let mut neighbors = Neighbors::default();
+        neighbors.add(address(1));
+        for _ in 0..REACHABILITY_FAILURE_THRESHOLD {
+            assert_eq!(neighbors.probe_targets(), [address(1)]);
+            assert_eq!(neighbors.failure(&address(1)), None);
+        }
+        // 閾値回のプローブに応答がないと、次のラウンドで到達不能とみなす
+        neighbors.probe_targets();
+        assert_eq!(neighbors.failure(&address(1)), Some(RerouteReason::Unreachable));
+
+        assert!(neighbors.record_reply(address(1)));
+        assert_eq!(neighbors.failure(&address(1)), None);
+        assert!(!neighbors.record_reply(address(1)));
+        assert!(!neighbors.record_reply(address(2)));
+    }
+
+    #[test]
+    fn neighbors_reply_resets_failure_count() {
+        let mut neighbors = Neighbors::default();
+        neighbors.add(address(1));
+        for _ in 0..REACHABILITY_FAILURE_THRESHOLD {
+            neighbors.probe_targets();
+        }
+        assert!(!neighbors.record_reply(address(1)));
+        for _ in 0..REACHABILITY_FAILURE_THRESHOLD {
+            neighbors.probe_targets();
+        }
+        assert_eq!(neighbors.failure(&address(1)), None);
+    }
+
+    #[test]
+    fn neighbors_report_explicit_failure_until_reply() {
+        let mut neighbors = Neighbors::default();
+        neighbors.mark_failed(address(2));
+        assert_eq!(neighbors.failure(&address(2)), Some(RerouteReason::NodeFailure));
+        assert_eq!(neighbors.failure(&address(3)), None);
+        assert!(neighbors.record_reply(address(2)));
+        assert_eq!(neighbors.failure(&address(2)), None);
+    }
+
+    #[test]
+    fn selects_best_scoring_path_after_local_sid() {
+        let alternative = select(2, |_| true, None).unwrap();
+        assert_eq!(alternative.path_id, "fast");
+        assert_eq!(alternative.segments, [sid(5)]);
+        assert_eq!(alternative.nodes, [address(5)]);
+        assert!(alternative.score.is_some());
+    }
+
+    #[test]
+    fn honours_preferred_path() {
+        let alternative = select(2, |_| true, Some("slow")).unwrap();
+        assert_eq!(alternative.path_id, "slow");
+        assert_eq!(alternative.segments, [sid(3), sid(4)]);
+        assert_eq!(alternative.score, None);
+
+        // 候補に残っていなければ通常どおり選ぶ
+        assert_eq!(select(2, |_| true, Some("primary")).unwrap().path_id, "fast");
+    }
+
+    #[test]
+    fn excludes_failed_and_unavailable_nodes() {
+        assert_eq!(select(5, |_| true, None).unwrap().path_id, "primary");
+        assert_eq!(select(2, |address| *address != self::address(5), None).unwrap().path_id, "slow");
+        assert_eq!(select(2, |address| *address == self::address(2), None), None);
+
+        // SIDが一致しなくても障害ノードのアドレスを通る経路は使わない
+        let alternative = select_alternative_path(&path_list(),
+                                                  |candidate| *candidate == sid(1),
+                                                  &sid(2),
+                                                  Some(address(4)),
+                                                  |_| true,
+                                                  None,
+                                                  &ScoreWeights::default());
+        assert_eq!(alternative.unwrap().path_id, "fast");
+    }
+
+    #[test]
+    fn requires_remaining_segments_after_local_sid() {
+        let alternative = select_alternative_path(&path_list(),
+                                                  |candidate| *candidate == sid(5),
+                                                  &sid(9),
+                                                  None,
+                                                  |_| true,
+                                                  None,
+                                                  &ScoreWeights::default());
+        assert_eq!(alternative, None);
+    }
+
+    fn policy(relays: &[u16]) -> SRv6Policy {
+        let segments = relays.iter().map(|&n| PolicySegment { sid: sid(n), address: address(n) }).collect();
+        SRv6Policy::new(address(100), COLOR_DEFAULT, segments)
+    }
+
+    fn keys_for(relays: &[u16]) -> HashMap<SocketAddr, SessionKeys> {
+        relays.iter()
+            .chain(&[100])
+            .map(|&n| (address(n), derive_keys(&[n as u8; 48], b"test")))
+            .collect()
+    }
+
+    #[test]
+    fn alternative_routes_collect_suffix_after_branch_relay() {
+        let alternative = policy(&[1, 3, 4]);
+        let routes = alternative_routes(&policy(&[1, 2]), std::slice::from_ref(&alternative), &keys_for(&[1, 3, 4]));
+
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].path_id, alternative.policy_id);
+        assert_eq!(routes[0].segments, [sid(3), sid(4)]);
+        // 分岐点より後ろの中継ノードと受信者の鍵を経路順に持つ
+        assert_eq!(routes[0].keys.len(), 3);
+        for (keys, n) in routes[0].keys.iter().zip([3u16, 4, 100]) {
+            assert_eq!(keys.encryption_key, derive_keys(&[n as u8; 48], b"test").encryption_key);
+        }
+    }
+
+    #[test]
+    fn alternative_routes_skip_unusable_paths() {
+        let primary = policy(&[1, 2]);
+        let alternatives = [
+            policy(&[6, 7]), // 分岐点を通らない
+            policy(&[2, 1]), // 分岐点より後ろに中継ノードがない
+            policy(&[1, 5]), // 鍵のないノードを含む
+        ];
+        assert!(alternative_routes(&primary, &alternatives, &keys_for(&[1, 2, 6, 7])).is_empty());
+        assert!(alternative_routes(&policy(&[]), &[policy(&[1, 3])], &keys_for(&[1, 3])).is_empty());
+    }
+
+    fn onion(path_id: &str, fill: u8) -> AlternativeOnion {
+        AlternativeOnion {
+            path_id: path_id.to_string(),
+            onion_header: vec![fill; ONION_HEADER_SIZE],
+            payload: vec![fill; PAYLOAD_SIZE],
+        }
+    }
+
+    #[test]
+    fn alternative_onion_round_trip() {
+        let onion = onion("経路-1", 0xab);
+        assert_eq!(AlternativeOnion::from_bytes(&onion.to_bytes()).unwrap(), onion);
+    }
+
+    #[test]
+    fn alternative_onion_rejects_malformed_bytes() {
+        let bytes = onion("path", 1).to_bytes();
+        for malformed in [&bytes[..1], &bytes[..bytes.len() - 1]] {
+            assert_eq!(AlternativeOnion::from_bytes(malformed).unwrap_err().code(), ErrorCode::MalformedPacket);
+        }
+        let mut extended = bytes.clone();
+        extended.push(0);
+        assert_eq!(AlternativeOnion::from_bytes(&extended).unwrap_err().code(), ErrorCode::MalformedPacket);
+
+        let mut invalid_utf8 = bytes;
+        invalid_utf8[2] = 0xff;
+        assert_eq!(AlternativeOnion::from_bytes(&invalid_utf8).unwrap_err().code(), ErrorCode::MalformedPacket);
+    }
+
+    #[test]
+    fn alternative_onions_are_taken_once_per_sequence() {
+        let mut onions = AlternativeOnions::default();
+        onions.insert(7, 1, onion("a", 1));
+        onions.insert(7, 1, onion("b", 2));
+        onions.insert(8, 1, onion("a", 3));
+
+        let taken = onions.take(7, 1);
+        assert_eq!(taken.len(), 2);
+        assert_eq!(taken["b"], onion("b", 2));
+        assert!(onions.take(7, 1).is_empty());
+        assert_eq!(onions.take(8, 1)["a"], onion("a", 3));
+    }
+
+    #[test]
+    fn alternative_onions_evict_oldest_sequences() {
+        let mut onions = AlternativeOnions::default();
+        for sequence in 0..=ALTERNATIVE_ONION_CACHE_SIZE as u64 {
+            onions.insert(7, sequence, onion("a", 0));
+        }
+        assert!(onions.take(7, 0).is_empty());
+        assert_eq!(onions.take(7, 1).len(), 1);
+        assert_eq!(onions.take(7, ALTERNATIVE_ONION_CACHE_SIZE as u64).len(), 1);
+    }
+
+    #[test]
+    fn alternative_onions_drop_sessions_not_retained() {
+        let mut onions = AlternativeOnions::default();
+        onions.insert(7, 1, onion("a", 0));
+        onions.insert(8, 1, onion("a", 0));
+        onions.retain_sessions(|session_id| *session_id == 8);
+        assert!(onions.take(7, 1).is_empty());
+        assert_eq!(onions.take(8, 1).len(), 1);
+    }
+}
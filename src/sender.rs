@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use rand::Rng;
+use tokio::net::UdpSocket;
+use tracing::{info, warn};
+
+use crate::crypto::SessionKeys;
+use crate::error::Error;
+use crate::node::Node;
+use crate::pathchange::PathChange;
+use crate::pathlist::{PathList, DEFAULT_PATH_LIST_LIFETIME};
+use crate::policy::SRv6Policy;
+use crate::reroute::alternative_routes;
+use crate::sid::SidStructure;
+use crate::topology::TopologyFile;
+
+// 送信者の一連の処理: 経路上と代替経路上のノードとのハンドシェイク、
+// 最初の中継ノードへのパスリストと代替経路のOnionの送付、メッセージの送信
+pub struct Sender {
+    node: Node,
+    socket: UdpSocket,
+    path_list_lifetime: Duration,
+    sid_argument: Option<(SidStructure, u64)>, // 経路上の各SIDのArgument部に埋め込む値
+}
+
+impl Sender {
+    pub fn new(node: Node, socket: UdpSocket) -> Self {
+        Self {
+            node,
+            socket,
+            path_list_lifetime: DEFAULT_PATH_LIST_LIFETIME,
+            sid_argument: None,
+        }
+    }
+    
+    // 最初の中継ノードに渡すパスリストの有効期間を設定
+    pub fn with_path_list_lifetime(mut self, lifetime: Duration) -> Self {
+        self.path_list_lifetime = lifetime;
+        self
+    }
+    
+    // 送信する経路の各SIDのArgument部を置き換える（経路IDやフローインデックスなど）。代替経路のSIDはそのまま
+    pub fn with_sid_argument(mut self, structure: SidStructure, argument: u64) -> Self {
+        self.sid_argument = Some((structure, argument));
+        self
+    }
+    
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+    
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.socket.local_addr()?)
+    }
+    
+    // トポロジーのpublic_keyを、経路変更通知（SID）とハンドシェイク応答（アドレス）の検証用に登録する
+    pub fn trust_topology(&self, file: &TopologyFile) {
+        for topology_node in &file.nodes {
+            let Some(key) = topology_node.identity_public_key() else {
+                continue;
+            };
+            if let Some(sid) = topology_node.sid {
+                self.node.add_trusted_key(sid, key);
+            }
+            if let Some(address) = topology_node.address {
+                self.node.add_peer_key(address, key);
+            }
+        }
+    }
+    
+    // 新しいセッションで経路上と代替経路上の各ノードとハンドシェイクし、メッセージを送信する。セッションIDを返す
+    pub async fn send(&self, policy: &SRv6Policy, alternatives: &[SRv6Policy], message: &[u8]) -> Result<u32, Error> {
+        policy.validate()?;
+        self.trust_path_keys(std::iter::once(policy).chain(alternatives));
+        let mut policy = policy.clone();
+        if let Some((structure, argument)) = self.sid_argument {
+            for segment in &mut policy.segment_list {
+                segment.sid = structure.with_argument(&segment.sid, argument)?;
+            }
+        }
+        
+        let session_id = rand::thread_rng().gen::<u32>();
+        info!(session_id, policy = %policy.policy_id, color = policy.color, "SRv6ポリシーで送信");
+        let node_addresses = policy.node_addresses();
+        let mut keys: Vec<SessionKeys> = Vec::with_capacity(node_addresses.len());
+        for address in &node_addresses {
+            keys.push(self.node.establish_session(session_id, *address, &self.socket).await?);
+        }
+        
+        // 経路変更に備え、代替経路のノードとも同じセッションを確立する。
+        // ハンドシェイクできなかったノードを含む代替経路は使わない
+        let mut keys_by_address: HashMap<SocketAddr, SessionKeys> = node_addresses.iter()
+            .copied()
+            .zip(keys.iter().cloned())
+            .collect();
+        for address in alternatives.iter().flat_map(SRv6Policy::node_addresses) {
+            if keys_by_address.contains_key(&address) {
+                continue;
+            }
+            match self.node.establish_session(session_id, address, &self.socket).await {
+                Ok(session_keys) => {
+                    keys_by_address.insert(address, session_keys);
+                },
+                Err(e) => warn!(%address, code = %e.code(), error = %e, "代替経路のノードとハンドシェイクできません"),
+            }
+        }
+        let routes = alternative_routes(&policy, alternatives, &keys_by_address);
+        
+        // 使用する経路を優先度1、代替経路をそれ以降としたパスリストを最初の中継ノードに渡す
+        let path_list = PathList::from_policies(session_id, std::iter::once(&policy).chain(alternatives), self.path_list_lifetime);
+        self.node.send_path_list(&path_list, &keys[0], node_addresses[0], &self.socket).await?;
+        
+        self.node.send_message(session_id, &policy, &keys, &routes, message, &self.socket).await?;
+        Ok(session_id)
+    }
+    
+    // 送信後に届く経路変更通知を待つ
+    pub async fn collect_path_changes(&self, wait: Duration) -> Vec<PathChange> {
+        self.node.collect_path_changes(&self.socket, wait).await
+    }
+    
+    // 経路上のSIDに経路変更通知用の識別鍵が登録されていれば、そのアドレスのハンドシェイク応答もその鍵で検証する。
+    // アドレスで登録済みの鍵を優先する
+    fn trust_path_keys<'a>(&self, policies: impl Iterator<Item = &'a SRv6Policy>) {
+        for segment in policies.flat_map(|policy| &policy.segment_list) {
+            if self.node.peer_key(&segment.address).is_some() {
+                continue;
+            }
+            if let Some(key) = self.node.trusted_key(&segment.sid) {
+                self.node.add_peer_key(segment.address, key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+    
+    use p384::SecretKey;
+    use rand::rngs::OsRng;
+    
+    use super::*;
+    use crate::crypto::public_key_hex;
+    use crate::error::ErrorCode;
+    use crate::node::NodeType;
+    use crate::onion::MAX_HOPS;
+    use crate::policy::{PolicySegment, COLOR_DEFAULT};
+    use crate::topology::TopologyNode;
+    
+    fn sid(n: u16) -> Ipv6Addr {
+        Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, n)
+    }
+    
+    fn address(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+    
+    async fn sender() -> Sender {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        Sender::new(Node::new(NodeType::Sender, socket.local_addr().unwrap()), socket)
+    }
+    
+    fn policy(hops: u16) -> SRv6Policy {
+        let segments = (1..=hops).map(|n| PolicySegment { sid: sid(n), address: address(9100 + n) }).collect();
+        SRv6Policy::new(address(9200), COLOR_DEFAULT, segments)
+    }
+    
+    #[tokio::test]
+    async fn trusts_topology_keys_by_sid_and_address() {
+        let sender = sender().await;
+        let key = SecretKey::random(&mut OsRng).public_key();
+        let mut relay = TopologyNode::new("relay1").with_sid(sid(1)).with_address(address(9101));
+        relay.public_key = Some(public_key_hex(&key));
+        let unkeyed = TopologyNode::new("relay2").with_sid(sid(2)).with_address(address(9102));
+        let file = TopologyFile { nodes: vec![relay, unkeyed], ..TopologyFile::default() };
+        
+        sender.trust_topology(&file);
+        
+        assert_eq!(sender.node().trusted_key(&sid(1)), Some(key));
+        assert_eq!(sender.node().peer_key(&address(9101)), Some(key));
+        assert_eq!(sender.node().trusted_key(&sid(2)), None);
+        assert_eq!(sender.node().peer_key(&address(9102)), None);
+    }
+    
+    #[tokio::test]
+    async fn path_keys_do_not_replace_registered_peer_keys() {
+        let sender = sender().await;
+        let trusted = SecretKey::random(&mut OsRng).public_key();
+        let peer = SecretKey::random(&mut OsRng).public_key();
+        sender.node().add_trusted_key(sid(1), trusted);
+        sender.node().add_trusted_key(sid(2), trusted);
+        sender.node().add_peer_key(address(9102), peer);
+        
+        sender.trust_path_keys(std::iter::once(&policy(2)));
+        
+        assert_eq!(sender.node().peer_key(&address(9101)), Some(trusted));
+        assert_eq!(sender.node().peer_key(&address(9102)), Some(peer));
+    }
+    
+    #[tokio::test]
+    async fn rejects_too_long_policy_before_handshake() {
+        let sender = sender().await;
+        let error = sender.send(&policy(MAX_HOPS as u16), &[], b"hello").await.unwrap_err();
+        assert_eq!(error.code(), ErrorCode::PathTooLong);
+    }
+}
@@ -0,0 +1,452 @@
+use std::fmt;
+use std::net::Ipv6Addr;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, PathError};
+
+// 定数
+pub const DEFAULT_LOCATOR_LEN: u8 = 64;
+pub const DEFAULT_FUNCTION_LEN: u8 = 16;
+pub const DEFAULT_ARGUMENT_LEN: u8 = 48;
+pub const MAX_FIELD_LEN: u8 = 64; // FunctionとArgumentはそれぞれ64ビットまで
+pub const DEFAULT_USID_BLOCK_LEN: u8 = 32;
+pub const DEFAULT_USID_LEN: u8 = 16;
+
+// SIDの構造（README §3.6.1、RFC 8986 §3.1）: Locator | Function | Argument の各ビット長
+// 合計が128ビットに満たない場合、残りの下位ビットは0とする。
+// 各ビット長は new（設定ファイルでは読み込み時）で検証し、検証済みの値しか作れない
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "SidStructureFields")]
+pub struct SidStructure {
+    locator_len: u8,
+    function_len: u8,
+    argument_len: u8,
+}
+
+// 設定ファイル上のSID構造（検証前）
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SidStructureFields {
+    locator_len: u8,
+    function_len: u8,
+    argument_len: u8,
+}
+
+// 構造に沿って分解したSID
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sid {
+    pub locator: Ipv6Addr, // Function以降を0にしたアドレス
+    pub function: u64,
+    pub argument: u64,
+}
+
+impl Default for SidStructure {
+    fn default() -> Self {
+        Self {
+            locator_len: DEFAULT_LOCATOR_LEN,
+            function_len: DEFAULT_FUNCTION_LEN,
+            argument_len: DEFAULT_ARGUMENT_LEN,
+        }
+    }
+}
+
+impl TryFrom<SidStructureFields> for SidStructure {
+    type Error = String;
+
+    fn try_from(fields: SidStructureFields) -> Result<Self, Self::Error> {
+        Self::new(fields.locator_len, fields.function_len, fields.argument_len)
+    }
+}
+
+impl SidStructure {
+    pub fn new(locator_len: u8, function_len: u8, argument_len: u8) -> Result<Self, String> {
+        let structure = Self { locator_len, function_len, argument_len };
+        structure.validate()?;
+        Ok(structure)
+    }
+
+    pub fn locator_len(&self) -> u8 {
+        self.locator_len
+    }
+
+    pub fn function_len(&self) -> u8 {
+        self.function_len
+    }
+
+    pub fn argument_len(&self) -> u8 {
+        self.argument_len
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let total = self.locator_len as u32 + self.function_len as u32 + self.argument_len as u32;
+        if total > 128 {
+            return Err(format!("SID構造の合計が128ビットを超えています: {}", self));
+        }
+        if self.function_len > MAX_FIELD_LEN || self.argument_len > MAX_FIELD_LEN {
+            return Err(format!("FunctionとArgumentはそれぞれ{}ビットまでです: {}", MAX_FIELD_LEN, self));
+        }
+        Ok(())
+    }
+
+    pub fn parse(&self, sid: &Ipv6Addr) -> Sid {
+        Sid {
+            locator: self.locator(sid),
+            function: self.function(sid),
+            argument: self.argument(sid),
+        }
+    }
+
+    pub fn locator(&self, sid: &Ipv6Addr) -> Ipv6Addr {
+        Ipv6Addr::from(u128::from(*sid) & high_mask(self.locator_len))
+    }
+
+    pub fn function(&self, sid: &Ipv6Addr) -> u64 {
+        extract(sid, self.locator_len, self.function_len)
+    }
+
+    pub fn argument(&self, sid: &Ipv6Addr) -> u64 {
+        extract(sid, self.locator_len.saturating_add(self.function_len), self.argument_len)
+    }
+
+    // Argumentを0にしたSID（SIDテーブルの検索キー）
+    pub fn without_argument(&self, sid: &Ipv6Addr) -> Ipv6Addr {
+        Ipv6Addr::from(u128::from(*sid) & high_mask(self.locator_len.saturating_add(self.function_len)))
+    }
+
+    // SIDのArgumentを置き換える（パケットごとの経路IDやフローインデックスなど）
+    pub fn with_argument(&self, sid: &Ipv6Addr, argument: u64) -> Result<Ipv6Addr, Error> {
+        let shift = self.field_shift(self.locator_len.saturating_add(self.function_len), self.argument_len);
+        let bits = fit(argument, self.argument_len, "Argument")?;
+        Ok(Ipv6Addr::from(u128::from(self.without_argument(sid)) | bits.checked_shl(shift).unwrap_or(0)))
+    }
+
+    // Locator、Function、ArgumentからSIDを組み立てる
+    pub fn compose(&self, locator: &Ipv6Addr, function: u64, argument: u64) -> Result<Ipv6Addr, Error> {
+        let function_bits = fit(function, self.function_len, "Function")?;
+        let shift = self.field_shift(self.locator_len, self.function_len);
+        let base = u128::from(self.locator(locator)) | function_bits.checked_shl(shift).unwrap_or(0);
+        self.with_argument(&Ipv6Addr::from(base), argument)
+    }
+
+    pub fn same_locator(&self, a: &Ipv6Addr, b: &Ipv6Addr) -> bool {
+        self.locator(a) == self.locator(b)
+    }
+
+    fn field_shift(&self, offset: u8, len: u8) -> u32 {
+        128 - offset as u32 - len as u32
+    }
+}
+
+// "64/16/48" 形式（Locator/Function/Argument のビット長）
+impl FromStr for SidStructure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lengths = s
+            .split('/')
+            .map(|part| part.trim().parse::<u8>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("SID構造が不正です: {}", s))?;
+        match lengths[..] {
+            [locator_len, function_len, argument_len] => Self::new(locator_len, function_len, argument_len),
+            _ => Err(format!("SID構造は Locator/Function/Argument のビット長で指定してください: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for SidStructure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}/{}", self.locator_len, self.function_len, self.argument_len)
+    }
+}
+
+// 圧縮SID（uSID、RFC 9800 のNEXT-C-SIDフレーバー）の形式: Locator-Block と uSID のビット長
+// uSIDキャリアは Locator-Block | uSID | uSID | ... | 0（End-of-Carrier）で、アクティブなuSIDは
+// 常にLocator-Blockの直後にある。32/16なら1つのキャリア（16バイト）に6ホップ分を収められる
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct UsidFormat {
+    pub block_len: u8,
+    pub usid_len: u8,
+}
+
+// uSIDキャリアに圧縮した経路
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompressedPath {
+    pub segments: Vec<Ipv6Addr>,    // SRHに載せるキャリアまたは非圧縮SID（経路順）
+    pub active_sids: Vec<Ipv6Addr>, // 経路の各SIDに対応する、そのノードに届くときのIPv6宛先
+}
+
+impl Default for UsidFormat {
+    fn default() -> Self {
+        Self { block_len: DEFAULT_USID_BLOCK_LEN, usid_len: DEFAULT_USID_LEN }
+    }
+}
+
+impl UsidFormat {
+    pub fn new(block_len: u8, usid_len: u8) -> Result<Self, String> {
+        let format = Self { block_len, usid_len };
+        format.validate()?;
+        Ok(format)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.usid_len == 0 || self.usid_len > MAX_FIELD_LEN {
+            return Err(format!("uSIDの長さは1〜{}ビットです: {}", MAX_FIELD_LEN, self));
+        }
+        if self.block_len as u32 + self.usid_len as u32 > 128 {
+            return Err(format!("Locator-BlockとuSIDの合計が128ビットを超えています: {}", self));
+        }
+        Ok(())
+    }
+
+    // 1つのキャリアに収まるuSIDの数
+    pub fn capacity(&self) -> usize {
+        (128 - self.block_len as usize) / self.usid_len as usize
+    }
+
+    pub fn block(&self, sid: &Ipv6Addr) -> Ipv6Addr {
+        Ipv6Addr::from(u128::from(*sid) & high_mask(self.block_len))
+    }
+
+    pub fn active_usid(&self, sid: &Ipv6Addr) -> u64 {
+        extract(sid, self.block_len, self.usid_len)
+    }
+
+    // Locator-BlockとアクティブなuSIDだけを残したアドレス（SIDテーブルの検索キー）
+    pub fn node_sid(&self, sid: &Ipv6Addr) -> Ipv6Addr {
+        Ipv6Addr::from(u128::from(*sid) & high_mask(self.block_len.saturating_add(self.usid_len)))
+    }
+
+    // Locator-Block | uSID | 0 の形でキャリアに詰められるSIDか
+    pub fn is_usid(&self, sid: &Ipv6Addr) -> bool {
+        self.active_usid(sid) != 0 && self.node_sid(sid) == *sid
+    }
+
+    // NEXT-C-SIDのシフト: Locator-Blockより後ろを uSID 1つ分左へずらす。
+    // 次のuSIDがなければ（End-of-Carrier）Noneを返し、通常のEnd動作で次のセグメントへ進む
+    pub fn shift(&self, sid: &Ipv6Addr) -> Option<Ipv6Addr> {
+        let value = u128::from(*sid);
+        let block_mask = high_mask(self.block_len);
+        let shifted = (value & !block_mask).checked_shl(self.usid_len as u32).unwrap_or(0) & !block_mask;
+        let next = Ipv6Addr::from((value & block_mask) | shifted);
+        (self.active_usid(&next) != 0).then_some(next)
+    }
+
+    // 経路を先頭から順にキャリアへ詰める。Locator-Blockが同じ uSID 形式のSIDが続く間は
+    // 同じキャリアに入れ、それ以外のSIDは非圧縮のままセグメントとする
+    pub fn compress(&self, path: &[Ipv6Addr]) -> CompressedPath {
+        let mut compressed = CompressedPath { segments: Vec::new(), active_sids: Vec::new() };
+        let mut carrier: Vec<Ipv6Addr> = Vec::new();
+        for sid in path {
+            if !self.is_usid(sid) {
+                self.push_carrier(&mut compressed, &mut carrier);
+                compressed.segments.push(*sid);
+                compressed.active_sids.push(*sid);
+                continue;
+            }
+            let fits = carrier.first().is_none_or(|first| self.block(first) == self.block(sid))
+                && carrier.len() < self.capacity();
+            if !fits {
+                self.push_carrier(&mut compressed, &mut carrier);
+            }
+            carrier.push(*sid);
+        }
+        self.push_carrier(&mut compressed, &mut carrier);
+        compressed
+    }
+
+    fn push_carrier(&self, compressed: &mut CompressedPath, usids: &mut Vec<Ipv6Addr>) {
+        let Some(first) = usids.first() else {
+            return;
+        };
+        let mut value = u128::from(self.block(first));
+        for (i, sid) in usids.iter().enumerate() {
+            let shift = 128 - self.block_len as u32 - (i as u32 + 1) * self.usid_len as u32;
+            value |= (self.active_usid(sid) as u128) << shift;
+        }
+        let carrier = Ipv6Addr::from(value);
+        compressed.segments.push(carrier);
+
+        let mut active = Some(carrier);
+        for _ in 0..usids.len() {
+            let Some(sid) = active else { break };
+            compressed.active_sids.push(sid);
+            active = self.shift(&sid);
+        }
+        usids.clear();
+    }
+}
+
+// "32/16" 形式（Locator-Block/uSID のビット長）
+impl FromStr for UsidFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (block_len, usid_len) = s.split_once('/')
+            .and_then(|(block, usid)| Some((block.trim().parse().ok()?, usid.trim().parse().ok()?)))
+            .ok_or_else(|| format!("uSID形式は Locator-Block/uSID のビット長で指定してください: {}", s))?;
+        Self::new(block_len, usid_len)
+    }
+}
+
+impl fmt::Display for UsidFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.block_len, self.usid_len)
+    }
+}
+
+// 上位 len ビットが1のマスク
+fn high_mask(len: u8) -> u128 {
+    u128::MAX.checked_shl(128 - len.min(128) as u32).unwrap_or(0)
+}
+
+// offset ビット目から len ビットを取り出す
+fn extract(sid: &Ipv6Addr, offset: u8, len: u8) -> u64 {
+    if len == 0 {
+        return 0;
+    }
+    let shift = 128 - offset as u32 - len as u32;
+    ((u128::from(*sid) >> shift) & (u128::MAX >> (128 - len as u32))) as u64
+}
+
+fn fit(value: u64, len: u8, field: &str) -> Result<u128, Error> {
+    if len < 64 && value >> len != 0 {
+        return Err(PathError::InvalidSid(format!("{}が{}ビットに収まりません: {}", field, len, value)).into());
+    }
+    Ok(value as u128)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+
+    fn addr(text: &str) -> Ipv6Addr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn structure_rejects_invalid_lengths() {
+        let structure = SidStructure::new(64, 16, 48).unwrap();
+        assert_eq!((structure.locator_len(), structure.function_len(), structure.argument_len()), (64, 16, 48));
+        assert!(structure.validate().is_ok());
+        assert!(SidStructure::new(80, 16, 40).is_err()); // 合計136ビット
+        assert!(SidStructure::new(0, 65, 0).is_err());
+        assert!(SidStructure::new(0, 0, 65).is_err());
+        assert!(SidStructure::new(0, 64, 64).is_ok());
+
+        assert_eq!("48/16/32".parse::<SidStructure>().unwrap(), SidStructure::new(48, 16, 32).unwrap());
+        assert!("64/16".parse::<SidStructure>().is_err());
+        assert!("64/x/48".parse::<SidStructure>().is_err());
+        assert!("64/16/64".parse::<SidStructure>().is_err());
+    }
+
+    #[test]
+    fn structure_is_validated_when_deserialized() {
+        let structure: SidStructure = serde_yaml::from_str("{locator_len: 48, function_len: 16, argument_len: 32}").unwrap();
+        assert_eq!(structure.to_string(), "48/16/32");
+        assert!(serde_yaml::from_str::<SidStructure>("{locator_len: 64, function_len: 32, argument_len: 48}").is_err());
+        assert!(serde_yaml::from_str::<SidStructure>("{locator_len: 64, function_len: 16, argument_len: 48, extra: 1}").is_err());
+    }
+
+    #[test]
+    fn parse_splits_sid_into_fields() {
+        let structure = SidStructure::default();
+        let sid = addr("2001:db8:cafe:1:f:1234:5678:9abc");
+        assert_eq!(structure.parse(&sid), Sid {
+            locator: addr("2001:db8:cafe:1::"),
+            function: 0xf,
+            argument: 0x1234_5678_9abc,
+        });
+        assert_eq!(structure.without_argument(&sid), addr("2001:db8:cafe:1:f::"));
+
+        // 合計が128ビットに満たなければ残りの下位ビットは無視する
+        let structure = SidStructure::new(48, 16, 16).unwrap();
+        assert_eq!(structure.parse(&addr("2001:db8:cafe:f:1:ffff::1")), Sid {
+            locator: addr("2001:db8:cafe::"),
+            function: 0xf,
+            argument: 1,
+        });
+    }
+
+    #[test]
+    fn with_argument_and_compose_keep_fields_in_range() {
+        let structure = SidStructure::default();
+        let sid = addr("2001:db8:cafe:1:f:1::");
+        assert_eq!(structure.with_argument(&sid, 0xabc).unwrap(), addr("2001:db8:cafe:1:f::abc"));
+        assert_eq!(structure.with_argument(&sid, 1 << 48).unwrap_err().code(), ErrorCode::InvalidSid);
+
+        let composed = structure.compose(&addr("2001:db8:cafe:1::"), 0xf, 7).unwrap();
+        assert_eq!(composed, addr("2001:db8:cafe:1:f::7"));
+        assert_eq!(structure.parse(&composed), Sid { locator: addr("2001:db8:cafe:1::"), function: 0xf, argument: 7 });
+        assert_eq!(structure.compose(&addr("2001:db8:cafe:1::"), 1 << 16, 0).unwrap_err().code(), ErrorCode::InvalidSid);
+
+        // Argumentが64ビットならu64全体を使える
+        let structure = SidStructure::new(48, 16, 64).unwrap();
+        assert_eq!(structure.argument(&structure.with_argument(&sid, u64::MAX).unwrap()), u64::MAX);
+    }
+
+    #[test]
+    fn same_locator_ignores_function_and_argument() {
+        let structure = SidStructure::default();
+        assert!(structure.same_locator(&addr("2001:db8:cafe:1:f::1"), &addr("2001:db8:cafe:1:e::2")));
+        assert!(!structure.same_locator(&addr("2001:db8:cafe:1:f::"), &addr("2001:db8:cafe:2:f::")));
+    }
+
+    #[test]
+    fn shift_moves_next_usid_to_active_position() {
+        let format = UsidFormat::default();
+        assert_eq!(format.shift(&addr("fc00:0:1:2:3::")), Some(addr("fc00:0:2:3::")));
+        assert_eq!(format.shift(&addr("fc00:0:2:3::")), Some(addr("fc00:0:3::")));
+        assert_eq!(format.shift(&addr("fc00:0:3::")), None);
+    }
+
+    #[test]
+    fn is_usid_requires_single_usid_after_block() {
+        let format = UsidFormat::default();
+        assert!(format.is_usid(&addr("fc00:0:1::")));
+        assert!(!format.is_usid(&addr("fc00:0:1:2::")));
+        assert!(!format.is_usid(&addr("fc00::")));
+    }
+
+    #[test]
+    fn compress_packs_path_into_one_carrier() {
+        let format = UsidFormat::default();
+        let compressed = format.compress(&[addr("fc00:0:1::"), addr("fc00:0:2::"), addr("fc00:0:3::")]);
+        assert_eq!(compressed.segments, vec![addr("fc00:0:1:2:3::")]);
+        assert_eq!(compressed.active_sids, vec![addr("fc00:0:1:2:3::"), addr("fc00:0:2:3::"), addr("fc00:0:3::")]);
+    }
+
+    #[test]
+    fn compress_starts_new_carrier_when_full_or_block_changes() {
+        let format = UsidFormat::default();
+        let path: Vec<Ipv6Addr> = (1..=7).map(|n| Ipv6Addr::new(0xfc00, 0, n, 0, 0, 0, 0, 0)).collect();
+        let compressed = format.compress(&path);
+        assert_eq!(compressed.segments, vec![addr("fc00:0:1:2:3:4:5:6"), addr("fc00:0:7::")]);
+        assert_eq!(compressed.active_sids.len(), path.len());
+
+        let compressed = format.compress(&[addr("fc00:0:1::"), addr("fc01:0:2::")]);
+        assert_eq!(compressed.segments, vec![addr("fc00:0:1::"), addr("fc01:0:2::")]);
+    }
+
+    #[test]
+    fn compress_keeps_plain_sids_uncompressed() {
+        let format = UsidFormat::default();
+        let path = [addr("fc00:0:1::"), addr("fc00:0:2::"), addr("2001:db8::5"), addr("fc00:0:3::")];
+        let compressed = format.compress(&path);
+        assert_eq!(compressed.segments, vec![addr("fc00:0:1:2::"), addr("2001:db8::5"), addr("fc00:0:3::")]);
+        assert_eq!(compressed.active_sids,
+                   vec![addr("fc00:0:1:2::"), addr("fc00:0:2::"), addr("2001:db8::5"), addr("fc00:0:3::")]);
+    }
+
+    #[test]
+    fn format_rejects_invalid_lengths() {
+        assert!("32/16".parse::<UsidFormat>().is_ok());
+        assert!("32/0".parse::<UsidFormat>().is_err());
+        assert!("120/16".parse::<UsidFormat>().is_err());
+        assert_eq!(UsidFormat::new(32, 16).unwrap().capacity(), 6);
+    }
+}
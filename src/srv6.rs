@@ -0,0 +1,599 @@
+use std::fmt;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{compute_srh_hmac, verify_srh_hmac, SrhHmacKey};
+use crate::error::{CommunicationError, CryptoError, Error};
+use crate::ipv6::{Ipv6Header, ONION_NEXT_HEADER};
+use crate::sid::UsidFormat;
+
+// 定数
+pub const SRH_FIXED_SIZE: usize = 8; // セグメントリストより前の固定部
+pub const ROUTING_TYPE_SRH: u8 = 4;
+pub const MAX_SRH_HMAC_SIZE: usize = 32;
+pub const MAX_SEGMENTS: usize = 127; // TLVなしでHdr Ext Len（8オクテット単位、1バイト）に収まるセグメント数
+pub const MAIN_TABLE: u32 = 0; // テーブル指定のない経路を登録するメインテーブル
+
+// ローカルSIDのエンドポイント動作（README §3.6.2、RFC 8986 §4）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SidBehavior {
+    End,                // 次のSIDへ。転送先はメインテーブルのロケータ経路で決める
+    EndX(SocketAddr),   // 次のSIDへ。指定した隣接ノードへ直接転送する
+    EndT(u32),          // 次のSIDへ。転送先は指定テーブルで検索する
+    EndDT6(u32),        // 最後のセグメントでSRHを取り除き、最終宛先を指定テーブルで検索する
+}
+
+impl fmt::Display for SidBehavior {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SidBehavior::End => write!(f, "End"),
+            SidBehavior::EndX(adjacency) => write!(f, "End.X({})", adjacency),
+            SidBehavior::EndT(table) => write!(f, "End.T({})", table),
+            SidBehavior::EndDT6(table) => write!(f, "End.DT6({})", table),
+        }
+    }
+}
+
+// "end", "end.x:[::1]:9003", "end.t:10", "end.dt6:10" の形式（大文字小文字は区別しない）
+impl FromStr for SidBehavior {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, argument) = match s.split_once(':') {
+            Some((name, argument)) => (name, Some(argument)),
+            None => (s, None),
+        };
+        let table = |argument: Option<&str>| {
+            argument.ok_or_else(|| format!("{} にはテーブルIDが必要です", name))?
+                .parse::<u32>()
+                .map_err(|_| format!("テーブルIDが不正です: {}", s))
+        };
+        match (name.to_ascii_lowercase().as_str(), argument) {
+            ("end", None) => Ok(SidBehavior::End),
+            ("end.x", Some(adjacency)) => adjacency.parse()
+                .map(SidBehavior::EndX)
+                .map_err(|_| format!("隣接ノードのアドレスが不正です: {}", adjacency)),
+            ("end.x", None) => Err("end.x には隣接ノードのアドレスが必要です".into()),
+            ("end.t", argument) => table(argument).map(SidBehavior::EndT),
+            ("end.dt6", argument) => table(argument).map(SidBehavior::EndDT6),
+            _ => Err(format!("不明なエンドポイント動作です: {} (使用可能: end, end.x, end.t, end.dt6)", s)),
+        }
+    }
+}
+
+// SIDテーブルにないSID宛のパケットの扱い
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownSidPolicy {
+    #[default]
+    Drop,    // 破棄する
+    Forward, // Onion処理を行わず、アクティブSIDのロケータ経路でそのまま転送する
+}
+
+impl FromStr for UnknownSidPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "drop" => Ok(UnknownSidPolicy::Drop),
+            "forward" => Ok(UnknownSidPolicy::Forward),
+            _ => Err(format!("不明な未知SIDポリシーです: {} (使用可能: drop, forward)", s)),
+        }
+    }
+}
+
+// SRH TLV（RFC 8754 §2.1）
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SrhTlv {
+    Pad1,                                   // 1オクテットのパディング
+    PadN(u8),                               // 値部のオクテット数を持つパディング
+    Hmac(HmacTlv),
+    Unknown { tlv_type: u8, value: Vec<u8> }, // 未対応のTLVはそのまま転送する
+}
+
+// HMAC TLV（RFC 8754 §2.1.2）
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HmacTlv {
+    pub destination_check_disabled: bool, // Dフラグ
+    pub key_id: u32,
+    pub hmac: Vec<u8>, // 8オクテットの倍数（最大32）
+}
+
+impl SrhTlv {
+    const TYPE_PAD1: u8 = 0;
+    const TYPE_PADN: u8 = 4;
+    const TYPE_HMAC: u8 = 5;
+    
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (tlv_type, value) = match self {
+            SrhTlv::Pad1 => return vec![Self::TYPE_PAD1],
+            SrhTlv::PadN(len) => (Self::TYPE_PADN, vec![0u8; *len as usize]),
+            SrhTlv::Hmac(tlv) => {
+                let flags: u16 = if tlv.destination_check_disabled { 0x8000 } else { 0 };
+                let mut value = flags.to_be_bytes().to_vec();
+                value.extend_from_slice(&tlv.key_id.to_be_bytes());
+                value.extend_from_slice(&tlv.hmac);
+                (Self::TYPE_HMAC, value)
+            },
+            SrhTlv::Unknown { tlv_type, value } => (*tlv_type, value.clone()),
+        };
+        
+        let mut bytes = Vec::with_capacity(2 + value.len());
+        bytes.push(tlv_type);
+        bytes.push(value.len() as u8);
+        bytes.extend_from_slice(&value);
+        bytes
+    }
+    
+    // 先頭のTLVを解析し、消費したバイト数とともに返す
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), Error> {
+        match bytes.first() {
+            None => return Err(CommunicationError::MalformedSrh("SRH TLVが空です".into()).into()),
+            Some(&Self::TYPE_PAD1) => return Ok((SrhTlv::Pad1, 1)),
+            Some(_) => {},
+        }
+        if bytes.len() < 2 {
+            return Err(CommunicationError::MalformedSrh("SRH TLVが短すぎます".into()).into());
+        }
+        
+        let tlv_type = bytes[0];
+        let length = bytes[1] as usize;
+        if bytes.len() < 2 + length {
+            return Err(CommunicationError::MalformedSrh(format!("SRH TLVのデータが不足しています: type={}", tlv_type)).into());
+        }
+        let value = &bytes[2..2 + length];
+        
+        let tlv = match tlv_type {
+            Self::TYPE_PADN => SrhTlv::PadN(length as u8),
+            Self::TYPE_HMAC => {
+                let hmac_len = length.checked_sub(6)
+                    .filter(|len| len.is_multiple_of(8) && *len <= MAX_SRH_HMAC_SIZE)
+                    .ok_or_else(|| CommunicationError::MalformedSrh(format!("HMAC TLVの長さが不正です: {}", length)))?;
+                SrhTlv::Hmac(HmacTlv {
+                    destination_check_disabled: value[0] & 0x80 != 0,
+                    key_id: u32::from_be_bytes([value[2], value[3], value[4], value[5]]),
+                    hmac: value[6..6 + hmac_len].to_vec(),
+                })
+            },
+            _ => SrhTlv::Unknown { tlv_type, value: value.to_vec() },
+        };
+        Ok((tlv, 2 + length))
+    }
+    
+    fn is_padding(&self) -> bool {
+        matches!(self, SrhTlv::Pad1 | SrhTlv::PadN(_))
+    }
+}
+
+// SRv6ヘッダー構造体（RFC 8754 §2）
+// セグメントリストは逆順に格納する: Segment List[0] が最後のセグメント、
+// Segment List[Last Entry] が最初のセグメントで、アクティブSIDは Segment List[Segments Left]
+#[derive(Clone, Debug)]
+pub struct SRv6Header {
+    pub next_header: u8,
+    pub hdr_ext_len: u8,
+    pub routing_type: u8, // SRHでは4
+    pub segments_left: u8,
+    pub last_entry: u8,
+    pub flags: u8,
+    pub tag: u16,
+    pub segment_list: Vec<Ipv6Addr>,
+    pub tlvs: Vec<SrhTlv>, // セグメントリストに続くTLV。変更後は update_length() で長さを合わせる
+}
+
+impl SRv6Header {
+    // 経路順（最初に訪れるSIDが先頭）のセグメントからSRHを作成
+    pub fn new(mut segment_list: Vec<Ipv6Addr>) -> Result<Self, Error> {
+        if segment_list.is_empty() || segment_list.len() > MAX_SEGMENTS {
+            return Err(CommunicationError::MalformedSrh(format!("セグメント数が不正です: {}", segment_list.len())).into());
+        }
+        segment_list.reverse();
+        let last_entry = (segment_list.len() - 1) as u8;
+        Ok(Self {
+            next_header: ONION_NEXT_HEADER, // Onion Routingヘッダー
+            // 先頭8オクテットを除いた8オクテット単位の長さ（セグメント1つにつき2）
+            hdr_ext_len: hdr_ext_len(segment_list.len() * 16)?,
+            routing_type: ROUTING_TYPE_SRH,
+            segments_left: last_entry,
+            last_entry,
+            flags: 0,
+            tag: 0,
+            segment_list,
+            tlvs: Vec::new(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![
+            self.next_header,
+            self.hdr_ext_len,
+            self.routing_type,
+            self.segments_left,
+            self.last_entry,
+            self.flags,
+        ];
+        bytes.extend_from_slice(&self.tag.to_be_bytes());
+        
+        // セグメントリスト
+        for segment in &self.segment_list {
+            bytes.extend_from_slice(&segment.octets());
+        }
+        
+        // TLV
+        for tlv in &self.tlvs {
+            bytes.extend_from_slice(&tlv.to_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < SRH_FIXED_SIZE {
+            return Err(CommunicationError::MalformedSrh("SRv6ヘッダーが短すぎます".into()).into());
+        }
+        
+        let next_header = bytes[0];
+        let hdr_ext_len = bytes[1];
+        let routing_type = bytes[2];
+        let segments_left = bytes[3];
+        let last_entry = bytes[4];
+        let flags = bytes[5];
+        let tag = u16::from_be_bytes([bytes[6], bytes[7]]);
+        
+        if routing_type != ROUTING_TYPE_SRH {
+            return Err(CommunicationError::MalformedSrh(format!("ルーティングタイプがSRHではありません: {}", routing_type)).into());
+        }
+        
+        // ヘッダー長の検証: セグメントリストはHdr Ext Lenの範囲内に収まっていなければならない
+        let header_len = SRH_FIXED_SIZE + hdr_ext_len as usize * 8;
+        let segments_count = last_entry as usize + 1;
+        if SRH_FIXED_SIZE + segments_count * 16 > header_len {
+            return Err(CommunicationError::MalformedSrh(
+                format!("Last Entryがヘッダー長を超えています (Last Entry: {}, Hdr Ext Len: {})", last_entry, hdr_ext_len)).into());
+        }
+        if segments_left > last_entry {
+            return Err(CommunicationError::MalformedSrh(
+                format!("Segments LeftがLast Entryを超えています (Segments Left: {}, Last Entry: {})", segments_left, last_entry)).into());
+        }
+        if bytes.len() < header_len {
+            return Err(CommunicationError::MalformedSrh("SRv6ヘッダーデータが不足しています".into()).into());
+        }
+        
+        // セグメントリストの解析
+        let mut segment_list = Vec::with_capacity(segments_count);
+        for i in 0..segments_count {
+            let offset = SRH_FIXED_SIZE + i * 16;
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&bytes[offset..offset + 16]);
+            segment_list.push(Ipv6Addr::from(octets));
+        }
+        
+        // セグメントリストの後ろ、Hdr Ext Lenの範囲内はすべてTLV
+        let mut tlvs = Vec::new();
+        let mut offset = SRH_FIXED_SIZE + segments_count * 16;
+        while offset < header_len {
+            let (tlv, consumed) = SrhTlv::from_bytes(&bytes[offset..header_len])?;
+            tlvs.push(tlv);
+            offset += consumed;
+        }
+        
+        Ok(Self {
+            next_header,
+            hdr_ext_len,
+            routing_type,
+            segments_left,
+            last_entry,
+            flags,
+            tag,
+            segment_list,
+            tlvs,
+        })
+    }
+
+    // ヘッダー全体の長さ（バイト）
+    pub fn header_len(&self) -> usize {
+        SRH_FIXED_SIZE + self.hdr_ext_len as usize * 8
+    }
+    
+    // TLVのパディングを8オクテット境界に合わせ直し、Hdr Ext Lenを更新する。
+    // Hdr Ext Len（1バイト）に収まらない場合はヘッダーを変更せずにエラーを返す
+    pub fn update_length(&mut self) -> Result<(), Error> {
+        let tlv_len: usize = self.tlvs.iter()
+            .filter(|tlv| !tlv.is_padding())
+            .map(|tlv| tlv.to_bytes().len())
+            .sum();
+        self.hdr_ext_len = hdr_ext_len(self.segment_list.len() * 16 + tlv_len)?;
+        self.tlvs.retain(|tlv| !tlv.is_padding());
+        match (8 - tlv_len % 8) % 8 {
+            0 => {},
+            1 => self.tlvs.push(SrhTlv::Pad1),
+            pad => self.tlvs.push(SrhTlv::PadN((pad - 2) as u8)),
+        }
+        Ok(())
+    }
+    
+    pub fn hmac_tlv(&self) -> Option<&HmacTlv> {
+        self.tlvs.iter().find_map(|tlv| match tlv {
+            SrhTlv::Hmac(hmac) => Some(hmac),
+            _ => None,
+        })
+    }
+    
+    // 送信元アドレスとセグメントリストに対するHMAC TLVを付与する（既存のHMAC TLVは置き換える）
+    pub fn set_hmac(&mut self, key: &SrhHmacKey, source: &Ipv6Addr) -> Result<(), Error> {
+        let hmac = compute_srh_hmac(key, source, self.last_entry, self.flags, &self.segment_list);
+        self.tlvs.retain(|tlv| !matches!(tlv, SrhTlv::Hmac(_)));
+        // HMAC TLVは40オクテットで、先頭に置けば8オクテット境界に揃う
+        self.tlvs.insert(0, SrhTlv::Hmac(HmacTlv {
+            destination_check_disabled: false,
+            key_id: key.key_id,
+            hmac: hmac.to_vec(),
+        }));
+        self.update_length()
+    }
+    
+    // HMAC TLVを定数時間で検証する
+    pub fn verify_hmac(&self, key: &SrhHmacKey, source: &Ipv6Addr) -> Result<(), Error> {
+        let tlv = self.hmac_tlv().ok_or(CryptoError::SrhHmac("HMAC TLVがありません"))?;
+        if tlv.key_id != key.key_id {
+            return Err(CryptoError::SrhHmac("HMAC Key IDが一致しません").into());
+        }
+        if !verify_srh_hmac(key, source, self.last_entry, self.flags, &self.segment_list, &tlv.hmac) {
+            return Err(CryptoError::SrhHmac("HMACが一致しません").into());
+        }
+        Ok(())
+    }
+    
+    // SRHの動的更新（README §3.5.4）: 未処理のセグメントを経路順の segments で置き換え、
+    // 先頭をアクティブSIDとして宛先に設定する。経路変更の有無や経路上の位置がヘッダー長から
+    // 分からないよう、Last EntryとHdr Ext Lenは変えずにエントリを上書きする。足りなければ処理済みの
+    // エントリも使い、余ったエントリは直前に処理したセグメント（なければ新しいアクティブSID）で埋める。
+    // HMAC TLVはセグメントリストを含むため、呼び出し側で付与し直す
+    pub fn replace_remaining(&mut self, ipv6_header: &mut Ipv6Header, segments: &[Ipv6Addr]) -> Result<Ipv6Addr, Error> {
+        let Some(&active_sid) = segments.first() else {
+            return Err(CommunicationError::MalformedSrh("置き換えるセグメントがありません".into()).into());
+        };
+        if segments.len() > self.segment_list.len() {
+            return Err(CommunicationError::MalformedSrh(format!(
+                "置き換えるセグメント数がSRHのエントリ数を超えています ({} > {})", segments.len(), self.segment_list.len())).into());
+        }
+        
+        let remaining = self.segments_left as usize + 1;
+        let filler = self.segment_list.get(remaining).copied().unwrap_or(active_sid);
+        for (index, entry) in self.segment_list.iter_mut().enumerate().take(remaining.max(segments.len())) {
+            // Segment List[0] が最後のセグメント
+            *entry = match segments.len().checked_sub(index + 1) {
+                Some(position) => segments[position],
+                None => filler,
+            };
+        }
+        self.segments_left = (segments.len() - 1) as u8;
+        ipv6_header.destination = active_sid;
+        Ok(active_sid)
+    }
+    
+    // 宛先のキャリアまたはSRHにまだ処理すべきセグメントが残っているか
+    pub fn has_remaining(&self, destination: &Ipv6Addr, usid: Option<&UsidFormat>) -> bool {
+        self.segments_left != 0 || usid.and_then(|format| format.shift(destination)).is_some()
+    }
+    
+    pub fn get_current_sid(&self) -> Option<Ipv6Addr> {
+        self.segment_list.get(self.segments_left as usize).cloned()
+    }
+    
+    pub fn advance_segment(&mut self) -> Option<Ipv6Addr> {
+        if self.segments_left == 0 {
+            return None;
+        }
+        self.segments_left -= 1;
+        self.get_current_sid()
+    }
+    
+    // End動作（RFC 8986 §4.1）: Segments Leftを減らし、IPv6宛先を新しいアクティブSIDに書き換える。
+    // uSIDキャリア宛（NEXT-C-SID、RFC 9800 §4.1）なら、キャリアに次のuSIDが残る間は宛先をシフトするだけで
+    // Segments Leftは変えない
+    pub fn apply_end(&mut self, ipv6_header: &mut Ipv6Header, usid: Option<&UsidFormat>) -> Result<Ipv6Addr, Error> {
+        if let Some(next) = usid.and_then(|format| format.shift(&ipv6_header.destination)) {
+            ipv6_header.destination = next;
+            return Ok(next);
+        }
+        if self.segments_left == 0 {
+            return Err(CommunicationError::MalformedSrh("Segments Leftが0のパケットにEnd動作は適用できません".into()).into());
+        }
+        let active_sid = self.advance_segment()
+            .ok_or(CommunicationError::MalformedSrh("Segments Leftがセグメントリストの範囲外です".into()))?;
+        ipv6_header.destination = active_sid;
+        Ok(active_sid)
+    }
+}
+
+// 先頭8オクテットに続くセグメントリストとTLVの長さ（オクテット）をHdr Ext Len（8オクテット単位）にする
+fn hdr_ext_len(len: usize) -> Result<u8, Error> {
+    u8::try_from(len.div_ceil(8))
+        .map_err(|_| CommunicationError::MalformedSrh(format!("SRHがHdr Ext Lenで表せる長さを超えています: {} オクテット", len)).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+
+    fn sid(n: u16) -> Ipv6Addr {
+        Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, n)
+    }
+
+    #[test]
+    fn new_rejects_empty_and_oversized_segment_lists() {
+        assert_eq!(SRv6Header::new(Vec::new()).unwrap_err().code(), ErrorCode::MalformedSrh);
+        let too_many = (0..=MAX_SEGMENTS as u16).map(sid).collect();
+        assert_eq!(SRv6Header::new(too_many).unwrap_err().code(), ErrorCode::MalformedSrh);
+    }
+
+    #[test]
+    fn hdr_ext_len_stays_within_one_octet() {
+        let mut header = SRv6Header::new((1..=MAX_SEGMENTS as u16).map(sid).collect()).unwrap();
+        assert_eq!(header.hdr_ext_len, 254);
+        assert_eq!(header.to_bytes().len(), header.header_len());
+
+        // 40オクテットのHMAC TLVを加えると255を超えるため拒否する
+        assert_eq!(header.set_hmac(&hmac_key(), &sid(100)).unwrap_err().code(), ErrorCode::MalformedSrh);
+
+        // 125セグメント + HMAC TLV でちょうど255
+        let mut header = SRv6Header::new((1..=125).map(sid).collect()).unwrap();
+        header.set_hmac(&hmac_key(), &sid(100)).unwrap();
+        assert_eq!(header.hdr_ext_len, 255);
+        assert_eq!(SRv6Header::from_bytes(&header.to_bytes()).unwrap().to_bytes(), header.to_bytes());
+    }
+
+    fn replaced(segments_left: u8, replacement: &[Ipv6Addr]) -> Result<(SRv6Header, Ipv6Header), Error> {
+        let mut header = SRv6Header::new(vec![sid(1), sid(2), sid(3), sid(4)]).unwrap();
+        header.segments_left = segments_left;
+        let mut ipv6_header = Ipv6Header::new(sid(100), header.get_current_sid().unwrap(), 0);
+        let before = header.to_bytes().len();
+        let active_sid = header.replace_remaining(&mut ipv6_header, replacement)?;
+        assert_eq!(active_sid, replacement[0]);
+        assert_eq!(ipv6_header.destination, replacement[0]);
+        assert_eq!(header.to_bytes().len(), before);
+        assert_eq!((header.last_entry, header.hdr_ext_len), (3, 8));
+        Ok((header, ipv6_header))
+    }
+
+    #[test]
+    fn replace_remaining_with_fewer_segments_pads_with_processed_segment() {
+        // sid(1) を処理済み、sid(2) がアクティブ
+        let (header, _) = replaced(2, &[sid(7)]).unwrap();
+        assert_eq!(header.segment_list, vec![sid(7), sid(1), sid(1), sid(1)]);
+        assert_eq!(header.segments_left, 0);
+        assert_eq!(header.get_current_sid(), Some(sid(7)));
+    }
+
+    #[test]
+    fn replace_remaining_with_more_segments_reuses_processed_entries() {
+        let (mut header, _) = replaced(1, &[sid(7), sid(8), sid(9)]).unwrap();
+        assert_eq!(header.segment_list, vec![sid(9), sid(8), sid(7), sid(1)]);
+        assert_eq!(header.segments_left, 2);
+        assert_eq!(header.advance_segment(), Some(sid(8)));
+        assert_eq!(header.advance_segment(), Some(sid(9)));
+        assert_eq!(header.advance_segment(), None);
+    }
+
+    #[test]
+    fn replace_remaining_at_first_segment_pads_with_active_sid() {
+        let (header, _) = replaced(3, &[sid(7), sid(8)]).unwrap();
+        assert_eq!(header.segment_list, vec![sid(8), sid(7), sid(7), sid(7)]);
+    }
+
+    #[test]
+    fn replace_remaining_rejects_empty_or_oversized_replacement() {
+        assert_eq!(replaced(1, &[]).unwrap_err().code(), ErrorCode::MalformedSrh);
+        let too_many: Vec<_> = (10..15).map(sid).collect();
+        assert_eq!(replaced(1, &too_many).unwrap_err().code(), ErrorCode::MalformedSrh);
+    }
+
+    #[test]
+    fn new_orders_segments_in_reverse() {
+        let header = SRv6Header::new(vec![sid(1), sid(2), sid(3)]).unwrap();
+        assert_eq!(header.segment_list, vec![sid(3), sid(2), sid(1)]);
+        assert_eq!(header.last_entry, 2);
+        assert_eq!(header.segments_left, 2);
+        assert_eq!(header.get_current_sid(), Some(sid(1)));
+    }
+
+    fn hmac_key() -> SrhHmacKey {
+        SrhHmacKey { key_id: 7, key: vec![1; 32] }
+    }
+
+    #[test]
+    fn tlv_round_trip() {
+        let tlvs = [
+            SrhTlv::Pad1,
+            SrhTlv::PadN(3),
+            SrhTlv::Hmac(HmacTlv { destination_check_disabled: true, key_id: 9, hmac: vec![5; 16] }),
+            SrhTlv::Unknown { tlv_type: 200, value: vec![1, 2, 3] },
+        ];
+        for tlv in tlvs {
+            let bytes = tlv.to_bytes();
+            assert_eq!(SrhTlv::from_bytes(&bytes).unwrap(), (tlv, bytes.len()));
+        }
+    }
+
+    #[test]
+    fn tlv_rejects_truncated_or_misaligned_hmac() {
+        assert!(SrhTlv::from_bytes(&[]).is_err());
+        assert!(SrhTlv::from_bytes(&[SrhTlv::TYPE_PADN]).is_err());
+        assert!(SrhTlv::from_bytes(&[SrhTlv::TYPE_PADN, 2, 0]).is_err());
+        let hmac = SrhTlv::Hmac(HmacTlv { destination_check_disabled: false, key_id: 1, hmac: vec![0; 12] });
+        assert_eq!(SrhTlv::from_bytes(&hmac.to_bytes()).unwrap_err().code(), ErrorCode::MalformedSrh);
+    }
+
+    #[test]
+    fn header_round_trip_with_hmac() {
+        let source = sid(100);
+        let mut header = SRv6Header::new(vec![sid(1), sid(2), sid(3)]).unwrap();
+        header.flags = 0x10;
+        header.tag = 0xbeef;
+        header.set_hmac(&hmac_key(), &source).unwrap();
+        let bytes = header.to_bytes();
+        assert_eq!(bytes.len(), header.header_len());
+        assert_eq!(bytes.len() % 8, 0);
+
+        let decoded = SRv6Header::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.to_bytes(), bytes);
+        assert_eq!(decoded.segment_list, header.segment_list);
+        assert_eq!((decoded.segments_left, decoded.last_entry, decoded.flags, decoded.tag), (2, 2, 0x10, 0xbeef));
+        assert_eq!(decoded.tlvs, header.tlvs);
+        decoded.verify_hmac(&hmac_key(), &source).unwrap();
+    }
+
+    #[test]
+    fn update_length_pads_tlvs_to_eight_octets() {
+        let mut header = SRv6Header::new(vec![sid(1)]).unwrap();
+        header.tlvs.push(SrhTlv::Unknown { tlv_type: 200, value: vec![1, 2, 3, 4, 5] });
+        header.update_length().unwrap();
+        assert_eq!(header.tlvs.last(), Some(&SrhTlv::Pad1));
+        assert_eq!(header.to_bytes().len(), header.header_len());
+
+        header.tlvs[0] = SrhTlv::Unknown { tlv_type: 200, value: vec![1] };
+        header.update_length().unwrap();
+        assert_eq!(header.tlvs.last(), Some(&SrhTlv::PadN(3)));
+        assert_eq!(header.to_bytes().len(), header.header_len());
+    }
+
+    #[test]
+    fn verify_hmac_detects_changes() {
+        let source = sid(100);
+        let mut header = SRv6Header::new(vec![sid(1), sid(2)]).unwrap();
+        header.set_hmac(&hmac_key(), &source).unwrap();
+
+        let mut tampered = header.clone();
+        tampered.segment_list[0] = sid(9);
+        assert!(tampered.verify_hmac(&hmac_key(), &source).is_err());
+        assert!(header.verify_hmac(&hmac_key(), &sid(101)).is_err());
+        assert!(header.verify_hmac(&SrhHmacKey { key_id: 8, ..hmac_key() }, &source).is_err());
+    }
+
+    #[test]
+    fn apply_end_rewrites_destination() {
+        let mut header = SRv6Header::new(vec![sid(1), sid(2)]).unwrap();
+        let mut ipv6_header = Ipv6Header::new(sid(100), sid(1), 0);
+        assert_eq!(header.apply_end(&mut ipv6_header, None).unwrap(), sid(2));
+        assert_eq!(ipv6_header.destination, sid(2));
+        assert_eq!(header.segments_left, 0);
+        assert_eq!(header.apply_end(&mut ipv6_header, None).unwrap_err().code(), ErrorCode::MalformedSrh);
+    }
+
+    #[test]
+    fn from_bytes_rejects_inconsistent_header() {
+        let bytes = SRv6Header::new(vec![sid(1), sid(2)]).unwrap().to_bytes();
+        let corrupt = |index: usize, value: u8| {
+            let mut bytes = bytes.clone();
+            bytes[index] = value;
+            SRv6Header::from_bytes(&bytes).unwrap_err().code()
+        };
+        assert_eq!(corrupt(2, 3), ErrorCode::MalformedSrh); // Routing Type
+        assert_eq!(corrupt(3, 2), ErrorCode::MalformedSrh); // Segments Left > Last Entry
+        assert_eq!(corrupt(4, 2), ErrorCode::MalformedSrh); // Last Entry がヘッダー長を超える
+        assert!(SRv6Header::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
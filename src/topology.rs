@@ -0,0 +1,874 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashSet};
+use std::fs;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+use p384::PublicKey;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::config::Role;
+use crate::crypto::decode_public_key;
+use crate::error::{ConfigError, Error, PathError};
+use crate::onion::MAX_HOPS;
+use crate::policy::{PolicyMetadata, PolicySegment, SRv6Policy};
+use crate::ranking::{select_paths, PathSelection};
+use crate::sid::UsidFormat;
+
+// 定数
+pub const DEFAULT_TRUST_SCORE: f64 = 0.5;
+const MAX_YEN_ITERATIONS_PER_PATH: usize = 16; // 制約違反の候補を読み飛ばす回数の上限（K 1本あたり）
+
+// ノードが提供するTEEの種別
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TeeType {
+    #[default]
+    None,
+    Sgx,
+    Sev,
+    Trustzone,
+}
+
+// グラフのノード（README §3.4.1）
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TopologyNode {
+    pub id: String,
+    #[serde(default = "default_role")]
+    pub role: Role,                  // entry=送信者, relay=中継ノード, exit=受信者
+    #[serde(default)]
+    pub sid: Option<Ipv6Addr>,       // 中継ノードのSID。ないノードは経路の途中に置けない
+    #[serde(default)]
+    pub address: Option<SocketAddr>, // ハンドシェイク・UDP転送先
+    #[serde(default = "default_trust_score")]
+    pub trust_score: f64,            // 信頼度スコア（0〜1）
+    #[serde(default)]
+    pub tee: TeeType,
+    #[serde(default)]
+    pub capacity: u32,               // 処理能力（同時に扱えるセッション数の目安。0は不明）
+    #[serde(default)]
+    pub public_key: Option<String>,  // 長期識別鍵の公開鍵（SEC1の16進表記、keygenの表示と同じ）
+    #[serde(default)]
+    pub key_file: Option<PathBuf>,   // demo で起動する際に読み込む長期識別鍵ファイル
+}
+
+// グラフのエッジ（有向）
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TopologyEdge {
+    pub from: String,
+    pub to: String,
+    pub latency_ms: f64,
+    pub bandwidth_mbps: f64,
+    #[serde(default)]
+    pub loss: f64,       // パケットロス率（%）
+    #[serde(default)]
+    pub congestion: f64, // 混雑度（0〜1）
+}
+
+// 経路探索の制約条件
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PathConstraints {
+    #[serde(default)]
+    pub min_bandwidth_mbps: Option<f64>, // これ未満のエッジは使わない
+    #[serde(default)]
+    pub max_latency_ms: Option<f64>,     // 経路全体の遅延の上限
+    #[serde(default)]
+    pub min_trust_score: Option<f64>,    // これ未満の中継ノードは使わない
+    #[serde(default)]
+    pub require_tee: bool,               // TEEを持たない中継ノードは使わない
+    #[serde(default = "default_max_relays")]
+    pub max_relays: usize,               // 中継ノード数の上限（Onionヘッダーのホップ数から受信者分を除く）
+}
+
+// 探索で見つかった経路（送信元から宛先までのノードID列）
+#[derive(Clone, Debug, PartialEq)]
+pub struct TopologyPath {
+    pub nodes: Vec<String>,
+    pub latency_ms: f64,      // 遅延の合計
+    pub bandwidth_mbps: f64,  // ボトルネック帯域
+    pub loss: f64,            // 経路全体のロス率（%）
+    pub min_trust_score: f64, // 中継ノードの信頼度の最小値
+}
+
+// ネットワークトポロジーのグラフ
+#[derive(Clone, Debug, Default)]
+pub struct Topology {
+    nodes: BTreeMap<String, TopologyNode>,
+    edges: BTreeMap<String, Vec<TopologyEdge>>, // 始点ノードID → 出ていくエッジ
+}
+
+impl Default for PathConstraints {
+    fn default() -> Self {
+        Self {
+            min_bandwidth_mbps: None,
+            max_latency_ms: None,
+            min_trust_score: None,
+            require_tee: false,
+            max_relays: default_max_relays(),
+        }
+    }
+}
+
+impl TopologyNode {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            role: Role::Relay,
+            sid: None,
+            address: None,
+            trust_score: DEFAULT_TRUST_SCORE,
+            tee: TeeType::None,
+            capacity: 0,
+            public_key: None,
+            key_file: None,
+        }
+    }
+
+    pub fn with_role(mut self, role: Role) -> Self {
+        self.role = role;
+        self
+    }
+
+    pub fn with_sid(mut self, sid: Ipv6Addr) -> Self {
+        self.sid = Some(sid);
+        self
+    }
+
+    pub fn with_address(mut self, address: SocketAddr) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    pub fn with_trust_score(mut self, trust_score: f64) -> Self {
+        self.trust_score = trust_score;
+        self
+    }
+
+    pub fn with_tee(mut self, tee: TeeType) -> Self {
+        self.tee = tee;
+        self
+    }
+
+    // 公開鍵の表記が不正ならNone（TopologyFile::validate で検出済み）
+    pub fn identity_public_key(&self) -> Option<PublicKey> {
+        self.public_key.as_deref().and_then(decode_public_key)
+    }
+}
+
+impl TopologyEdge {
+    pub fn new(from: &str, to: &str, latency_ms: f64, bandwidth_mbps: f64) -> Self {
+        Self {
+            from: from.to_string(),
+            to: to.to_string(),
+            latency_ms,
+            bandwidth_mbps,
+            loss: 0.0,
+            congestion: 0.0,
+        }
+    }
+}
+
+impl TopologyPath {
+    // 中継ノード（送信元と宛先を除く）のID
+    pub fn relays(&self) -> &[String] {
+        match self.nodes.len() {
+            0..=2 => &[],
+            len => &self.nodes[1..len - 1],
+        }
+    }
+
+    // 経路上の有向エッジ
+    pub fn edges(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.nodes.windows(2).map(|pair| (pair[0].as_str(), pair[1].as_str()))
+    }
+}
+
+impl Topology {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_node(&mut self, node: TopologyNode) {
+        self.nodes.insert(node.id.clone(), node);
+    }
+
+    // 有向エッジを追加（同じ向きのエッジがあれば置き換える）
+    pub fn add_edge(&mut self, edge: TopologyEdge) -> Result<(), Error> {
+        for id in [&edge.from, &edge.to] {
+            if !self.nodes.contains_key(id) {
+                return Err(PathError::UnknownNode(id.clone()).into());
+            }
+        }
+        let edges = self.edges.entry(edge.from.clone()).or_default();
+        edges.retain(|existing| existing.to != edge.to);
+        edges.push(edge);
+        Ok(())
+    }
+
+    // 双方向のリンクとしてエッジを追加
+    pub fn add_link(&mut self, edge: TopologyEdge) -> Result<(), Error> {
+        let reverse = TopologyEdge { from: edge.to.clone(), to: edge.from.clone(), ..edge.clone() };
+        self.add_edge(edge)?;
+        self.add_edge(reverse)
+    }
+
+    pub fn node(&self, id: &str) -> Option<&TopologyNode> {
+        self.nodes.get(id)
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &TopologyNode> {
+        self.nodes.values()
+    }
+
+    pub fn edge(&self, from: &str, to: &str) -> Option<&TopologyEdge> {
+        self.edges.get(from)?.iter().find(|edge| edge.to == to)
+    }
+
+    pub fn edges(&self) -> impl Iterator<Item = &TopologyEdge> {
+        self.edges.values().flatten()
+    }
+
+    // ノードから出ていくエッジの先のノード
+    pub fn neighbors(&self, id: &str) -> impl Iterator<Item = &TopologyNode> {
+        self.edges.get(id)
+            .into_iter()
+            .flatten()
+            .filter_map(|edge| self.nodes.get(&edge.to))
+    }
+
+    // 経路上の中継ノードのSIDとハンドシェイク先（SRv6ポリシーのセグメントリスト）
+    pub fn segments(&self, path: &TopologyPath) -> Result<Vec<PolicySegment>, Error> {
+        path.relays().iter()
+            .map(|id| match self.node(id) {
+                Some(TopologyNode { sid: Some(sid), address: Some(address), .. }) => {
+                    Ok(PolicySegment { sid: *sid, address: *address })
+                },
+                _ => Err(PathError::UnknownNode(id.clone()).into()),
+            })
+            .collect()
+    }
+
+    // 経路からSRv6ポリシーを作る。宛先ノードのアドレスを最終宛先とする
+    pub fn to_policy(&self, path: &TopologyPath, color: u32) -> Result<SRv6Policy, Error> {
+        let destination = path.nodes.last().map(String::as_str).unwrap_or_default();
+        let endpoint = self.node(destination)
+            .and_then(|node| node.address)
+            .ok_or_else(|| PathError::UnknownNode(destination.to_string()))?;
+        let metadata = PolicyMetadata {
+            latency_ms: Some(path.latency_ms.ceil() as u32),
+            bandwidth_mbps: Some(path.bandwidth_mbps.min(u32::MAX as f64) as u32),
+            priority: 0,
+        };
+        Ok(SRv6Policy::new(endpoint, color, self.segments(path)?).with_metadata(metadata))
+    }
+
+    // 制約付きYen's K最短経路（README §3.4.1）。遅延の小さい順に最大 k 本を返す
+    pub fn k_shortest_paths(&self,
+                            source: &str,
+                            destination: &str,
+                            k: usize,
+                            constraints: &PathConstraints) -> Result<Vec<TopologyPath>, Error> {
+        for id in [source, destination] {
+            if !self.nodes.contains_key(id) {
+                return Err(PathError::UnknownNode(id.to_string()).into());
+            }
+        }
+        if k == 0 || source == destination {
+            return Ok(Vec::new());
+        }
+
+        // 見つかった経路（制約違反のものも分岐元として使う）と、そのうち制約を満たすもの
+        let mut found: Vec<TopologyPath> = Vec::new();
+        let mut accepted: Vec<TopologyPath> = Vec::new();
+        let mut candidates: Vec<TopologyPath> = Vec::new();
+        let no_edges = HashSet::new();
+        let no_nodes = HashSet::new();
+
+        let Some(first) = self.shortest_path(source, destination, constraints, &no_edges, &no_nodes) else {
+            return Ok(Vec::new());
+        };
+        let mut next = Some(first);
+        let max_iterations = k.saturating_mul(MAX_YEN_ITERATIONS_PER_PATH);
+
+        while let Some(path) = next.take() {
+            // 遅延の小さい順に見つかるため、上限を超えたら以降の経路もすべて超える
+            if constraints.max_latency_ms.is_some_and(|max| path.latency_ms > max) {
+                break;
+            }
+            if path.relays().len() <= constraints.max_relays {
+                accepted.push(path.clone());
+                if accepted.len() >= k {
+                    break;
+                }
+            }
+            found.push(path);
+            if found.len() >= max_iterations {
+                break;
+            }
+
+            // 直前の経路の各ノードを分岐点として迂回経路を探す
+            let previous = found.last().expect("直前の経路");
+            for i in 0..previous.nodes.len() - 1 {
+                let spur_node = &previous.nodes[i];
+                let root = &previous.nodes[..=i];
+
+                // 同じ根を持つ既知の経路の次のエッジと、根のノード（分岐点を除く）を一時的に除外
+                let removed_edges: HashSet<(&str, &str)> = found.iter()
+                    .chain(candidates.iter())
+                    .filter(|known| known.nodes.len() > i + 1 && known.nodes[..=i] == *root)
+                    .map(|known| (known.nodes[i].as_str(), known.nodes[i + 1].as_str()))
+                    .collect();
+                let removed_nodes: HashSet<&str> = root[..i].iter().map(String::as_str).collect();
+
+                let Some(spur) = self.shortest_path(spur_node, destination, constraints, &removed_edges, &removed_nodes) else {
+                    continue;
+                };
+                let mut nodes = root[..i].to_vec();
+                nodes.extend(spur.nodes);
+                let Some(candidate) = self.measure(nodes) else {
+                    continue;
+                };
+                let known = found.iter().chain(candidates.iter()).any(|path| path.nodes == candidate.nodes);
+                if !known {
+                    candidates.push(candidate);
+                }
+            }
+
+            // 次に良い候補（遅延、ノード数、ノードIDの順）を取り出す
+            candidates.sort_by(compare_paths);
+            if !candidates.is_empty() {
+                next = Some(candidates.remove(0));
+            }
+        }
+
+        Ok(accepted)
+    }
+
+    // 遅延を重みとする制約付きDijkstra
+    fn shortest_path(&self,
+                     source: &str,
+                     destination: &str,
+                     constraints: &PathConstraints,
+                     removed_edges: &HashSet<(&str, &str)>,
+                     removed_nodes: &HashSet<&str>) -> Option<TopologyPath> {
+        let mut distances: BTreeMap<&str, f64> = BTreeMap::new();
+        let mut previous: BTreeMap<&str, &str> = BTreeMap::new();
+        let mut queue = BinaryHeap::new();
+        distances.insert(source, 0.0);
+        queue.push(QueueEntry { cost: 0.0, node: source });
+
+        while let Some(QueueEntry { cost, node }) = queue.pop() {
+            if node == destination {
+                break;
+            }
+            if distances.get(node).is_some_and(|best| cost > *best) {
+                continue;
+            }
+            for edge in self.edges.get(node).into_iter().flatten() {
+                let to = edge.to.as_str();
+                if removed_nodes.contains(to) || removed_edges.contains(&(node, to)) {
+                    continue;
+                }
+                if constraints.min_bandwidth_mbps.is_some_and(|min| edge.bandwidth_mbps < min) {
+                    continue;
+                }
+                // 宛先以外の経由ノードは中継ノードとして使えなければならない
+                if to != destination && !self.usable_relay(to, constraints) {
+                    continue;
+                }
+                let next_cost = cost + edge.latency_ms;
+                if distances.get(to).is_none_or(|best| next_cost < *best) {
+                    distances.insert(to, next_cost);
+                    previous.insert(to, node);
+                    queue.push(QueueEntry { cost: next_cost, node: to });
+                }
+            }
+        }
+
+        distances.get(destination)?;
+        let mut nodes = vec![destination.to_string()];
+        let mut current = destination;
+        while current != source {
+            current = previous.get(current)?;
+            nodes.push(current.to_string());
+        }
+        nodes.reverse();
+        self.measure(nodes)
+    }
+
+    fn usable_relay(&self, id: &str, constraints: &PathConstraints) -> bool {
+        let Some(node) = self.nodes.get(id) else {
+            return false;
+        };
+        node.sid.is_some()
+            && constraints.min_trust_score.is_none_or(|min| node.trust_score >= min)
+            && (!constraints.require_tee || node.tee != TeeType::None)
+    }
+
+    // ノード列の各エッジから経路の指標を集計する（エッジがなければNone）
+    fn measure(&self, nodes: Vec<String>) -> Option<TopologyPath> {
+        let mut latency_ms = 0.0;
+        let mut bandwidth_mbps = f64::INFINITY;
+        let mut delivery = 1.0;
+        for pair in nodes.windows(2) {
+            let edge = self.edge(&pair[0], &pair[1])?;
+            latency_ms += edge.latency_ms;
+            bandwidth_mbps = bandwidth_mbps.min(edge.bandwidth_mbps);
+            delivery *= 1.0 - edge.loss / 100.0;
+        }
+        let min_trust_score = nodes.get(1..nodes.len().saturating_sub(1))
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|id| self.nodes.get(id))
+            .map(|node| node.trust_score)
+            .fold(1.0, f64::min);
+        Some(TopologyPath {
+            nodes,
+            latency_ms,
+            bandwidth_mbps,
+            loss: (1.0 - delivery) * 100.0,
+            min_trust_score,
+        })
+    }
+}
+
+// トポロジーファイル: ノード、リンクとその指標、経路選択の設定
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TopologyFile {
+    pub nodes: Vec<TopologyNode>,
+    #[serde(default)]
+    pub links: Vec<TopologyEdge>, // 双方向のリンク
+    #[serde(default)]
+    pub edges: Vec<TopologyEdge>, // 片方向のエッジ
+    #[serde(default)]
+    pub usid: Option<UsidFormat>, // 中継ノードのSIDがuSID形式なら経路をuSIDキャリアに詰める
+    #[serde(default)]
+    pub path_selection: PathSelection,
+}
+
+impl TopologyFile {
+    // 拡張子が .json ならJSON、それ以外はYAMLとして読み込み、検証する
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = fs::read_to_string(path)
+            .map_err(|source| ConfigError::Read { path: path.to_path_buf(), source })?;
+        let file = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::from_json_str(&text)?,
+            _ => Self::from_yaml_str(&text)?,
+        };
+        file.validate()?;
+        Ok(file)
+    }
+
+    pub fn from_yaml_str(text: &str) -> Result<Self, Error> {
+        serde_yaml::from_str(text)
+            .map_err(|e| ConfigError::Parse(format!("YAML: {}", e)).into())
+    }
+
+    pub fn from_json_str(text: &str) -> Result<Self, Error> {
+        serde_json::from_str(text)
+            .map_err(|e| ConfigError::Parse(format!("JSON: {}", e)).into())
+    }
+
+    // 内容を検証し、問題をすべてまとめて報告する
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut problems = Vec::new();
+        let mut ids = HashSet::new();
+        let mut addresses = HashSet::new();
+        let mut sids = HashSet::new();
+
+        if self.nodes.is_empty() {
+            problems.push("nodes が空です".to_string());
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.id.trim().is_empty() {
+                problems.push(format!("nodes[{}].id が空です", i));
+            } else if !ids.insert(node.id.as_str()) {
+                problems.push(format!("nodes[{}].id が重複しています: {}", i, node.id));
+            }
+            if !(0.0..=1.0).contains(&node.trust_score) {
+                problems.push(format!("nodes[{}].trust_score は0〜1の範囲で指定してください: {}", i, node.trust_score));
+            }
+            match node.address {
+                None => problems.push(format!("nodes[{}].address がありません: {}", i, node.id)),
+                Some(address) if node.role != Role::Entry && address.port() == 0 => {
+                    problems.push(format!("nodes[{}].address にポート番号を指定してください: {}", i, node.id));
+                },
+                Some(address) if !addresses.insert(address) => {
+                    problems.push(format!("nodes[{}].address が重複しています: {}", i, address));
+                },
+                Some(_) => {},
+            }
+            match node.sid {
+                None if node.role == Role::Relay => {
+                    problems.push(format!("nodes[{}].sid がありません（relayロールには必要です）: {}", i, node.id));
+                },
+                Some(sid) if !sids.insert(sid) => problems.push(format!("nodes[{}].sid が重複しています: {}", i, sid)),
+                _ => {},
+            }
+            if let (Some(sid), Some(format)) = (node.sid, self.usid) {
+                if node.role == Role::Relay && !format.is_usid(&sid) {
+                    problems.push(format!("nodes[{}].sid がuSID形式ではありません: {}", i, sid));
+                }
+            }
+            if node.public_key.is_some() && node.identity_public_key().is_none() {
+                problems.push(format!("nodes[{}].public_key がP-384公開鍵として不正です: {}", i, node.id));
+            }
+            if let Some(path) = &node.key_file {
+                if !path.is_file() {
+                    problems.push(format!("nodes[{}].key_file が見つかりません: {}", i, path.display()));
+                }
+            }
+        }
+
+        let edges = [("links", &self.links), ("edges", &self.edges)]
+            .into_iter()
+            .flat_map(|(field, edges)| edges.iter().enumerate().map(move |(i, edge)| (format!("{}[{}]", field, i), edge)));
+        for (name, edge) in edges {
+            for id in [&edge.from, &edge.to] {
+                if !ids.contains(id.as_str()) {
+                    problems.push(format!("{} のノードが見つかりません: {}", name, id));
+                }
+            }
+            if edge.from == edge.to {
+                problems.push(format!("{} の始点と終点が同じです: {}", name, edge.from));
+            }
+            if !edge.latency_ms.is_finite() || edge.latency_ms < 0.0 {
+                problems.push(format!("{}.latency_ms は0以上が必要です: {}", name, edge.latency_ms));
+            }
+            if !edge.bandwidth_mbps.is_finite() || edge.bandwidth_mbps <= 0.0 {
+                problems.push(format!("{}.bandwidth_mbps は正の値が必要です: {}", name, edge.bandwidth_mbps));
+            }
+            if !(0.0..=100.0).contains(&edge.loss) {
+                problems.push(format!("{}.loss は0〜100の範囲で指定してください: {}", name, edge.loss));
+            }
+            if !(0.0..=1.0).contains(&edge.congestion) {
+                problems.push(format!("{}.congestion は0〜1の範囲で指定してください: {}", name, edge.congestion));
+            }
+        }
+
+        if let Some(Err(e)) = self.usid.map(|format| format.validate()) {
+            problems.push(format!("usid: {}", e));
+        }
+        if let Err(e) = self.path_selection.validate() {
+            match e {
+                Error::Config(ConfigError::Invalid(mut selection_problems)) => problems.append(&mut selection_problems),
+                e => problems.push(e.to_string()),
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems).into())
+        }
+    }
+
+    // 経路探索用のグラフを構築する
+    pub fn topology(&self) -> Result<Topology, Error> {
+        let mut topology = Topology::new();
+        for node in &self.nodes {
+            topology.add_node(node.clone());
+        }
+        for link in &self.links {
+            topology.add_link(link.clone())?;
+        }
+        for edge in &self.edges {
+            topology.add_edge(edge.clone())?;
+        }
+        Ok(topology)
+    }
+
+    // IDを指定すればそのノード（ロールが一致すること）、省略すれば指定ロールの唯一のノード
+    pub fn endpoint(&self, role: Role, id: Option<&str>) -> Result<&TopologyNode, Error> {
+        if let Some(id) = id {
+            let node = self.nodes.iter()
+                .find(|node| node.id == id)
+                .ok_or_else(|| PathError::UnknownNode(id.to_string()))?;
+            if node.role != role {
+                return Err(ConfigError::Invalid(vec![format!("{} は{}ロールではありません: {}", id, role, node.role)]).into());
+            }
+            return Ok(node);
+        }
+        let mut candidates = self.nodes.iter().filter(|node| node.role == role);
+        match (candidates.next(), candidates.next()) {
+            (Some(node), None) => Ok(node),
+            (None, _) => Err(ConfigError::Invalid(vec![format!("{}ロールのノードがありません", role)]).into()),
+            (Some(_), Some(_)) => Err(ConfigError::Invalid(
+                vec![format!("{}ロールのノードが複数あります。ノードIDを指定してください", role)]).into()),
+        }
+    }
+
+    // 経路を探索・評価し、選択順（優先度1が先頭）のSRv6ポリシーにする。送信元にアドレスがあればヘッドエンドとする
+    pub fn policies(&self,
+                    source: &TopologyNode,
+                    destination: &TopologyNode,
+                    selection: &PathSelection,
+                    color: u32) -> Result<Vec<SRv6Policy>, Error> {
+        let topology = self.topology()?;
+        let ranked = select_paths(&topology,
+                                  &source.id,
+                                  &destination.id,
+                                  selection.paths,
+                                  &selection.constraints,
+                                  &selection.weights)?;
+        ranked.iter()
+            .map(|ranked| {
+                info!(priority = ranked.priority, path = %ranked.path.nodes.join(" -> "), score = %ranked.score, "経路を選択");
+                let policy = ranked.to_policy(&topology, color)?;
+                Ok(match source.address {
+                    Some(address) => policy.with_headend(address),
+                    None => policy,
+                })
+            })
+            .collect()
+    }
+}
+
+fn compare_paths(a: &TopologyPath, b: &TopologyPath) -> Ordering {
+    a.latency_ms.total_cmp(&b.latency_ms)
+        .then(a.nodes.len().cmp(&b.nodes.len()))
+        .then_with(|| a.nodes.cmp(&b.nodes))
+}
+
+fn default_role() -> Role {
+    Role::Relay
+}
+
+fn default_trust_score() -> f64 {
+    DEFAULT_TRUST_SCORE
+}
+
+fn default_max_relays() -> usize {
+    MAX_HOPS - 1
+}
+
+// Dijkstraの優先度付きキューの要素（コストの小さい順に取り出す）
+struct QueueEntry<'a> {
+    cost: f64,
+    node: &'a str,
+}
+
+impl PartialEq for QueueEntry<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueueEntry<'_> {}
+
+impl PartialOrd for QueueEntry<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueueEntry<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost).then_with(|| other.node.cmp(self.node))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::public_key_hex;
+    use crate::error::ErrorCode;
+    use p384::SecretKey;
+    use rand::rngs::OsRng;
+
+    // s → {a, b} → c → d の有向グラフ。a, c はTEEあり、b は信頼度が低い
+    fn topology() -> Topology {
+        let mut topology = Topology::new();
+        topology.add_node(TopologyNode::new("s").with_role(Role::Entry));
+        topology.add_node(TopologyNode::new("a").with_sid("2001:db8::a".parse().unwrap()).with_tee(TeeType::Sgx));
+        topology.add_node(TopologyNode::new("b").with_sid("2001:db8::b".parse().unwrap()).with_trust_score(0.2));
+        topology.add_node(TopologyNode::new("c").with_sid("2001:db8::c".parse().unwrap()).with_tee(TeeType::Sev));
+        topology.add_node(TopologyNode::new("d").with_role(Role::Exit));
+        for (from, to, latency_ms, bandwidth_mbps) in [
+            ("s", "a", 1.0, 1000.0),
+            ("s", "b", 2.0, 1000.0),
+            ("a", "b", 1.0, 1000.0),
+            ("a", "c", 3.0, 1000.0),
+            ("b", "c", 1.0, 10.0),
+            ("c", "d", 1.0, 1000.0),
+            ("b", "d", 4.0, 1000.0),
+        ] {
+            topology.add_edge(TopologyEdge::new(from, to, latency_ms, bandwidth_mbps)).unwrap();
+        }
+        topology
+    }
+
+    fn paths(constraints: &PathConstraints) -> Vec<String> {
+        topology().k_shortest_paths("s", "d", 5, constraints)
+            .unwrap()
+            .iter()
+            .map(|path| path.nodes.concat())
+            .collect()
+    }
+
+    #[test]
+    fn returns_paths_in_latency_order() {
+        assert_eq!(paths(&PathConstraints::default()), ["sbcd", "sabcd", "sacd", "sbd", "sabd"]);
+
+        let shortest = topology().k_shortest_paths("s", "d", 1, &PathConstraints::default()).unwrap();
+        assert_eq!(shortest[0].latency_ms, 4.0);
+        assert_eq!(shortest[0].bandwidth_mbps, 10.0);
+        assert_eq!(shortest[0].min_trust_score, 0.2);
+        assert_eq!(shortest[0].relays(), ["b", "c"]);
+    }
+
+    #[test]
+    fn skips_edges_below_min_bandwidth() {
+        let constraints = PathConstraints { min_bandwidth_mbps: Some(100.0), ..PathConstraints::default() };
+        assert_eq!(paths(&constraints), ["sacd", "sbd", "sabd"]);
+    }
+
+    #[test]
+    fn skips_untrusted_relays_and_relays_without_tee() {
+        let trusted = PathConstraints { min_trust_score: Some(0.5), ..PathConstraints::default() };
+        assert_eq!(paths(&trusted), ["sacd"]);
+        let tee = PathConstraints { require_tee: true, ..PathConstraints::default() };
+        assert_eq!(paths(&tee), ["sacd"]);
+    }
+
+    #[test]
+    fn limits_latency_and_relay_count() {
+        let latency = PathConstraints { max_latency_ms: Some(5.0), ..PathConstraints::default() };
+        assert_eq!(paths(&latency), ["sbcd", "sabcd", "sacd"]);
+        let relays = PathConstraints { max_relays: 1, ..PathConstraints::default() };
+        assert_eq!(paths(&relays), ["sbd"]);
+    }
+
+    #[test]
+    fn handles_trivial_and_unknown_endpoints() {
+        let topology = topology();
+        let constraints = PathConstraints::default();
+        assert!(topology.k_shortest_paths("s", "d", 0, &constraints).unwrap().is_empty());
+        assert!(topology.k_shortest_paths("s", "s", 3, &constraints).unwrap().is_empty());
+        assert!(topology.k_shortest_paths("d", "s", 3, &constraints).unwrap().is_empty());
+        assert!(topology.k_shortest_paths("s", "x", 3, &constraints).is_err());
+    }
+
+    const TOPOLOGY_YAML: &str = r#"
+nodes:
+  - { id: "sender", role: "entry", address: "[::1]:9100" }
+  - { id: "r1", sid: "2001:db8::1", address: "[::1]:9101" }
+  - { id: "r2", sid: "2001:db8::2", address: "[::1]:9102" }
+  - { id: "receiver", role: "exit", address: "[::1]:9105" }
+links:
+  - { from: "sender", to: "r1", latency_ms: 5.0, bandwidth_mbps: 1000.0 }
+  - { from: "r1", to: "r2", latency_ms: 1.0, bandwidth_mbps: 1000.0 }
+  - { from: "r2", to: "receiver", latency_ms: 2.0, bandwidth_mbps: 1000.0 }
+"#;
+
+    fn topology_file() -> TopologyFile {
+        let file = TopologyFile::from_yaml_str(TOPOLOGY_YAML).unwrap();
+        file.validate().unwrap();
+        file
+    }
+
+    fn problems(file: &TopologyFile) -> Vec<String> {
+        match file.validate() {
+            Err(Error::Config(ConfigError::Invalid(problems))) => problems,
+            other => panic!("検証エラーになるはず: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn topology_file_builds_graph_with_bidirectional_links() {
+        let topology = topology_file().topology().unwrap();
+        let paths = topology.k_shortest_paths("sender", "receiver", 3, &PathConstraints::default()).unwrap();
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].relays(), ["r1", "r2"]);
+        assert!(topology.edge("receiver", "r2").is_some());
+    }
+
+    #[test]
+    fn policies_follow_selected_paths_from_source() {
+        let file = topology_file();
+        let source = file.endpoint(Role::Entry, None).unwrap();
+        let destination = file.endpoint(Role::Exit, None).unwrap();
+        let policies = file.policies(source, destination, &file.path_selection, 7).unwrap();
+        assert_eq!(policies.len(), 1);
+        assert_eq!(policies[0].sids(), ["2001:db8::1".parse::<Ipv6Addr>().unwrap(), "2001:db8::2".parse().unwrap()]);
+        assert_eq!(policies[0].headend, source.address);
+        assert_eq!(policies[0].endpoint, destination.address.unwrap());
+        assert_eq!(policies[0].color, 7);
+    }
+
+    #[test]
+    fn loads_example_topology() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("config/topology.example.yaml");
+        let file = TopologyFile::load(&path).unwrap();
+        assert_eq!(file.endpoint(Role::Entry, None).unwrap().id, "sender");
+        assert_eq!(file.endpoint(Role::Exit, None).unwrap().id, "receiver");
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let text = TOPOLOGY_YAML.replace("links:", "unknown: 1\nlinks:");
+        assert_eq!(TopologyFile::from_yaml_str(&text).unwrap_err().code(), ErrorCode::ConfigParse);
+    }
+
+    #[test]
+    fn validate_reports_duplicates_together() {
+        let mut file = topology_file();
+        let mut duplicate = file.nodes[1].clone();
+        duplicate.public_key = Some("02abcd".into());
+        file.nodes.push(duplicate);
+
+        let problems = problems(&file);
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems[0].contains("id が重複"));
+        assert!(problems[1].contains("address が重複"));
+        assert!(problems[2].contains("sid が重複"));
+        assert!(problems[3].contains("public_key"));
+    }
+
+    #[test]
+    fn validate_requires_sid_for_relays_only() {
+        let mut file = topology_file();
+        file.nodes[1].sid = None;
+        let problems = problems(&file);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("sid がありません"));
+
+        // 送信者・受信者にはSIDは不要
+        assert!(topology_file().nodes.iter().filter(|node| node.role != Role::Relay).all(|node| node.sid.is_none()));
+    }
+
+    #[test]
+    fn validate_accepts_valid_public_key() {
+        let mut file = topology_file();
+        let key = public_key_hex(&SecretKey::random(&mut OsRng).public_key());
+        file.nodes[1].public_key = Some(key);
+        file.validate().unwrap();
+        assert!(file.nodes[1].identity_public_key().is_some());
+    }
+
+    #[test]
+    fn validate_checks_links() {
+        let mut file = topology_file();
+        file.links[0].to = "missing".into();
+        file.links[1].bandwidth_mbps = 0.0;
+        assert_eq!(problems(&file).len(), 2);
+    }
+
+    #[test]
+    fn endpoint_requires_unique_role_or_matching_id() {
+        let mut file = topology_file();
+        assert_eq!(file.endpoint(Role::Relay, Some("r2")).unwrap().id, "r2");
+        assert_eq!(file.endpoint(Role::Exit, Some("r1")).unwrap_err().code(), ErrorCode::ConfigInvalid);
+        assert_eq!(file.endpoint(Role::Exit, Some("missing")).unwrap_err().code(), ErrorCode::UnknownNode);
+
+        // relayロールは複数あるため、IDを省略すると選べない
+        assert_eq!(file.endpoint(Role::Relay, None).unwrap_err().code(), ErrorCode::ConfigInvalid);
+        file.nodes.retain(|node| node.role != Role::Exit);
+        assert_eq!(file.endpoint(Role::Exit, None).unwrap_err().code(), ErrorCode::ConfigInvalid);
+    }
+}
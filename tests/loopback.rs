@@ -0,0 +1,258 @@
+// ループバック上で送信者→中継ノード→受信者を実際に動かす結合テスト
+use std::collections::HashMap;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use hornet_plus::crypto::SessionKeys;
+use hornet_plus::message::{MessageType, SealedMessage};
+use hornet_plus::node::{DeliveredMessage, Node, NodeType};
+use hornet_plus::pathchange::{PathChange, ReverseLayer, SignedPathChange};
+use hornet_plus::pathlist::{PathList, DEFAULT_PATH_LIST_LIFETIME};
+use hornet_plus::policy::{PolicySegment, SRv6Policy, COLOR_DEFAULT};
+use hornet_plus::reroute::{alternative_routes, RerouteReason};
+use hornet_plus::sender::Sender;
+use p384::{PublicKey, SecretKey};
+use rand::rngs::OsRng;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(2);
+const PATH_CHANGE_WAIT: Duration = Duration::from_millis(500);
+const SESSION_ID: u32 = 0x4e54;
+
+fn sid(n: u16) -> Ipv6Addr {
+    Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, n)
+}
+
+async fn bind() -> UdpSocket {
+    UdpSocket::bind("127.0.0.1:0").await.unwrap()
+}
+
+// ノードを別タスクで起動する
+fn spawn(node: Node, socket: UdpSocket) -> Arc<Node> {
+    let node = Arc::new(node);
+    let running = Arc::clone(&node);
+    tokio::spawn(async move { running.run(Arc::new(socket)).await });
+    node
+}
+
+struct Network {
+    sender: Node,
+    sender_socket: UdpSocket,
+    relays: Vec<(PolicySegment, Arc<Node>)>,
+    identity_keys: Vec<SecretKey>,
+    receiver: SocketAddr,
+    receiver_key: PublicKey,
+    messages: mpsc::UnboundedReceiver<DeliveredMessage>,
+}
+
+// 中継ノード count 個と受信者を起動する。中継ノードは互いのSIDへの経路と識別公開鍵を持ち、
+// 送信者は中継ノードの識別公開鍵を信頼する
+async fn network(count: u16) -> Network {
+    let mut sockets = Vec::new();
+    for n in 1..=count {
+        let socket = bind().await;
+        sockets.push((PolicySegment { sid: sid(n), address: socket.local_addr().unwrap() }, socket));
+    }
+    let segments: Vec<PolicySegment> = sockets.iter().map(|(segment, _)| *segment).collect();
+    let identity_keys: Vec<SecretKey> = segments.iter().map(|_| SecretKey::random(&mut OsRng)).collect();
+
+    let relays = sockets.into_iter()
+        .zip(&identity_keys)
+        .map(|((segment, socket), identity_key)| {
+            let node = Node::new(NodeType::Relay(segment.sid), segment.address)
+                .with_probe_interval(None)
+                .with_identity_key(identity_key.clone());
+            for neighbor in segments.iter().filter(|neighbor| neighbor.sid != segment.sid) {
+                node.add_sid_route(neighbor.sid, neighbor.address);
+            }
+            for (sid, identity_key) in segments.iter().map(|segment| segment.sid).zip(&identity_keys) {
+                node.add_trusted_key(sid, identity_key.public_key());
+            }
+            (segment, spawn(node, socket))
+        })
+        .collect();
+
+    let (sink, messages) = mpsc::unbounded_channel();
+    let receiver_socket = bind().await;
+    let receiver = receiver_socket.local_addr().unwrap();
+    let receiver_key = SecretKey::random(&mut OsRng);
+    spawn(Node::new(NodeType::Receiver, receiver)
+              .with_identity_key(receiver_key.clone())
+              .with_message_sink(sink),
+          receiver_socket);
+
+    let sender_socket = bind().await;
+    let sender = Node::new(NodeType::Sender, sender_socket.local_addr().unwrap());
+    for (segment, identity_key) in segments.iter().zip(&identity_keys) {
+        sender.add_trusted_key(segment.sid, identity_key.public_key());
+        sender.add_peer_key(segment.address, identity_key.public_key());
+    }
+    sender.add_peer_key(receiver, receiver_key.public_key());
+    Network { sender, sender_socket, relays, identity_keys, receiver, receiver_key: receiver_key.public_key(), messages }
+}
+
+impl Network {
+    // 中継ノードの番号（1始まり）の順に通る経路
+    fn policy(&self, path: &[usize]) -> SRv6Policy {
+        let segments = path.iter().map(|n| self.relays[n - 1].0).collect();
+        SRv6Policy::new(self.receiver, COLOR_DEFAULT, segments)
+    }
+
+    fn relay(&self, n: usize) -> &Node {
+        &self.relays[n - 1].1
+    }
+
+    // 経路上のすべてのノードとハンドシェイクする
+    async fn establish(&self, policies: &[&SRv6Policy]) -> HashMap<SocketAddr, SessionKeys> {
+        let mut keys = HashMap::new();
+        for address in policies.iter().flat_map(|policy| policy.node_addresses()) {
+            if keys.contains_key(&address) {
+                continue;
+            }
+            let session_keys = self.sender.establish_session(SESSION_ID, address, &self.sender_socket).await.unwrap();
+            keys.insert(address, session_keys);
+        }
+        keys
+    }
+
+    // 主経路と代替経路のパスリストを最初の中継ノードに渡してから送信する。ノードごとの鍵を返す
+    async fn send(&self,
+                  primary: &SRv6Policy,
+                  alternatives: &[SRv6Policy],
+                  with_alternative_onions: bool,
+                  message: &[u8]) -> HashMap<SocketAddr, SessionKeys> {
+        let policies: Vec<&SRv6Policy> = std::iter::once(primary).chain(alternatives).collect();
+        let keys_by_address = self.establish(&policies).await;
+        let keys: Vec<SessionKeys> = primary.node_addresses().iter().map(|address| keys_by_address[address].clone()).collect();
+
+        let path_list = PathList::from_policies(SESSION_ID, policies, DEFAULT_PATH_LIST_LIFETIME);
+        self.sender.send_path_list(&path_list, &keys[0], primary.node_addresses()[0], &self.sender_socket).await.unwrap();
+
+        let routes = match with_alternative_onions {
+            true => alternative_routes(primary, alternatives, &keys_by_address),
+            false => Vec::new(),
+        };
+        self.sender.send_message(SESSION_ID, primary, &keys, &routes, message, &self.sender_socket).await.unwrap();
+        keys_by_address
+    }
+
+    async fn delivered(&mut self) -> Option<DeliveredMessage> {
+        timeout(DELIVERY_TIMEOUT, self.messages.recv()).await.ok().flatten()
+    }
+
+    async fn path_changes(&self) -> Vec<PathChange> {
+        self.sender.collect_path_changes(&self.sender_socket, PATH_CHANGE_WAIT).await
+    }
+}
+
+#[tokio::test]
+async fn delivers_message_through_relays() {
+    let mut network = network(2).await;
+    let policy = network.policy(&[1, 2]);
+
+    network.send(&policy, &[], true, b"hello").await;
+
+    let delivered = network.delivered().await.expect("受信者に届く");
+    assert_eq!(delivered.session_id, SESSION_ID);
+    assert_eq!(delivered.message, b"hello");
+}
+
+#[tokio::test]
+async fn delivers_rerouted_message_around_failed_relay() {
+    let mut network = network(3).await;
+    let primary = network.policy(&[1, 2]);
+    let alternative = network.policy(&[1, 3]);
+    network.relay(1).mark_neighbor_failed(primary.segment_list[1].address);
+
+    network.send(&primary, std::slice::from_ref(&alternative), true, b"rerouted").await;
+
+    let delivered = network.delivered().await.expect("代替経路で受信者に届く");
+    assert_eq!(delivered.message, b"rerouted");
+    assert_eq!(network.relay(1).rerouted_path(SESSION_ID), Some(alternative.policy_id.clone()));
+    assert_eq!(network.relay(3).stats().mac_failures(), 0);
+
+    let changes = network.path_changes().await;
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].old_path_id, primary.policy_id);
+    assert_eq!(changes[0].new_path_id, alternative.policy_id);
+    assert_eq!(changes[0].reason, RerouteReason::NodeFailure);
+    // 新しい経路の中継ノードも署名付き通知を検証し、セッションの経路として記録する
+    assert_eq!(network.relay(3).rerouted_path(SESSION_ID), Some(alternative.policy_id.clone()));
+}
+
+#[tokio::test]
+async fn forwards_path_change_along_reverse_path() {
+    let mut network = network(3).await;
+    let policy = network.policy(&[1, 2, 3]);
+    let keys = network.send(&policy, &[], true, b"hello").await;
+    network.delivered().await.expect("受信者に届く");
+
+    // 3番目の中継ノードの通知として、その鍵で暗号化した層を2番目の中継ノードに渡す
+    let change = PathChange::new(SESSION_ID, "old".into(), "new".into(), RerouteReason::Unreachable);
+    let notification = SignedPathChange::sign(change.clone(), sid(3), &network.identity_keys[2]);
+    let origin = &keys[&policy.segment_list[2].address];
+    let sealed = SealedMessage::seal(SESSION_ID, &origin.encryption_key, &ReverseLayer::Origin(notification).to_bytes()).unwrap();
+    let socket = bind().await;
+    // 同じ通知の再送は中継ノードで包み直されても送信者が破棄する
+    for _ in 0..2 {
+        socket.send_to(&MessageType::ReversePathChange.frame(&sealed.to_bytes()), policy.segment_list[1].address).await.unwrap();
+    }
+
+    assert_eq!(network.path_changes().await, vec![change]);
+}
+
+#[tokio::test]
+async fn ignores_path_change_sent_directly_to_sender() {
+    let network = network(2).await;
+    let policy = network.policy(&[1, 2]);
+    network.send(&policy, &[], true, b"hello").await;
+
+    // 逆方向経路の暗号化がない署名付き通知は経路上のノードから届いたものとみなさない
+    let change = PathChange::new(SESSION_ID, "old".into(), "new".into(), RerouteReason::NoRoute);
+    let notification = SignedPathChange::sign(change, sid(1), &network.identity_keys[0]);
+    let socket = bind().await;
+    socket.send_to(&MessageType::PathChange.frame(&notification.to_bytes()), network.sender_socket.local_addr().unwrap()).await.unwrap();
+
+    assert!(network.path_changes().await.is_empty());
+}
+
+#[tokio::test]
+async fn does_not_reroute_without_alternative_onion() {
+    let mut network = network(3).await;
+    let primary = network.policy(&[1, 2]);
+    let alternative = network.policy(&[1, 3]);
+    network.relay(1).mark_neighbor_failed(primary.segment_list[1].address);
+
+    network.send(&primary, std::slice::from_ref(&alternative), false, b"dropped").await;
+
+    assert!(network.delivered().await.is_none());
+    assert_eq!(network.relay(1).rerouted_path(SESSION_ID), None);
+}
+
+#[tokio::test]
+async fn sender_verifies_relays_with_keys_trusted_by_sid() {
+    let mut network = network(3).await;
+    let primary = network.policy(&[1, 2]);
+    let alternative = network.policy(&[1, 3]);
+    network.relay(1).mark_neighbor_failed(primary.segment_list[1].address);
+
+    // 中継ノードはSIDの識別鍵だけを登録し、ハンドシェイク応答もその鍵で検証させる
+    let socket = bind().await;
+    let sender = Sender::new(Node::new(NodeType::Sender, socket.local_addr().unwrap()), socket);
+    for (n, identity_key) in network.identity_keys.iter().enumerate() {
+        sender.node().add_trusted_key(sid(n as u16 + 1), identity_key.public_key());
+    }
+    sender.node().add_peer_key(network.receiver, network.receiver_key);
+
+    let session_id = sender.send(&primary, std::slice::from_ref(&alternative), b"via sender").await.unwrap();
+
+    let delivered = network.delivered().await.expect("代替経路で受信者に届く");
+    assert_eq!(delivered.session_id, session_id);
+    assert_eq!(delivered.message, b"via sender");
+    let changes = sender.collect_path_changes(PATH_CHANGE_WAIT).await;
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].new_path_id, alternative.policy_id);
+}